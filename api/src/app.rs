@@ -20,6 +20,8 @@ pub async fn create_app(app_state: AppState) -> Router {
         .merge(routes::auth::create_route())
         .merge(routes::api::create_route())
         .merge(routes::html::create_route())
+        .merge(routes::media::create_route())
+        .merge(routes::storage::create_route())
         .merge(Router::new().nest_service("/assets", ServeDir::new("./dist/assets")))
         .fallback_service(serve_dir)
         .layer(
@@ -0,0 +1,58 @@
+// 既存の行を新しい形式に作り直す、一度だけ実行するコマンドに共通する処理
+// 処理できた行は取得の条件から外れるので、条件に合う行がなくなるまでページごとに取得する
+use crate::gq::error::HasuraError;
+use std::collections::HashSet;
+use std::future::Future;
+
+// 1回に取得する行の数
+const PAGE_SIZE: i32 = 100;
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub done: usize,
+    pub failed: usize,
+}
+
+// listには、取得する件数と、処理できなかったために除く行のIDを渡す
+// 処理できなかった行は記録だけして、同じ実行の中では取得し直さない
+pub async fn run<T, List, ListFuture, Process, ProcessFuture, E>(
+    list: List,
+    process: Process,
+) -> Result<Report, HasuraError>
+where
+    List: Fn(i32, Vec<uuid::Uuid>) -> ListFuture,
+    ListFuture: Future<Output = Result<Vec<(uuid::Uuid, T)>, HasuraError>>,
+    Process: Fn(uuid::Uuid, T) -> ProcessFuture,
+    ProcessFuture: Future<Output = Result<(), E>>,
+    E: std::fmt::Debug,
+{
+    let mut report = Report::default();
+    let mut seen = HashSet::new();
+    let mut failed = Vec::new();
+
+    loop {
+        let rows = list(PAGE_SIZE, failed.clone()).await?;
+        if rows.is_empty() {
+            break;
+        }
+
+        for (id, row) in rows {
+            // 処理したのに条件から外れない行があれば、いつまでも終わらないのでやめる
+            if !seen.insert(id) {
+                tracing::error!("{} was listed again after being processed", id);
+                return Ok(report);
+            }
+
+            match process(id, row).await {
+                Ok(()) => report.done += 1,
+                Err(err) => {
+                    tracing::warn!("Failed to backfill {}: {:?}", id, err);
+                    failed.push(id);
+                    report.failed += 1;
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
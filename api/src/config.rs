@@ -1,6 +1,8 @@
 use once_cell::sync::Lazy;
 use serde::Deserialize;
 
+use crate::model::Role;
+
 pub static CONFIG: Lazy<Config> = Lazy::new(|| Config::new().expect("Failed to setup config"));
 
 #[derive(Debug, Clone, Deserialize)]
@@ -21,16 +23,51 @@ pub struct Config {
 
     pub minio_endpoint: String,
 
+    // メディアの保存先（s3: S3やMinIO、local: ローカルのファイルシステム）
+    #[serde(default)]
+    pub media_store: MediaStoreKind,
+    // S3でのバケットの分け方（bucket_per_user: ユーザーごと、single_bucket: 1つのバケットにユーザーごとのプレフィックス）
+    #[serde(default)]
+    pub media_store_layout: MediaStoreLayout,
+    #[serde(default = "default_media_bucket")]
+    pub media_bucket: String,
+    #[serde(default = "default_s3_region")]
+    pub s3_region: String,
+    // localのときに保存するディレクトリ
+    #[serde(default = "default_media_store_path")]
+    pub media_store_path: String,
+
     pub smtp_username: String,
     pub smtp_password: String,
 
     hasura_graphql_jwt_secret: String,
 
-    // for oauth_1
-    api_key: String,
-    api_secret_key: String,
-    access_token: String,
-    access_token_secret: String,
+    // プランごとのRSSフィードの確認間隔（分）
+    #[serde(default = "default_basic_rss_interval_minutes")]
+    basic_rss_interval_minutes: u32,
+    #[serde(default = "default_basic_rss_interval_minutes")]
+    basic_min_rss_interval_minutes: u32,
+    #[serde(default = "default_premium_rss_interval_minutes")]
+    premium_rss_interval_minutes: u32,
+    #[serde(default = "default_premium_min_rss_interval_minutes")]
+    premium_min_rss_interval_minutes: u32,
+    #[serde(default = "default_max_rss_interval_minutes")]
+    max_rss_interval_minutes: u32,
+
+    // プランごとのメディアの保存容量（MB）
+    #[serde(default = "default_basic_storage_quota_mb")]
+    basic_storage_quota_mb: u64,
+    #[serde(default = "default_premium_storage_quota_mb")]
+    premium_storage_quota_mb: u64,
+
+    // 参照されないメディアのオブジェクトを削除する間隔と、削除しない猶予期間（時間）
+    // dry runのときは削除せずにログに出すだけにする
+    #[serde(default = "default_media_gc_interval_hours")]
+    pub media_gc_interval_hours: u32,
+    #[serde(default = "default_media_gc_grace_hours")]
+    pub media_gc_grace_hours: i64,
+    #[serde(default)]
+    pub media_gc_dry_run: bool,
 }
 
 impl Config {
@@ -44,19 +81,150 @@ impl Config {
         jwt_secret.key
     }
 
-    pub fn oauth_1_token(&self) -> oauth::Token {
-        oauth::Token::from_parts(
-            self.api_key.clone(),
-            self.api_secret_key.clone(),
-            self.access_token.clone(),
-            self.access_token_secret.clone(),
-        )
+    pub fn plan_limits(&self, role: &Role) -> PlanLimits {
+        match role {
+            Role::Premium => PlanLimits {
+                rss_interval_minutes: self.premium_rss_interval_minutes,
+                min_rss_interval_minutes: self.premium_min_rss_interval_minutes,
+                max_rss_interval_minutes: self.max_rss_interval_minutes,
+                storage_quota_bytes: self.premium_storage_quota_mb * 1024 * 1024,
+            },
+            Role::Basic | Role::Anonymous => PlanLimits {
+                rss_interval_minutes: self.basic_rss_interval_minutes,
+                min_rss_interval_minutes: self.basic_min_rss_interval_minutes,
+                max_rss_interval_minutes: self.max_rss_interval_minutes,
+                storage_quota_bytes: self.basic_storage_quota_mb * 1024 * 1024,
+            },
+        }
     }
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaStoreKind {
+    #[default]
+    S3,
+    Local,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaStoreLayout {
+    #[default]
+    BucketPerUser,
+    SingleBucket,
+}
+
 #[derive(Deserialize)]
 struct JwtSecret {
     #[serde(rename = "type")]
     type_: String,
     key: String,
 }
+
+fn default_media_bucket() -> String {
+    String::from("still-bott-media")
+}
+
+fn default_s3_region() -> String {
+    String::from("ap-northeast-1")
+}
+
+fn default_media_store_path() -> String {
+    String::from("./media")
+}
+
+fn default_basic_rss_interval_minutes() -> u32 {
+    4 * 60
+}
+
+fn default_premium_rss_interval_minutes() -> u32 {
+    60
+}
+
+fn default_premium_min_rss_interval_minutes() -> u32 {
+    15
+}
+
+fn default_max_rss_interval_minutes() -> u32 {
+    24 * 60
+}
+
+fn default_basic_storage_quota_mb() -> u64 {
+    100
+}
+
+fn default_premium_storage_quota_mb() -> u64 {
+    1024
+}
+
+fn default_media_gc_interval_hours() -> u32 {
+    24
+}
+
+fn default_media_gc_grace_hours() -> i64 {
+    24
+}
+
+// プランごとの制限
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlanLimits {
+    pub rss_interval_minutes: u32,
+    pub min_rss_interval_minutes: u32,
+    pub max_rss_interval_minutes: u32,
+    pub storage_quota_bytes: u64,
+}
+
+impl PlanLimits {
+    // タスクごとの指定はプランで許可された範囲に収める
+    // プランが変更された場合もここで丸められるため、再スケジュールするだけで制限が反映される
+    pub fn rss_interval_minutes(&self, requested: Option<i32>) -> u32 {
+        let max = self
+            .max_rss_interval_minutes
+            .max(self.min_rss_interval_minutes);
+
+        match requested {
+            Some(minutes) => (minutes.max(0) as u32).clamp(self.min_rss_interval_minutes, max),
+            None => self
+                .rss_interval_minutes
+                .clamp(self.min_rss_interval_minutes, max),
+        }
+    }
+
+    // 保存済みのメディアに追加しても、プランの保存容量に収まるか
+    pub fn fits_storage_quota(&self, used_bytes: u64, adding_bytes: u64) -> bool {
+        used_bytes.saturating_add(adding_bytes) <= self.storage_quota_bytes
+    }
+}
+
+#[test]
+fn test_rss_interval_minutes() {
+    let limits = PlanLimits {
+        rss_interval_minutes: 60,
+        min_rss_interval_minutes: 15,
+        max_rss_interval_minutes: 1440,
+        storage_quota_bytes: 0,
+    };
+
+    assert_eq!(limits.rss_interval_minutes(None), 60);
+    assert_eq!(limits.rss_interval_minutes(Some(30)), 30);
+    assert_eq!(limits.rss_interval_minutes(Some(5)), 15);
+    assert_eq!(limits.rss_interval_minutes(Some(-1)), 15);
+    assert_eq!(limits.rss_interval_minutes(Some(10000)), 1440);
+}
+
+#[test]
+fn test_fits_storage_quota() {
+    let limits = PlanLimits {
+        rss_interval_minutes: 60,
+        min_rss_interval_minutes: 15,
+        max_rss_interval_minutes: 1440,
+        storage_quota_bytes: 100,
+    };
+
+    assert!(limits.fits_storage_quota(0, 100));
+    assert!(limits.fits_storage_quota(60, 40));
+    assert!(!limits.fits_storage_quota(60, 41));
+    assert!(!limits.fits_storage_quota(101, 0));
+    assert!(!limits.fits_storage_quota(u64::MAX, 1));
+}
@@ -8,12 +8,60 @@ pub enum Error {
         source: HasuraError,
     },
 
-    MinioError {
-        source: crate::minio::Error,
+    TemplateError {
+        source: crate::template::Error,
     },
 
-    TwitterError {
-        source: crate::twitter::Error,
+    MediaError {
+        source: crate::media::Error,
+    },
+
+    StorageError {
+        source: crate::storage::Error,
+    },
+
+    WatermarkError {
+        source: crate::watermark::Error,
+    },
+
+    RssFilterError {
+        source: crate::rss_filter::Error,
+    },
+
+    FeedError {
+        source: crate::feed::Error,
+    },
+
+    #[snafu(display("{source}"))]
+    PublisherError {
+        source: crate::publisher::Error,
+    },
+
+    #[snafu(display("ツイートの文字数が上限を超えています（{weighted_length}/280）"))]
+    TweetTooLong {
+        weighted_length: usize,
+    },
+
+    #[snafu(display("投稿の文字数が上限を超えています（{length}/{max}）"))]
+    PostTooLong {
+        length: usize,
+        max: usize,
+    },
+
+    #[snafu(display("ツイートの本文が空か、使用できない文字が含まれています"))]
+    InvalidTweet,
+
+    #[snafu(display("この投稿先にはGIFアニメや動画を添付できません。静止画にしてください"))]
+    UnsupportedMedia,
+
+    #[snafu(display(
+        "スレッドの投稿が途中で失敗しました（{posted}/{total}件投稿済み）: {source}"
+    ))]
+    ThreadInterrupted {
+        posted: usize,
+        total: usize,
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<Error>,
     },
 
     #[snafu(whatever, display("{message}"))]
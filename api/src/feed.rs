@@ -0,0 +1,484 @@
+// RSS・Atomフィードの取得と解析
+//
+// 形式はルート要素で判定する（<rss>・<rdf:RDF>ならRSS、<feed>ならAtom）。
+// HTMLページのURLが指定された場合は<link rel="alternate">からフィードを探す。
+// 必須の項目が欠けている記事は読み飛ばし、その内容をproblemsに記録する。
+use crate::model::RssItem;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use snafu::prelude::*;
+use strum_macros::Display;
+use time::OffsetDateTime;
+
+pub const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 6.3; Trident/7.0; rv 11.0) like Gecko";
+
+// 読み込むフィードとHTMLページの最大サイズ
+const MAX_FEED_BYTES: usize = 5 * 1024 * 1024;
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum Error {
+    #[snafu(display("URLにアクセスできませんでした: {source}"))]
+    Request { source: reqwest::Error },
+
+    #[snafu(display("URLにアクセスできませんでした (HTTP {status})"))]
+    Status { status: u16 },
+
+    #[snafu(display("{source}"))]
+    Fetch { source: crate::net::Error },
+
+    #[snafu(display("RSSとして読み込めませんでした: {source}"))]
+    Rss { source: rss::Error },
+
+    #[snafu(display("Atomとして読み込めませんでした: {source}"))]
+    Atom { source: atom_syndication::Error },
+
+    #[snafu(display("RSS・Atomフィードではありません"))]
+    UnknownFormat,
+
+    #[snafu(display("ページ内にRSS・Atomフィードが見つかりませんでした"))]
+    FeedNotFound,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum Format {
+    Rss,
+    Atom,
+}
+
+#[derive(Debug, Clone)]
+pub struct Feed {
+    // フィードのURL。HTMLページから探した場合は見つかったフィードのURL
+    pub url: String,
+    pub format: Format,
+    pub title: String,
+    pub link: String,
+    pub pub_date: Option<OffsetDateTime>,
+    pub items: Vec<RssItem>,
+    pub problems: Vec<String>,
+}
+
+impl Feed {
+    // 新しい順に並べた記事
+    pub fn latest_items(&self, count: usize) -> Vec<&RssItem> {
+        let mut items: Vec<&RssItem> = self.items.iter().collect();
+        items.sort_by_key(|item| std::cmp::Reverse(item.pub_date));
+        items.truncate(count);
+        items
+    }
+}
+
+// フィードのURLを取得して解析する
+pub async fn fetch(url: &str) -> Result<Feed, Error> {
+    let bytes = get(url).await?;
+
+    parse(url, &bytes)
+}
+
+// HTMLページのURLであれば<link rel="alternate">で示されたフィードを順に試す
+pub async fn discover(url: &str) -> Result<Feed, Error> {
+    let bytes = get(url).await?;
+
+    if root_element(&bytes).is_some() {
+        return parse(url, &bytes);
+    }
+
+    let html = String::from_utf8_lossy(&bytes);
+    let candidates = alternate_links(&html, url);
+    ensure!(!candidates.is_empty(), FeedNotFoundSnafu);
+
+    let mut last_error = Error::FeedNotFound;
+    for candidate in candidates {
+        match fetch(&candidate).await {
+            Ok(feed) => return Ok(feed),
+            Err(err) => last_error = err,
+        }
+    }
+
+    Err(last_error)
+}
+
+async fn get(url: &str) -> Result<Vec<u8>, Error> {
+    let url = crate::net::ensure_public_url(url).context(FetchSnafu)?;
+    let resp = crate::net::client()
+        .get(url)
+        .header("User-Agent", USER_AGENT)
+        .send()
+        .await
+        .context(RequestSnafu)?;
+
+    let status = resp.status();
+    ensure!(
+        status.is_success(),
+        StatusSnafu {
+            status: status.as_u16()
+        }
+    );
+
+    crate::net::read_limited(resp, MAX_FEED_BYTES)
+        .await
+        .context(FetchSnafu)
+}
+
+pub fn parse(url: &str, bytes: &[u8]) -> Result<Feed, Error> {
+    match root_element(bytes) {
+        Some(Format::Rss) => parse_rss(url, bytes),
+        Some(Format::Atom) => parse_atom(url, bytes),
+        None => Err(Error::UnknownFormat),
+    }
+}
+
+// XML宣言・コメント・DOCTYPEを読み飛ばし、最初の要素名で形式を判定する
+fn root_element(bytes: &[u8]) -> Option<Format> {
+    static ROOT: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"(?s)^(?:\s|<\?[^>]*\?>|<!--.*?-->|<!DOCTYPE[^>]*>)*<([A-Za-z_][\w:.-]*)")
+            .unwrap()
+    });
+
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(4096)]);
+    let head = head.trim_start_matches('\u{feff}');
+    let name = ROOT.captures(head)?.get(1)?.as_str();
+
+    match name {
+        "rss" | "rdf:RDF" => Some(Format::Rss),
+        "feed" => Some(Format::Atom),
+        _ => None,
+    }
+}
+
+fn parse_rss(url: &str, bytes: &[u8]) -> Result<Feed, Error> {
+    let channel = rss::Channel::read_from(bytes).context(RssSnafu)?;
+    let mut problems = Vec::new();
+
+    let pub_date = match channel
+        .pub_date
+        .as_deref()
+        .or(channel.last_build_date.as_deref())
+    {
+        Some(date) => {
+            let parsed = parse_date(date);
+            if parsed.is_none() {
+                problems.push(format!("フィードの日付「{}」を読み込めません", date));
+            }
+            parsed
+        }
+        None => None,
+    };
+
+    let items = channel
+        .items
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, item)| {
+            let position = index + 1;
+
+            let link = match item.link.clone().or_else(|| {
+                item.guid
+                    .as_ref()
+                    .filter(|guid| guid.permalink)
+                    .map(|guid| guid.value.clone())
+            }) {
+                Some(link) => link,
+                None => {
+                    problems.push(format!("{}件目の記事にリンクがありません", position));
+                    return None;
+                }
+            };
+
+            let pub_date = match item.pub_date.as_deref() {
+                Some(date) => match parse_date(date) {
+                    Some(pub_date) => pub_date,
+                    None => {
+                        problems.push(format!(
+                            "{}件目の記事の日付「{}」を読み込めません",
+                            position, date
+                        ));
+                        return None;
+                    }
+                },
+                None => {
+                    problems.push(format!("{}件目の記事に日付がありません", position));
+                    return None;
+                }
+            };
+
+            let title = item.title.clone().unwrap_or_else(|| {
+                problems.push(format!("{}件目の記事にタイトルがありません", position));
+                String::new()
+            });
+
+            let author = item.author.clone().or_else(|| {
+                item.dublin_core_ext
+                    .as_ref()
+                    .and_then(|dc| dc.creators.first().cloned())
+            });
+            let image_url = rss_item_image_url(&item);
+            let categories = item
+                .categories
+                .into_iter()
+                .map(|category| category.name)
+                .collect();
+
+            Some(RssItem::new(
+                title,
+                link,
+                pub_date,
+                item.description,
+                author,
+                categories,
+                image_url,
+            ))
+        })
+        .collect();
+
+    Ok(Feed {
+        url: url.to_string(),
+        format: Format::Rss,
+        title: channel.title,
+        link: channel.link,
+        pub_date,
+        items,
+        problems,
+    })
+}
+
+fn parse_atom(url: &str, bytes: &[u8]) -> Result<Feed, Error> {
+    let feed = atom_syndication::Feed::read_from(bytes).context(AtomSnafu)?;
+    let mut problems = Vec::new();
+
+    let items = feed
+        .entries()
+        .iter()
+        .enumerate()
+        .filter_map(|(index, entry)| {
+            let link = match alternate_link(entry.links()) {
+                Some(link) => link,
+                None => {
+                    problems.push(format!("{}件目の記事にリンクがありません", index + 1));
+                    return None;
+                }
+            };
+
+            let pub_date = from_chrono(entry.published().unwrap_or(entry.updated()));
+
+            let description = entry
+                .summary()
+                .map(|summary| summary.as_str().to_string())
+                .or_else(|| entry.content().and_then(|c| c.value()).map(String::from));
+            let author = entry
+                .authors()
+                .first()
+                .or_else(|| feed.authors().first())
+                .map(|person| person.name().to_string());
+            let categories = entry
+                .categories()
+                .iter()
+                .map(|category| category.label().unwrap_or(category.term()).to_string())
+                .collect();
+            let image_url = entry
+                .links()
+                .iter()
+                .find(|link| {
+                    link.rel() == "enclosure"
+                        && link.mime_type().is_some_and(|t| t.starts_with("image/"))
+                })
+                .map(|link| link.href().to_string());
+
+            Some(RssItem::new(
+                entry.title().as_str().to_string(),
+                link,
+                pub_date,
+                description,
+                author,
+                categories,
+                image_url,
+            ))
+        })
+        .collect();
+
+    Ok(Feed {
+        url: url.to_string(),
+        format: Format::Atom,
+        title: feed.title().as_str().to_string(),
+        link: alternate_link(feed.links()).unwrap_or_default(),
+        pub_date: Some(from_chrono(feed.updated())),
+        items,
+        problems,
+    })
+}
+
+fn alternate_link(links: &[atom_syndication::Link]) -> Option<String> {
+    links
+        .iter()
+        .find(|link| link.rel() == "alternate")
+        .or_else(|| links.first())
+        .map(|link| link.href().to_string())
+}
+
+fn from_chrono(date: &atom_syndication::FixedDateTime) -> OffsetDateTime {
+    OffsetDateTime::from_unix_timestamp(date.timestamp()).unwrap_or(OffsetDateTime::UNIX_EPOCH)
+}
+
+// RSSの日付はRFC 2822が正しいが、RFC 3339で書かれたフィードも多い
+fn parse_date(date: &str) -> Option<OffsetDateTime> {
+    use time::format_description::well_known::{Rfc2822, Rfc3339};
+
+    let date = date.trim();
+    OffsetDateTime::parse(date, &Rfc2822)
+        .or_else(|_| OffsetDateTime::parse(date, &Rfc3339))
+        .ok()
+}
+
+// <enclosure>、<media:content>、<media:thumbnail>の順に画像を探す
+fn rss_item_image_url(item: &rss::Item) -> Option<String> {
+    if let Some(enclosure) = &item.enclosure {
+        if enclosure.mime_type.starts_with("image/") {
+            return Some(enclosure.url.clone());
+        }
+    }
+
+    let media = item.extensions.get("media")?;
+
+    let content = media.get("content").and_then(|contents| {
+        contents.iter().find_map(|content| {
+            let is_image = match (content.attrs.get("medium"), content.attrs.get("type")) {
+                (Some(medium), _) => medium == "image",
+                (None, Some(mime_type)) => mime_type.starts_with("image/"),
+                (None, None) => true,
+            };
+
+            if is_image {
+                content.attrs.get("url").cloned()
+            } else {
+                None
+            }
+        })
+    });
+
+    content.or_else(|| {
+        media
+            .get("thumbnail")
+            .and_then(|thumbnails| thumbnails.first())
+            .and_then(|thumbnail| thumbnail.attrs.get("url").cloned())
+    })
+}
+
+// HTMLの<link rel="alternate" type="application/rss+xml" href="...">を探し、絶対URLにして返す
+pub fn alternate_links(html: &str, base: &str) -> Vec<String> {
+    static LINK: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?is)<link\b[^>]*>").unwrap());
+    static ATTR: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r#"(?is)([a-z:_-]+)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap());
+
+    let base = reqwest::Url::parse(base).ok();
+
+    LINK.find_iter(html)
+        .filter_map(|link| {
+            let mut rel = None;
+            let mut mime_type = None;
+            let mut href = None;
+
+            for attr in ATTR.captures_iter(link.as_str()) {
+                let value = attr
+                    .get(2)
+                    .or_else(|| attr.get(3))
+                    .map(|v| v.as_str().trim());
+                match attr[1].to_ascii_lowercase().as_str() {
+                    "rel" => rel = value,
+                    "type" => mime_type = value,
+                    "href" => href = value,
+                    _ => {}
+                }
+            }
+
+            let is_alternate = rel.is_some_and(|rel| {
+                rel.split_whitespace()
+                    .any(|rel| rel.eq_ignore_ascii_case("alternate"))
+            });
+            let is_feed = matches!(
+                mime_type.map(|t| t.to_ascii_lowercase()).as_deref(),
+                Some("application/rss+xml") | Some("application/atom+xml")
+            );
+
+            if !is_alternate || !is_feed {
+                return None;
+            }
+
+            let href = href?;
+            match &base {
+                Some(base) => base.join(href).ok().map(|url| url.to_string()),
+                None => Some(href.to_string()),
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_feed() {
+    let rss = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0"><channel>
+  <title>質屋ブログ</title>
+  <link>https://example.com/</link>
+  <description>d</description>
+  <item>
+    <title>ROLEXの買取相場</title>
+    <link>https://example.com/rolex</link>
+    <pubDate>Tue, 22 Aug 2023 10:00:00 +0900</pubDate>
+  </item>
+  <item>
+    <title>日付のない記事</title>
+    <link>https://example.com/no-date</link>
+  </item>
+</channel></rss>"#;
+
+    let feed = parse("https://example.com/rss", rss.as_bytes()).unwrap();
+    assert_eq!(feed.format, Format::Rss);
+    assert_eq!(feed.items.len(), 1);
+    assert_eq!(
+        feed.problems,
+        vec!["2件目の記事に日付がありません".to_string()]
+    );
+
+    let atom = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>質屋ブログ</title>
+  <id>urn:uuid:1</id>
+  <updated>2023-08-22T10:00:00+09:00</updated>
+  <link rel="alternate" href="https://example.com/"/>
+  <entry>
+    <title>ROLEXの買取相場</title>
+    <id>urn:uuid:2</id>
+    <link rel="alternate" href="https://example.com/rolex"/>
+    <updated>2023-08-22T10:00:00+09:00</updated>
+    <category term="watch" label="腕時計"/>
+  </entry>
+</feed>"#;
+
+    let feed = parse("https://example.com/atom", atom.as_bytes()).unwrap();
+    assert_eq!(feed.format, Format::Atom);
+    assert_eq!(feed.link, "https://example.com/");
+    assert_eq!(feed.items[0].link, "https://example.com/rolex");
+    assert_eq!(feed.items[0].categories, vec!["腕時計".to_string()]);
+
+    assert!(matches!(
+        parse("https://example.com/", b"<!DOCTYPE html><html></html>"),
+        Err(Error::UnknownFormat)
+    ));
+}
+
+#[test]
+fn test_alternate_links() {
+    let html = r#"<html><head>
+        <link rel="stylesheet" href="/style.css">
+        <link rel="alternate" type="application/rss+xml" title="RSS" href="/rss/blog">
+        <link href='https://example.com/atom.xml' type='application/atom+xml' rel='alternate'>
+    </head></html>"#;
+
+    assert_eq!(
+        alternate_links(html, "https://example.com/blog/"),
+        vec![
+            "https://example.com/rss/blog".to_string(),
+            "https://example.com/atom.xml".to_string(),
+        ]
+    );
+}
@@ -59,6 +59,19 @@ impl From<crate::model::Role> for enums::RoleEnum {
     }
 }
 
+impl From<enums::RoleEnum> for crate::model::Role {
+    fn from(value: enums::RoleEnum) -> Self {
+        use self::enums::RoleEnum;
+        use crate::model::Role;
+
+        match value {
+            RoleEnum::Anonymous => Role::Anonymous,
+            RoleEnum::Basic => Role::Basic,
+            RoleEnum::Premium => Role::Premium,
+        }
+    }
+}
+
 pub async fn run_graphql<T, V>(
     operation: cynic::Operation<T, V>,
 ) -> Result<cynic::GraphQlResponse<T>, cynic::http::CynicReqwestError>
@@ -14,6 +14,9 @@ pub enum HasuraError {
 
     SessionNotFound,
 
+    #[snafu(display("account is linked to another user"))]
+    AccountLinkedToAnotherUser,
+
     #[snafu(whatever, display("{message}"))]
     Whatever {
         message: String,
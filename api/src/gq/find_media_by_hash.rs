@@ -0,0 +1,74 @@
+/*
+query FindMediaByHash($user_id: String!, $content_hash: String!) {
+  media(where: {user_id: {_eq: $user_id}, content_hash: {_eq: $content_hash}}, limit: 1) {
+    id
+    thumbnail
+    uploaded_at
+    user_id
+  }
+}
+*/
+#[cynic::schema_for_derives(file = r#"schema.graphql"#, module = "schema")]
+mod queries {
+    use crate::gq::common::scalars::*;
+    use crate::gq::common::schema;
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct FindMediaByHashVariables {
+        pub user_id: String,
+        pub content_hash: String,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "query_root", variables = "FindMediaByHashVariables")]
+    pub struct FindMediaByHash {
+        #[arguments(where: { user_id: { _eq: $user_id }, content_hash: { _eq: $content_hash } }, limit: 1)]
+        pub media: Vec<Media>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "media")]
+    pub struct Media {
+        pub id: Uuid,
+        pub thumbnail: String,
+        #[cynic(rename = "uploaded_at")]
+        pub uploaded_at: Timestamptz,
+        #[cynic(rename = "user_id")]
+        pub user_id: String,
+    }
+}
+
+use super::error::{build_errors, HasuraError, NetworkSnafu};
+use snafu::prelude::*;
+
+// ユーザーのライブラリから、内容が同じメディアを探す
+pub async fn exec(
+    user_id: String,
+    content_hash: String,
+) -> Result<Option<crate::model::Media>, HasuraError> {
+    use cynic::QueryBuilder;
+
+    let vars = queries::FindMediaByHashVariables {
+        user_id,
+        content_hash,
+    };
+
+    let operation = queries::FindMediaByHash::build(vars);
+
+    let resp = super::common::run_graphql(operation)
+        .await
+        .context(NetworkSnafu)?;
+
+    resp.data
+        .ok_or_else(|| build_errors(resp.errors))
+        .map(|data| {
+            data.media.into_iter().next().map(|media| {
+                let format: time::format_description::well_known::iso8601::Iso8601 =
+                    time::format_description::well_known::Iso8601;
+                let uploaded_at: time::OffsetDateTime =
+                    time::OffsetDateTime::parse(&media.uploaded_at.0, &format).unwrap();
+
+                crate::model::Media::new(media.id.0, media.user_id, media.thumbnail, uploaded_at)
+            })
+        })
+}
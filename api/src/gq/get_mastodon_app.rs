@@ -0,0 +1,60 @@
+/*
+query GetMastodonApp($instance: String!) {
+  mastodon_app_by_pk(instance: $instance) {
+    client_id
+    client_secret
+  }
+}
+*/
+#[cynic::schema_for_derives(file = r#"schema.graphql"#, module = "schema")]
+mod queries {
+    use crate::gq::common::schema;
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct GetMastodonAppVariables {
+        pub instance: String,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "query_root", variables = "GetMastodonAppVariables")]
+    pub struct GetMastodonApp {
+        #[arguments(instance: $instance)]
+        #[cynic(rename = "mastodon_app_by_pk")]
+        pub mastodon_app_by_pk: Option<MastodonApp>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "mastodon_app")]
+    pub struct MastodonApp {
+        #[cynic(rename = "client_id")]
+        pub client_id: String,
+        #[cynic(rename = "client_secret")]
+        pub client_secret: String,
+    }
+}
+
+use super::error::{build_errors, HasuraError, NetworkSnafu};
+use crate::publisher::mastodon::App;
+use snafu::prelude::*;
+
+// まだアプリを登録していないインスタンスの場合はNoneを返す
+pub async fn exec(instance: String) -> Result<Option<App>, HasuraError> {
+    use cynic::QueryBuilder;
+
+    let vars = queries::GetMastodonAppVariables { instance };
+
+    let operation = queries::GetMastodonApp::build(vars);
+
+    let resp = super::common::run_graphql(operation)
+        .await
+        .context(NetworkSnafu)?;
+
+    resp.data
+        .ok_or_else(|| build_errors(resp.errors))
+        .map(|data| {
+            data.mastodon_app_by_pk.map(|app| App {
+                client_id: app.client_id,
+                client_secret: app.client_secret,
+            })
+        })
+}
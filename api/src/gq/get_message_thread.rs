@@ -0,0 +1,107 @@
+/*
+query GetMessageThread($id: uuid!) {
+  message_by_pk(id: $id) {
+    id
+    thread_tweet_id
+    parts {
+      id
+      position
+      text
+      media_id
+      media {
+        alt_text
+      }
+      tweet_id
+    }
+  }
+}
+*/
+#[cynic::schema_for_derives(file = r#"schema.graphql"#, module = "schema")]
+mod queries {
+    use crate::gq::common::scalars::*;
+    use crate::gq::common::schema;
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct GetMessageThreadVariables {
+        pub id: Uuid,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "query_root", variables = "GetMessageThreadVariables")]
+    pub struct GetMessageThread {
+        #[arguments(id: $id)]
+        #[cynic(rename = "message_by_pk")]
+        pub message_by_pk: Option<Message>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "message")]
+    pub struct Message {
+        #[allow(dead_code)]
+        pub id: Uuid,
+        #[cynic(rename = "thread_tweet_id")]
+        pub thread_tweet_id: Option<String>,
+        pub parts: Vec<MessagePart>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "message_part")]
+    pub struct MessagePart {
+        pub id: Uuid,
+        pub position: i32,
+        pub text: String,
+        #[cynic(rename = "media_id")]
+        pub media_id: Option<Uuid>,
+        pub media: Option<Media>,
+        #[cynic(rename = "tweet_id")]
+        pub tweet_id: Option<String>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "media")]
+    pub struct Media {
+        #[cynic(rename = "alt_text")]
+        pub alt_text: Option<String>,
+    }
+}
+
+use super::error::{build_errors, HasuraError, NetworkSnafu};
+use crate::model::{MessagePart, MessageThread};
+use snafu::prelude::*;
+
+pub async fn exec(message_id: uuid::Uuid) -> Result<MessageThread, HasuraError> {
+    use cynic::QueryBuilder;
+
+    let vars = queries::GetMessageThreadVariables {
+        id: crate::gq::common::scalars::Uuid(message_id),
+    };
+
+    let operation = queries::GetMessageThread::build(vars);
+
+    let resp = super::common::run_graphql(operation)
+        .await
+        .context(NetworkSnafu)?;
+
+    let message = resp
+        .data
+        .ok_or_else(|| build_errors(resp.errors))?
+        .message_by_pk
+        .ok_or(HasuraError::DataNotFound)?;
+
+    let parts = message
+        .parts
+        .into_iter()
+        .map(|part| {
+            MessagePart::new(
+                part.id.0,
+                part.position,
+                part.text,
+                part.media_id.map(|media_id| media_id.0),
+                part.media.and_then(|media| media.alt_text),
+                part.tweet_id,
+            )
+        })
+        .collect();
+
+    Ok(MessageThread::new(message.thread_tweet_id, parts))
+}
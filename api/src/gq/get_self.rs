@@ -7,6 +7,7 @@ query GetSelfProfile($id: String!) {
     active
     last_seen
     email_confirm_code_issued_at
+    require_alt_text
   }
 }
 */
@@ -46,6 +47,8 @@ mod queries {
         pub email_confirm_code: Option<String>,
         #[cynic(rename = "email_confirm_code_issued_at")]
         pub email_confirm_code_issued_at: Option<Timestamptz>,
+        #[cynic(rename = "require_alt_text")]
+        pub require_alt_text: bool,
     }
 }
 
@@ -108,6 +111,7 @@ pub async fn get_self(user_id: String) -> Result<Option<crate::model::HasuraUser
                     email_confirm_code_issued_at: user
                         .email_confirm_code_issued_at
                         .map(|email_confirm_code_issued_at| email_confirm_code_issued_at.into()),
+                    require_alt_text: user.require_alt_text,
                 }
             })
         })
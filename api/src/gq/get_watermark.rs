@@ -0,0 +1,69 @@
+/*
+query GetWatermark($user_id: String!) {
+  watermark_by_pk(user_id: $user_id) {
+    enabled
+    media_id
+    opacity
+    position
+    scale
+  }
+}
+*/
+#[cynic::schema_for_derives(file = r#"schema.graphql"#, module = "schema")]
+mod queries {
+    use crate::gq::common::scalars::*;
+    use crate::gq::common::schema;
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct GetWatermarkVariables {
+        pub user_id: String,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "query_root", variables = "GetWatermarkVariables")]
+    pub struct GetWatermark {
+        #[arguments(user_id: $user_id)]
+        #[cynic(rename = "watermark_by_pk")]
+        pub watermark_by_pk: Option<Watermark>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "watermark")]
+    pub struct Watermark {
+        pub enabled: bool,
+        #[cynic(rename = "media_id")]
+        pub media_id: Uuid,
+        pub opacity: f64,
+        pub position: String,
+        pub scale: f64,
+    }
+}
+
+use super::error::{build_errors, HasuraError, NetworkSnafu};
+use snafu::prelude::*;
+
+// 透かしを設定していないか、無効にしている場合はNoneを返す
+pub async fn exec(user_id: String) -> Result<Option<crate::watermark::Settings>, HasuraError> {
+    use cynic::QueryBuilder;
+
+    let vars = queries::GetWatermarkVariables { user_id };
+
+    let operation = queries::GetWatermark::build(vars);
+
+    let resp = super::common::run_graphql(operation)
+        .await
+        .context(NetworkSnafu)?;
+
+    resp.data
+        .ok_or_else(|| build_errors(resp.errors))
+        .map(|data| {
+            data.watermark_by_pk
+                .filter(|watermark| watermark.enabled)
+                .map(|watermark| crate::watermark::Settings {
+                    media_id: watermark.media_id.0,
+                    position: watermark.position.parse().unwrap_or_default(),
+                    opacity: watermark.opacity as f32,
+                    scale: watermark.scale as f32,
+                })
+        })
+}
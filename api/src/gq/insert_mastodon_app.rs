@@ -0,0 +1,64 @@
+/*
+mutation InsertMastodonApp($instance: String!, $client_id: String!, $client_secret: String!) {
+  insert_mastodon_app_one(
+    object: {instance: $instance, client_id: $client_id, client_secret: $client_secret}
+    on_conflict: {constraint: mastodon_app_pkey, update_columns: []}
+  ) {
+    instance
+  }
+}
+*/
+#[cynic::schema_for_derives(file = r#"schema.graphql"#, module = "schema")]
+mod queries {
+    use crate::gq::common::schema;
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct InsertMastodonAppVariables {
+        pub instance: String,
+        pub client_id: String,
+        pub client_secret: String,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(
+        graphql_type = "mutation_root",
+        variables = "InsertMastodonAppVariables"
+    )]
+    pub struct InsertMastodonApp {
+        #[arguments(object: { instance: $instance, client_id: $client_id, client_secret: $client_secret }, on_conflict: { constraint: "mastodon_app_pkey", update_columns: [] })]
+        #[cynic(rename = "insert_mastodon_app_one")]
+        pub insert_mastodon_app_one: Option<MastodonApp>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "mastodon_app")]
+    #[allow(dead_code)]
+    pub struct MastodonApp {
+        pub instance: String,
+    }
+}
+
+use super::error::{build_errors, HasuraError, NetworkSnafu};
+use crate::publisher::mastodon::App;
+use snafu::prelude::*;
+
+// 同時に登録された場合は先に保存したアプリを使うので、上書きせずにfalseを返す
+pub async fn exec(instance: String, app: &App) -> Result<bool, HasuraError> {
+    use cynic::MutationBuilder;
+
+    let vars = queries::InsertMastodonAppVariables {
+        instance,
+        client_id: app.client_id.clone(),
+        client_secret: app.client_secret.clone(),
+    };
+
+    let operation = queries::InsertMastodonApp::build(vars);
+
+    let resp = super::common::run_graphql(operation)
+        .await
+        .context(NetworkSnafu)?;
+
+    resp.data
+        .ok_or_else(|| build_errors(resp.errors))
+        .map(|data| data.insert_mastodon_app_one.is_some())
+}
@@ -0,0 +1,168 @@
+/*
+mutation InsertTaskRssHistory($objects: [task_rss_history_insert_input!]!, $on_conflict: task_rss_history_on_conflict!) {
+  insert_task_rss_history(objects: $objects, on_conflict: $on_conflict) {
+    affected_rows
+  }
+}
+*/
+#[cynic::schema_for_derives(file = r#"schema.graphql"#, module = "schema")]
+mod queries {
+    use crate::gq::common::scalars::*;
+    use crate::gq::common::schema;
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct InsertTaskRssHistoryVariables {
+        pub objects: Vec<TaskRssHistoryInsertInput>,
+        pub on_conflict: TaskRssHistoryOnConflict,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(
+        graphql_type = "mutation_root",
+        variables = "InsertTaskRssHistoryVariables"
+    )]
+    pub struct InsertTaskRssHistory {
+        #[arguments(objects: $objects, on_conflict: $on_conflict)]
+        #[cynic(rename = "insert_task_rss_history")]
+        pub insert_task_rss_history: Option<TaskRssHistoryMutationResponse>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "task_rss_history_mutation_response")]
+    pub struct TaskRssHistoryMutationResponse {
+        #[cynic(rename = "affected_rows")]
+        pub affected_rows: i32,
+    }
+
+    #[derive(cynic::InputObject, Debug)]
+    #[cynic(graphql_type = "task_rss_history_insert_input")]
+    pub struct TaskRssHistoryInsertInput {
+        #[cynic(rename = "task_rss_id")]
+        pub task_rss_id: Uuid,
+        #[cynic(rename = "user_id")]
+        pub user_id: String,
+        pub status: String,
+        pub title: Option<String>,
+        pub link: Option<String>,
+        #[cynic(rename = "pub_date")]
+        pub pub_date: Option<Timestamptz>,
+        pub reason: Option<String>,
+        #[cynic(rename = "created_at")]
+        pub created_at: Timestamptz,
+    }
+
+    #[derive(cynic::InputObject, Debug)]
+    #[cynic(graphql_type = "task_rss_history_on_conflict")]
+    pub struct TaskRssHistoryOnConflict {
+        pub constraint: TaskRssHistoryConstraint,
+        #[cynic(rename = "update_columns")]
+        pub update_columns: Vec<TaskRssHistoryUpdateColumn>,
+    }
+
+    #[derive(cynic::Enum, Clone, Copy, Debug)]
+    #[cynic(graphql_type = "task_rss_history_constraint")]
+    pub enum TaskRssHistoryConstraint {
+        #[cynic(rename = "task_rss_history_task_rss_id_link_status_key")]
+        TaskRssIdLinkStatusKey,
+        #[cynic(rename = "task_rss_history_pkey")]
+        Pkey,
+    }
+
+    #[derive(cynic::Enum, Clone, Copy, Debug)]
+    #[cynic(graphql_type = "task_rss_history_update_column")]
+    pub enum TaskRssHistoryUpdateColumn {
+        #[cynic(rename = "created_at")]
+        CreatedAt,
+        #[cynic(rename = "id")]
+        Id,
+        #[cynic(rename = "link")]
+        Link,
+        #[cynic(rename = "pub_date")]
+        PubDate,
+        #[cynic(rename = "reason")]
+        Reason,
+        #[cynic(rename = "status")]
+        Status,
+        #[cynic(rename = "task_rss_id")]
+        TaskRssId,
+        #[cynic(rename = "title")]
+        Title,
+        #[cynic(rename = "user_id")]
+        UserId,
+    }
+}
+
+use super::error::{build_errors, HasuraError, NetworkSnafu};
+use crate::model::{RssHistory, RssHistoryStatus};
+use snafu::prelude::*;
+
+// 同じ記事の同じ結果は1行にまとめる
+// 投稿や失敗は最後に記録された日時と理由で上書きし、除外は最初に除外した日時のまま残す
+// ランダムに投稿するタスクは実行のたびにフィード全体を評価するので、除外を上書きすると毎回すべての行が書き換わる
+pub async fn exec(histories: Vec<RssHistory>) -> Result<i32, HasuraError> {
+    use queries::TaskRssHistoryUpdateColumn;
+
+    let (filtered, others): (Vec<_>, Vec<_>) = histories
+        .into_iter()
+        .partition(|history| matches!(history.status, RssHistoryStatus::Filtered));
+
+    let mut affected_rows = 0;
+    if !others.is_empty() {
+        affected_rows += insert(
+            others,
+            vec![
+                TaskRssHistoryUpdateColumn::Title,
+                TaskRssHistoryUpdateColumn::PubDate,
+                TaskRssHistoryUpdateColumn::Reason,
+                TaskRssHistoryUpdateColumn::CreatedAt,
+            ],
+        )
+        .await?;
+    }
+    if !filtered.is_empty() {
+        affected_rows += insert(filtered, vec![]).await?;
+    }
+
+    Ok(affected_rows)
+}
+
+async fn insert(
+    histories: Vec<RssHistory>,
+    update_columns: Vec<queries::TaskRssHistoryUpdateColumn>,
+) -> Result<i32, HasuraError> {
+    use cynic::MutationBuilder;
+
+    let objects = histories
+        .into_iter()
+        .map(|history| queries::TaskRssHistoryInsertInput {
+            task_rss_id: crate::gq::common::scalars::Uuid(history.task_rss_id),
+            user_id: history.user_id,
+            status: history.status.to_string(),
+            title: history.title,
+            link: history.link,
+            pub_date: history.pub_date.map(|pub_date| pub_date.into()),
+            reason: history.reason,
+            created_at: time::OffsetDateTime::now_utc().into(),
+        })
+        .collect();
+
+    let vars = queries::InsertTaskRssHistoryVariables {
+        objects,
+        on_conflict: queries::TaskRssHistoryOnConflict {
+            constraint: queries::TaskRssHistoryConstraint::TaskRssIdLinkStatusKey,
+            update_columns,
+        },
+    };
+
+    let operation = queries::InsertTaskRssHistory::build(vars);
+
+    let resp = super::common::run_graphql(operation)
+        .await
+        .context(NetworkSnafu)?;
+
+    resp.data
+        .ok_or_else(|| build_errors(resp.errors))?
+        .insert_task_rss_history
+        .map(|resp| resp.affected_rows)
+        .ok_or(HasuraError::DataNotFound)
+}
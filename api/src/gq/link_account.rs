@@ -0,0 +1,167 @@
+use super::error::{build_errors, HasuraError, NetworkSnafu};
+use snafu::prelude::*;
+/*
+mutation LinkAccount($access_token: String!, $expires_in: Int, $id: String!, $issued_at: timestamptz!, $refresh_token: String, $name: String!, $user_name: String!, $user_id: String!, $provider: String!, $instance: String, $on_conflict: session_on_conflict!) {
+    insert_session_one(
+      object: {access_token: $access_token, expires_in: $expires_in, id: $id, refresh_token: $refresh_token, issued_at: $issued_at, name: $name, user_name: $user_name, user_id: $user_id, provider: $provider, instance: $instance}
+      on_conflict: $on_conflict
+    ) {
+      id
+    }
+  }
+*/
+#[allow(non_camel_case_types)]
+#[cynic::schema_for_derives(file = r#"schema.graphql"#, module = "schema")]
+mod queries {
+    use crate::gq::common::scalars::*;
+    use crate::gq::common::schema;
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct LinkAccountVariables {
+        pub access_token: String,
+        pub expires_in: Option<i32>,
+        pub id: String,
+        pub issued_at: Timestamptz,
+        pub refresh_token: Option<String>,
+        pub name: String,
+        pub user_name: String,
+        pub user_id: String,
+        pub provider: String,
+        pub instance: Option<String>,
+        pub on_conflict: SessionOnConflict,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "mutation_root", variables = "LinkAccountVariables")]
+    pub struct LinkAccount {
+        #[arguments(object: { access_token: $access_token, expires_in: $expires_in, id: $id, issued_at: $issued_at, refresh_token: $refresh_token, name: $name, user_name: $user_name, user_id: $user_id, provider: $provider, instance: $instance }, on_conflict: $on_conflict)]
+        #[cynic(rename = "insert_session_one")]
+        pub insert_session_one: Option<session>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[allow(non_camel_case_types)]
+    pub struct session {
+        pub id: String,
+    }
+
+    #[derive(cynic::InputObject, Debug)]
+    #[cynic(graphql_type = "session_on_conflict")]
+    pub struct SessionOnConflict {
+        pub constraint: SessionConstraint,
+        #[cynic(rename = "update_columns")]
+        pub update_columns: Vec<SessionUpdateColumn>,
+        #[cynic(rename = "where")]
+        pub where_: Option<SessionBoolExp>,
+    }
+
+    #[derive(cynic::InputObject, Debug)]
+    #[cynic(graphql_type = "session_bool_exp")]
+    pub struct SessionBoolExp {
+        #[cynic(rename = "user_id")]
+        pub user_id: Option<StringComparisonExp>,
+    }
+
+    #[derive(cynic::InputObject, Debug)]
+    #[cynic(graphql_type = "String_comparison_exp")]
+    pub struct StringComparisonExp {
+        #[cynic(rename = "_eq")]
+        pub eq: Option<String>,
+    }
+
+    #[derive(cynic::Enum, Clone, Copy, Debug)]
+    #[cynic(graphql_type = "session_constraint")]
+    pub enum SessionConstraint {
+        #[cynic(rename = "session_pkey")]
+        Pkey,
+    }
+
+    #[derive(cynic::Enum, Clone, Copy, Debug)]
+    #[cynic(graphql_type = "session_update_column")]
+    pub enum SessionUpdateColumn {
+        #[cynic(rename = "access_token")]
+        AccessToken,
+        #[cynic(rename = "expires_in")]
+        ExpiresIn,
+        #[cynic(rename = "id")]
+        Id,
+        #[cynic(rename = "instance")]
+        Instance,
+        #[cynic(rename = "issued_at")]
+        IssuedAt,
+        #[cynic(rename = "name")]
+        Name,
+        #[cynic(rename = "provider")]
+        Provider,
+        #[cynic(rename = "refresh_token")]
+        RefreshToken,
+        #[cynic(rename = "user_id")]
+        UserId,
+        #[cynic(rename = "user_name")]
+        UserName,
+    }
+}
+
+// ログイン中のユーザーに、別のアカウントのトークンを連携する
+// 他のユーザーに連携済みのアカウントと、ログインに使われているアカウントは上書きせず、エラーにする
+pub async fn exec(
+    user_id: String,
+    account: crate::publisher::Account,
+    name: String,
+    user_name: String,
+) -> Result<String, HasuraError> {
+    use cynic::MutationBuilder;
+    use queries::{SessionConstraint, SessionUpdateColumn};
+
+    // ログインに使われているアカウントのuser_idは空なので、upsertの条件だけでは防げない
+    let token = account.token;
+    if super::get_self::get_self(token.id.clone()).await?.is_some() {
+        return Err(HasuraError::AccountLinkedToAnotherUser);
+    }
+
+    let on_conflict = queries::SessionOnConflict {
+        constraint: SessionConstraint::Pkey,
+        update_columns: vec![
+            SessionUpdateColumn::AccessToken,
+            SessionUpdateColumn::RefreshToken,
+            SessionUpdateColumn::ExpiresIn,
+            SessionUpdateColumn::IssuedAt,
+            SessionUpdateColumn::Name,
+            SessionUpdateColumn::UserName,
+            SessionUpdateColumn::Provider,
+            SessionUpdateColumn::Instance,
+        ],
+        // 連携し直せるのは、同じユーザーに連携済みのアカウントだけ
+        where_: Some(queries::SessionBoolExp {
+            user_id: Some(queries::StringComparisonExp {
+                eq: Some(user_id.clone()),
+            }),
+        }),
+    };
+
+    let vars = queries::LinkAccountVariables {
+        id: token.id,
+        access_token: token.access_token,
+        refresh_token: token.refresh_token,
+        expires_in: token.expires_in.map(|d| d.as_secs() as i32),
+        issued_at: token.issued_at.into(),
+        name,
+        user_name,
+        user_id,
+        provider: account.provider.to_string(),
+        instance: account.instance,
+        on_conflict,
+    };
+
+    let operation = queries::LinkAccount::build(vars);
+
+    let resp = super::common::run_graphql(operation)
+        .await
+        .context(NetworkSnafu)?;
+
+    resp.data
+        .ok_or_else(|| build_errors(resp.errors))?
+        .insert_session_one
+        .map(|session| session.id)
+        .ok_or(HasuraError::AccountLinkedToAnotherUser)
+}
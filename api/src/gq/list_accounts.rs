@@ -0,0 +1,92 @@
+/*
+query ListAccounts($where: session_bool_exp!) {
+  session(where: $where) {
+    id
+  }
+}
+*/
+#[cynic::schema_for_derives(file = r#"schema.graphql"#, module = "schema")]
+mod queries {
+    use crate::gq::common::schema;
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct ListAccountsVariables {
+        #[cynic(rename = "where")]
+        pub where_: SessionBoolExp,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "query_root", variables = "ListAccountsVariables")]
+    pub struct ListAccounts {
+        #[arguments(where: $where_)]
+        pub session: Vec<Session>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "session")]
+    pub struct Session {
+        pub id: String,
+    }
+
+    #[derive(cynic::InputObject, Debug)]
+    #[cynic(graphql_type = "session_bool_exp")]
+    pub struct SessionBoolExp {
+        #[cynic(rename = "_or", skip_serializing_if = "Option::is_none")]
+        pub or: Option<Vec<SessionBoolExp>>,
+        #[cynic(skip_serializing_if = "Option::is_none")]
+        pub id: Option<StringComparisonExp>,
+        #[cynic(rename = "user_id", skip_serializing_if = "Option::is_none")]
+        pub user_id: Option<StringComparisonExp>,
+    }
+
+    #[derive(cynic::InputObject, Debug)]
+    #[cynic(graphql_type = "String_comparison_exp")]
+    pub struct StringComparisonExp {
+        #[cynic(rename = "_eq")]
+        pub eq: Option<String>,
+    }
+}
+
+use super::error::{build_errors, HasuraError, NetworkSnafu};
+use snafu::prelude::*;
+
+// ログインに使ったアカウントと、連携したアカウントのIDを返す
+pub async fn exec(user_id: String) -> Result<Vec<String>, HasuraError> {
+    use cynic::QueryBuilder;
+
+    use queries::{SessionBoolExp, StringComparisonExp};
+
+    let eq = |user_id: &str| {
+        Some(StringComparisonExp {
+            eq: Some(user_id.to_string()),
+        })
+    };
+    let vars = queries::ListAccountsVariables {
+        where_: SessionBoolExp {
+            or: Some(vec![
+                SessionBoolExp {
+                    or: None,
+                    id: eq(&user_id),
+                    user_id: None,
+                },
+                SessionBoolExp {
+                    or: None,
+                    id: None,
+                    user_id: eq(&user_id),
+                },
+            ]),
+            id: None,
+            user_id: None,
+        },
+    };
+
+    let operation = queries::ListAccounts::build(vars);
+
+    let resp = super::common::run_graphql(operation)
+        .await
+        .context(NetworkSnafu)?;
+
+    resp.data
+        .ok_or_else(|| build_errors(resp.errors))
+        .map(|data| data.session.into_iter().map(|session| session.id).collect())
+}
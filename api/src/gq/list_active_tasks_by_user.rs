@@ -2,29 +2,59 @@
 query ListActiveTasksByUser($id: String!) {
   user(where: {active: {_eq: true}, id: {_eq: $id}}) {
     id
+    role
+    require_alt_text
     tasks_fixed_time(where: {enabled: {_eq: true}}) {
+      account_id
+      content_warning
       fri
       id
       mon
       random
+      reshuffle
       sat
+      strategy
       sun
       thu
       tue
       tweet_at
+      visibility
       wed
       tag {
         messages {
           priority
-          media_id
+          attachments {
+            media_id
+            position
+            media {
+              content_type
+              alt_text
+            }
+          }
           text
           id
           created_at
           updated_at
+          tweeted
+          last_tweeted_at
+          thread_retry
+          thread_tweet_id
+          parts {
+            id
+            position
+            text
+            media_id
+            media {
+              alt_text
+            }
+            tweet_id
+          }
         }
       }
     }
     tasks_rss(where: {enabled: {_eq: true}}) {
+      account_id
+      content_warning
       fri
       id
       mon
@@ -34,16 +64,26 @@ query ListActiveTasksByUser($id: String!) {
       thu
       tue
       tweet_at
+      visibility
       wed
       template
       url
       last_pub_date
+      attach_image
+      interval_minutes
+      filters {
+        action
+        field
+        pattern
+        regex
+      }
     }
   }
 }
 */
 
 mod queries {
+    use crate::gq::common::enums::*;
     use crate::gq::common::scalars::*;
     use crate::gq::common::schema;
 
@@ -66,6 +106,9 @@ mod queries {
     #[cynic(graphql_type = "user")]
     pub struct User {
         pub id: String,
+        pub role: RoleEnum,
+        #[cynic(rename = "require_alt_text")]
+        pub require_alt_text: bool,
         #[arguments(where: { enabled: { _eq: true } })]
         #[cynic(rename = "tasks_fixed_time")]
         pub tasks_fixed_time: Vec<TaskFixedTime>,
@@ -78,16 +121,23 @@ mod queries {
     #[derive(cynic::QueryFragment, Debug)]
     #[cynic(graphql_type = "task_fixed_time")]
     pub struct TaskFixedTime {
+        #[cynic(rename = "account_id")]
+        pub account_id: Option<String>,
+        #[cynic(rename = "content_warning")]
+        pub content_warning: Option<String>,
         pub fri: bool,
         pub id: Uuid,
         pub mon: bool,
         pub random: bool,
+        pub reshuffle: bool,
         pub sat: bool,
+        pub strategy: Option<String>,
         pub sun: bool,
         pub thu: bool,
         pub tue: bool,
         #[cynic(rename = "tweet_at")]
         pub tweet_at: Time,
+        pub visibility: Option<String>,
         pub wed: bool,
         pub tag: Option<Tag>,
     }
@@ -102,19 +152,61 @@ mod queries {
     #[cynic(graphql_type = "message")]
     pub struct Message {
         pub priority: i32,
-        #[cynic(rename = "media_id")]
-        pub media_id: Option<Uuid>,
+        pub attachments: Vec<MessageMedia>,
         pub text: String,
         pub id: Uuid,
         #[cynic(rename = "created_at")]
         pub created_at: Timestamptz,
         #[cynic(rename = "updated_at")]
         pub updated_at: Timestamptz,
+        pub tweeted: bool,
+        #[cynic(rename = "last_tweeted_at")]
+        pub last_tweeted_at: Option<Timestamptz>,
+        #[cynic(rename = "thread_retry")]
+        pub thread_retry: String,
+        #[cynic(rename = "thread_tweet_id")]
+        pub thread_tweet_id: Option<String>,
+        pub parts: Vec<MessagePart>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "message_media")]
+    pub struct MessageMedia {
+        #[cynic(rename = "media_id")]
+        pub media_id: Uuid,
+        pub position: i32,
+        pub media: Media,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "media")]
+    pub struct Media {
+        #[cynic(rename = "content_type")]
+        pub content_type: Option<String>,
+        #[cynic(rename = "alt_text")]
+        pub alt_text: Option<String>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "message_part")]
+    pub struct MessagePart {
+        pub id: Uuid,
+        pub position: i32,
+        pub text: String,
+        #[cynic(rename = "media_id")]
+        pub media_id: Option<Uuid>,
+        pub media: Option<Media>,
+        #[cynic(rename = "tweet_id")]
+        pub tweet_id: Option<String>,
     }
 
     #[derive(cynic::QueryFragment, Debug)]
     #[cynic(graphql_type = "task_rss")]
     pub struct TaskRss {
+        #[cynic(rename = "account_id")]
+        pub account_id: Option<String>,
+        #[cynic(rename = "content_warning")]
+        pub content_warning: Option<String>,
         pub fri: bool,
         pub id: Uuid,
         pub mon: bool,
@@ -125,11 +217,26 @@ mod queries {
         pub tue: bool,
         #[cynic(rename = "tweet_at")]
         pub tweet_at: Time,
+        pub visibility: Option<String>,
         pub wed: bool,
         pub template: Option<String>,
         pub url: String,
         #[cynic(rename = "last_pub_date")]
         pub last_pub_date: Option<Timestamptz>,
+        #[cynic(rename = "attach_image")]
+        pub attach_image: bool,
+        #[cynic(rename = "interval_minutes")]
+        pub interval_minutes: Option<i32>,
+        pub filters: Vec<TaskRssFilter>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "task_rss_filter")]
+    pub struct TaskRssFilter {
+        pub action: String,
+        pub field: String,
+        pub pattern: String,
+        pub regex: bool,
     }
 }
 
@@ -200,7 +307,12 @@ use super::error::{build_errors, HasuraError, NetworkSnafu};
 
 use snafu::prelude::*;
 
-use crate::model::{ActiveUser, EnabledTask, Message, RssTask, Schedule};
+use crate::model::{
+    ActiveUser, EnabledTask, Message, MessageMedia, MessagePart, RssTask, Schedule,
+};
+use crate::publisher::PostOptions;
+use crate::rss_filter::FilterRule;
+use crate::selection::Strategy;
 
 pub async fn list_active_tasks_by_user(
     id: String,
@@ -234,10 +346,43 @@ pub async fn list_active_tasks_by_user(
                                             message.id.0,
                                             user.id.clone(),
                                             message.text.clone(),
-                                            message.media_id.clone().map(|media_id| media_id.0),
+                                            message
+                                                .attachments
+                                                .iter()
+                                                .map(|attachment| {
+                                                    MessageMedia::new(
+                                                        attachment.media_id.0,
+                                                        attachment.position,
+                                                        attachment.media.content_type.clone(),
+                                                        attachment.media.alt_text.clone(),
+                                                    )
+                                                })
+                                                .collect(),
                                             message.priority,
                                             message.created_at.clone().into(),
                                             message.updated_at.clone().into(),
+                                            message.tweeted,
+                                            message.last_tweeted_at.clone().map(|d| d.into()),
+                                            message.thread_retry.parse().unwrap_or_default(),
+                                            message.thread_tweet_id.clone(),
+                                            message
+                                                .parts
+                                                .iter()
+                                                .map(|part| {
+                                                    MessagePart::new(
+                                                        part.id.0,
+                                                        part.position,
+                                                        part.text.clone(),
+                                                        part.media_id
+                                                            .clone()
+                                                            .map(|media_id| media_id.0),
+                                                        part.media.as_ref().and_then(|media| {
+                                                            media.alt_text.clone()
+                                                        }),
+                                                        part.tweet_id.clone(),
+                                                    )
+                                                })
+                                                .collect(),
                                         )
                                     })
                                     .collect(),
@@ -257,7 +402,24 @@ pub async fn list_active_tasks_by_user(
                                 task.fri,
                                 task.sat,
                             );
-                            EnabledTask::new(schedule, user.id.clone(), messages, task.random)
+                            let strategy = Strategy::from_task(
+                                task.strategy.as_deref(),
+                                task.random,
+                                task.reshuffle,
+                            );
+
+                            EnabledTask::new(
+                                schedule,
+                                user.id.clone(),
+                                task.account_id.clone().unwrap_or_else(|| user.id.clone()),
+                                PostOptions::new(
+                                    task.visibility.as_deref(),
+                                    task.content_warning.clone(),
+                                ),
+                                messages,
+                                strategy,
+                                user.require_alt_text,
+                            )
                         })
                         .collect();
 
@@ -283,15 +445,33 @@ pub async fn list_active_tasks_by_user(
                                 task.id.0,
                                 schedule,
                                 user.id.clone(),
+                                task.account_id.clone().unwrap_or_else(|| user.id.clone()),
+                                PostOptions::new(
+                                    task.visibility.as_deref(),
+                                    task.content_warning.clone(),
+                                ),
                                 task.url.clone(),
                                 task.random,
                                 task.last_pub_date.clone().map(|d| d.into()),
                                 task.template.clone(),
+                                task.attach_image,
+                                task.filters
+                                    .iter()
+                                    .map(|filter| {
+                                        FilterRule::new(
+                                            filter.action.clone(),
+                                            filter.field.clone(),
+                                            filter.pattern.clone(),
+                                            filter.regex,
+                                        )
+                                    })
+                                    .collect(),
+                                task.interval_minutes,
                             )
                         })
                         .collect();
 
-                    ActiveUser::new(user.id.clone(), tasks, tasks_rss)
+                    ActiveUser::new(user.id.clone(), user.role.into(), tasks, tasks_rss)
                 })
                 .collect()
         })
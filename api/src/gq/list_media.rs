@@ -0,0 +1,81 @@
+/*
+query ListMedia($ids: [uuid!], $user_id: String!) {
+  media(where: {id: {_in: $ids}, user_id: {_eq: $user_id}}) {
+    id
+    content_type
+    alt_text
+  }
+}
+*/
+#[cynic::schema_for_derives(file = r#"schema.graphql"#, module = "schema")]
+mod queries {
+    use crate::gq::common::scalars::*;
+    use crate::gq::common::schema;
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct ListMediaVariables {
+        pub ids: Option<Vec<Uuid>>,
+        pub user_id: String,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "query_root", variables = "ListMediaVariables")]
+    pub struct ListMedia {
+        #[arguments(where: { id: { _in: $ids }, user_id: { _eq: $user_id } })]
+        pub media: Vec<Media>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "media")]
+    pub struct Media {
+        pub id: Uuid,
+        #[cynic(rename = "content_type")]
+        pub content_type: Option<String>,
+        #[cynic(rename = "alt_text")]
+        pub alt_text: Option<String>,
+    }
+}
+
+use super::error::{build_errors, HasuraError, NetworkSnafu};
+use snafu::prelude::*;
+
+// メディアのID、MIMEタイプと代替テキストを返す
+pub async fn exec(
+    user_id: String,
+    media_ids: Vec<uuid::Uuid>,
+) -> Result<Vec<crate::model::MessageMedia>, HasuraError> {
+    use cynic::QueryBuilder;
+
+    let vars = queries::ListMediaVariables {
+        ids: Some(
+            media_ids
+                .into_iter()
+                .map(crate::gq::common::scalars::Uuid)
+                .collect(),
+        ),
+        user_id,
+    };
+
+    let operation = queries::ListMedia::build(vars);
+
+    let resp = super::common::run_graphql(operation)
+        .await
+        .context(NetworkSnafu)?;
+
+    resp.data
+        .ok_or_else(|| build_errors(resp.errors))
+        .map(|data| {
+            data.media
+                .into_iter()
+                .enumerate()
+                .map(|(position, media)| {
+                    crate::model::MessageMedia::new(
+                        media.id.0,
+                        position as i32,
+                        media.content_type,
+                        media.alt_text,
+                    )
+                })
+                .collect()
+        })
+}
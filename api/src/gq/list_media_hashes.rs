@@ -0,0 +1,58 @@
+/*
+query ListMediaHashes($user_id: String!) {
+  media(where: {user_id: {_eq: $user_id}, perceptual_hash: {_is_null: false}}) {
+    id
+    perceptual_hash
+  }
+}
+*/
+#[cynic::schema_for_derives(file = r#"schema.graphql"#, module = "schema")]
+mod queries {
+    use crate::gq::common::scalars::*;
+    use crate::gq::common::schema;
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct ListMediaHashesVariables {
+        pub user_id: String,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "query_root", variables = "ListMediaHashesVariables")]
+    pub struct ListMediaHashes {
+        #[arguments(where: { user_id: { _eq: $user_id }, perceptual_hash: { _is_null: false } })]
+        pub media: Vec<Media>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "media")]
+    pub struct Media {
+        pub id: Uuid,
+        #[cynic(rename = "perceptual_hash")]
+        pub perceptual_hash: Option<String>,
+    }
+}
+
+use super::error::{build_errors, HasuraError, NetworkSnafu};
+use snafu::prelude::*;
+
+// ユーザーの画像のIDとdHashを返す（ハッシュの無い動画などは含まない）
+pub async fn exec(user_id: String) -> Result<Vec<(uuid::Uuid, String)>, HasuraError> {
+    use cynic::QueryBuilder;
+
+    let vars = queries::ListMediaHashesVariables { user_id };
+
+    let operation = queries::ListMediaHashes::build(vars);
+
+    let resp = super::common::run_graphql(operation)
+        .await
+        .context(NetworkSnafu)?;
+
+    resp.data
+        .ok_or_else(|| build_errors(resp.errors))
+        .map(|data| {
+            data.media
+                .into_iter()
+                .filter_map(|media| Some((media.id.0, media.perceptual_hash?)))
+                .collect()
+        })
+}
@@ -0,0 +1,50 @@
+/*
+query ListMediaOwners {
+  media {
+    id
+    user_id
+  }
+}
+*/
+#[cynic::schema_for_derives(file = r#"schema.graphql"#, module = "schema")]
+mod queries {
+    use crate::gq::common::scalars::*;
+    use crate::gq::common::schema;
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "query_root")]
+    pub struct ListMediaOwners {
+        pub media: Vec<Media>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "media")]
+    pub struct Media {
+        pub id: Uuid,
+        #[cynic(rename = "user_id")]
+        pub user_id: String,
+    }
+}
+
+use super::error::{build_errors, HasuraError, NetworkSnafu};
+use snafu::prelude::*;
+
+// すべてのメディアのIDと所有者を返す
+pub async fn exec() -> Result<Vec<(uuid::Uuid, String)>, HasuraError> {
+    use cynic::QueryBuilder;
+
+    let operation = queries::ListMediaOwners::build(());
+
+    let resp = super::common::run_graphql(operation)
+        .await
+        .context(NetworkSnafu)?;
+
+    resp.data
+        .ok_or_else(|| build_errors(resp.errors))
+        .map(|data| {
+            data.media
+                .into_iter()
+                .map(|media| (media.id.0, media.user_id))
+                .collect()
+        })
+}
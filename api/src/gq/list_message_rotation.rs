@@ -0,0 +1,76 @@
+/*
+query ListMessageRotation($ids: [uuid!]) {
+  message(where: {id: {_in: $ids}}) {
+    id
+    tweeted
+    last_tweeted_at
+  }
+}
+*/
+#[cynic::schema_for_derives(file = r#"schema.graphql"#, module = "schema")]
+mod queries {
+    use crate::gq::common::scalars::*;
+    use crate::gq::common::schema;
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct ListMessageRotationVariables {
+        pub ids: Option<Vec<Uuid>>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(
+        graphql_type = "query_root",
+        variables = "ListMessageRotationVariables"
+    )]
+    pub struct ListMessageRotation {
+        #[arguments(where: { id: { _in: $ids } })]
+        pub message: Vec<Message>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "message")]
+    pub struct Message {
+        pub id: Uuid,
+        pub tweeted: bool,
+        #[cynic(rename = "last_tweeted_at")]
+        pub last_tweeted_at: Option<Timestamptz>,
+    }
+}
+
+use super::error::{build_errors, HasuraError, NetworkSnafu};
+use crate::model::MessageRotation;
+use snafu::prelude::*;
+
+pub async fn exec(message_ids: Vec<uuid::Uuid>) -> Result<Vec<MessageRotation>, HasuraError> {
+    use cynic::QueryBuilder;
+
+    let vars = queries::ListMessageRotationVariables {
+        ids: Some(
+            message_ids
+                .into_iter()
+                .map(crate::gq::common::scalars::Uuid)
+                .collect(),
+        ),
+    };
+
+    let operation = queries::ListMessageRotation::build(vars);
+
+    let resp = super::common::run_graphql(operation)
+        .await
+        .context(NetworkSnafu)?;
+
+    resp.data
+        .ok_or_else(|| build_errors(resp.errors))
+        .map(|data| {
+            data.message
+                .into_iter()
+                .map(|message| {
+                    MessageRotation::new(
+                        message.id.0,
+                        message.tweeted,
+                        message.last_tweeted_at.map(|d| d.into()),
+                    )
+                })
+                .collect()
+        })
+}
@@ -0,0 +1,73 @@
+/*
+query ListUnhashedMedia($limit: Int!, $skip: [uuid!]) {
+  media(where: {content_hash: {_is_null: true}, id: {_nin: $skip}}, limit: $limit) {
+    id
+    user_id
+    content_type
+  }
+}
+*/
+#[cynic::schema_for_derives(file = r#"schema.graphql"#, module = "schema")]
+mod queries {
+    use crate::gq::common::scalars::*;
+    use crate::gq::common::schema;
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct ListUnhashedMediaVariables {
+        pub limit: i32,
+        pub skip: Option<Vec<Uuid>>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "query_root", variables = "ListUnhashedMediaVariables")]
+    pub struct ListUnhashedMedia {
+        #[arguments(where: { content_hash: { _is_null: true }, id: { _nin: $skip } }, limit: $limit)]
+        pub media: Vec<Media>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "media")]
+    pub struct Media {
+        pub id: Uuid,
+        #[cynic(rename = "user_id")]
+        pub user_id: String,
+        #[cynic(rename = "content_type")]
+        pub content_type: Option<String>,
+    }
+}
+
+use super::error::{build_errors, HasuraError, NetworkSnafu};
+use snafu::prelude::*;
+
+// ハッシュをまだ計算していないメディアのID、所有者とMIMEタイプを返す
+// skipには、同じ実行の中で処理できなかったメディアを渡して取得し直さないようにする
+pub async fn exec(
+    limit: i32,
+    skip: Vec<uuid::Uuid>,
+) -> Result<Vec<(uuid::Uuid, String, Option<String>)>, HasuraError> {
+    use cynic::QueryBuilder;
+
+    let vars = queries::ListUnhashedMediaVariables {
+        limit,
+        skip: Some(
+            skip.into_iter()
+                .map(crate::gq::common::scalars::Uuid)
+                .collect(),
+        ),
+    };
+
+    let operation = queries::ListUnhashedMedia::build(vars);
+
+    let resp = super::common::run_graphql(operation)
+        .await
+        .context(NetworkSnafu)?;
+
+    resp.data
+        .ok_or_else(|| build_errors(resp.errors))
+        .map(|data| {
+            data.media
+                .into_iter()
+                .map(|media| (media.id.0, media.user_id, media.content_type))
+                .collect()
+        })
+}
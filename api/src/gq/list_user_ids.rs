@@ -0,0 +1,41 @@
+/*
+query ListUserIds {
+  user {
+    id
+  }
+}
+*/
+#[cynic::schema_for_derives(file = r#"schema.graphql"#, module = "schema")]
+mod queries {
+    use crate::gq::common::schema;
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "query_root")]
+    pub struct ListUserIds {
+        pub user: Vec<User>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "user")]
+    pub struct User {
+        pub id: String,
+    }
+}
+
+use super::error::{build_errors, HasuraError, NetworkSnafu};
+use snafu::prelude::*;
+
+// バケットはユーザーごとに作成されるので、すべてのユーザーのIDを返す
+pub async fn exec() -> Result<Vec<String>, HasuraError> {
+    use cynic::QueryBuilder;
+
+    let operation = queries::ListUserIds::build(());
+
+    let resp = super::common::run_graphql(operation)
+        .await
+        .context(NetworkSnafu)?;
+
+    resp.data
+        .ok_or_else(|| build_errors(resp.errors))
+        .map(|data| data.user.into_iter().map(|user| user.id).collect())
+}
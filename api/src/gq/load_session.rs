@@ -6,6 +6,8 @@ query LoadSession($id: String!) {
     issued_at
     refresh_token
     id
+    provider
+    instance
   }
 }
 */
@@ -39,21 +41,25 @@ mod queries {
         #[cynic(rename = "refresh_token")]
         pub refresh_token: Option<String>,
         pub id: String,
+        pub provider: String,
+        pub instance: Option<String>,
     }
 }
 
 use super::error::{build_errors, HasuraError, NetworkSnafu};
+use crate::publisher::{Account, Provider};
 use oauth2::basic::BasicClient;
 use snafu::prelude::*;
 
+// トークンはログインしたユーザーではなく、投稿に使うアカウントごとに保存している
 pub async fn load_session(
-    user_id: String,
+    account_id: String,
     oauth_client: BasicClient,
-) -> Result<crate::model::Token, HasuraError> {
+) -> Result<Account, HasuraError> {
     use cynic::QueryBuilder;
 
     let vars = queries::LoadSessionVariables {
-        id: user_id.clone(),
+        id: account_id.clone(),
     };
 
     let operation: cynic::Operation<queries::LoadSession, queries::LoadSessionVariables> =
@@ -83,6 +89,9 @@ pub async fn load_session(
         .session_by_pk
         .ok_or(HasuraError::SessionNotFound)?;
 
+    let provider = session.provider.parse().unwrap_or_default();
+    let instance = session.instance;
+
     let token = crate::model::Token::from_session(
         session.id,
         session.access_token,
@@ -91,19 +100,42 @@ pub async fn load_session(
         session.issued_at.into(),
     );
 
-    if token_expired(token.issued_at, token.expires_in) {
-        let refresh_token = token
-            .refresh_token
-            .whatever_context("RefreshToken not found")?;
-
-        let new_token = crate::twitter::refresh_token(oauth_client, user_id.clone(), refresh_token)
-            .await
-            .with_whatever_context(|err| err.to_string())?;
+    // 期限が近いトークンは更新して保存し直す（MastodonとMisskeyのトークンには有効期限がない）
+    let token = match provider {
+        Provider::Mastodon | Provider::Misskey => token,
+        _ if !token_expired(token.issued_at, token.expires_in) => token,
+        Provider::Twitter => {
+            let refresh_token = token
+                .refresh_token
+                .whatever_context("RefreshToken not found")?;
+
+            let new_token =
+                crate::twitter::refresh_token(oauth_client, account_id.clone(), refresh_token)
+                    .await
+                    .with_whatever_context(|err| err.to_string())?;
+
+            super::store_session::store_session(new_token.clone()).await?
+        }
+        Provider::Bluesky => {
+            let service = instance.as_deref().whatever_context("Instance not found")?;
+            let refresh_token = token
+                .refresh_token
+                .whatever_context("RefreshToken not found")?;
+
+            let new_token = crate::publisher::bluesky::refresh_session(service, &refresh_token)
+                .await
+                .with_whatever_context(|err| err.to_string())?
+                .into_token(account_id.clone());
+
+            super::store_session::store_session(new_token).await?
+        }
+    };
 
-        super::store_session::store_session(new_token.clone()).await
-    } else {
-        Ok(token)
-    }
+    Ok(Account {
+        provider,
+        instance,
+        token,
+    })
 }
 
 // アクセストークンは2時間（7200秒）有効とされているので、5分間の余裕をもって無効かどうかを判断する
@@ -1,13 +1,35 @@
 pub mod common;
 pub mod error;
+pub mod find_media_by_hash;
+pub mod get_mastodon_app;
+pub mod get_message_thread;
 pub mod get_self;
+pub mod get_watermark;
+pub mod link_account;
 // pub mod list_active_messages;
+pub mod list_accounts;
 pub mod list_active_tasks;
 pub mod list_active_tasks_by_user;
+pub mod list_legacy_thumbnails;
+pub mod list_media;
+pub mod list_media_hashes;
+pub mod list_media_owners;
+pub mod list_message_rotation;
+pub mod list_unhashed_media;
+pub mod list_user_ids;
 // pub mod list_task_fixed_time;
 pub mod delete_media;
+pub mod insert_mastodon_app;
+pub mod insert_task_rss_history;
 pub mod load_session;
+pub mod reset_message_rotation;
+pub mod reset_message_thread;
 pub mod store_session;
 pub mod update_last_pub_date_task_rss;
+pub mod update_media_hashes;
+pub mod update_media_thumbnail;
+pub mod update_message_part_tweet;
+pub mod update_message_thread;
+pub mod update_message_tweeted;
 pub mod upload_media;
 pub mod upsert_email;
@@ -0,0 +1,64 @@
+/*
+mutation ResetMessageRotation($ids: [uuid!]) {
+  update_message(where: {id: {_in: $ids}}, _set: {tweeted: false}) {
+    affected_rows
+  }
+}
+*/
+#[cynic::schema_for_derives(file = r#"schema.graphql"#, module = "schema")]
+mod queries {
+    use crate::gq::common::scalars::*;
+    use crate::gq::common::schema;
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct ResetMessageRotationVariables {
+        pub ids: Option<Vec<Uuid>>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(
+        graphql_type = "mutation_root",
+        variables = "ResetMessageRotationVariables"
+    )]
+    pub struct ResetMessageRotation {
+        #[arguments(where: { id: { _in: $ids } }, _set: { tweeted: false })]
+        #[cynic(rename = "update_message")]
+        pub update_message: Option<MessageMutationResponse>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "message_mutation_response")]
+    pub struct MessageMutationResponse {
+        #[cynic(rename = "affected_rows")]
+        pub affected_rows: i32,
+    }
+}
+
+use super::error::{build_errors, HasuraError, NetworkSnafu};
+use snafu::prelude::*;
+
+// ローテーションの1サイクルが終わったら、投稿済みの印をすべて消す
+pub async fn exec(message_ids: Vec<uuid::Uuid>) -> Result<i32, HasuraError> {
+    use cynic::MutationBuilder;
+
+    let vars = queries::ResetMessageRotationVariables {
+        ids: Some(
+            message_ids
+                .into_iter()
+                .map(crate::gq::common::scalars::Uuid)
+                .collect(),
+        ),
+    };
+
+    let operation = queries::ResetMessageRotation::build(vars);
+
+    let resp = super::common::run_graphql(operation)
+        .await
+        .context(NetworkSnafu)?;
+
+    resp.data
+        .ok_or_else(|| build_errors(resp.errors))?
+        .update_message
+        .map(|resp| resp.affected_rows)
+        .ok_or(HasuraError::DataNotFound)
+}
@@ -0,0 +1,77 @@
+/*
+mutation ResetMessageThread($id: uuid!) {
+  update_message_by_pk(pk_columns: {id: $id}, _set: {thread_tweet_id: null}) {
+    id
+  }
+  update_message_part(where: {message_id: {_eq: $id}}, _set: {tweet_id: null}) {
+    affected_rows
+  }
+}
+*/
+#[cynic::schema_for_derives(file = r#"schema.graphql"#, module = "schema")]
+mod queries {
+    use crate::gq::common::scalars::*;
+    use crate::gq::common::schema;
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct ResetMessageThreadVariables {
+        pub id: Uuid,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(
+        graphql_type = "mutation_root",
+        variables = "ResetMessageThreadVariables"
+    )]
+    pub struct ResetMessageThread {
+        #[arguments(pk_columns: { id: $id }, _set: { thread_tweet_id: null })]
+        #[cynic(rename = "update_message_by_pk")]
+        pub update_message_by_pk: Option<Message>,
+        #[arguments(where: { message_id: { _eq: $id } }, _set: { tweet_id: null })]
+        #[cynic(rename = "update_message_part")]
+        #[allow(dead_code)]
+        pub update_message_part: Option<MessagePartMutationResponse>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "message")]
+    pub struct Message {
+        pub id: Uuid,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "message_part_mutation_response")]
+    #[allow(dead_code)]
+    pub struct MessagePartMutationResponse {
+        #[cynic(rename = "affected_rows")]
+        pub affected_rows: i32,
+    }
+}
+
+use super::error::{build_errors, HasuraError, NetworkSnafu};
+use snafu::prelude::*;
+
+// スレッドを最後まで投稿したとき、またはやり直すときに、投稿途中の記録を消す
+pub async fn exec(message_id: uuid::Uuid) -> Result<uuid::Uuid, HasuraError> {
+    use cynic::MutationBuilder;
+
+    let vars = queries::ResetMessageThreadVariables {
+        id: crate::gq::common::scalars::Uuid(message_id),
+    };
+
+    let operation = queries::ResetMessageThread::build(vars);
+
+    let resp = super::common::run_graphql(operation)
+        .await
+        .context(NetworkSnafu)?;
+
+    let id = resp
+        .data
+        .ok_or_else(|| build_errors(resp.errors))?
+        .update_message_by_pk
+        .ok_or(HasuraError::DataNotFound)?
+        .id
+        .0;
+
+    Ok(id)
+}
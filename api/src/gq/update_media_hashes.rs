@@ -0,0 +1,67 @@
+/*
+mutation UpdateMediaHashes($id: uuid!, $content_hash: String!, $perceptual_hash: String) {
+  update_media_by_pk(pk_columns: {id: $id}, _set: {content_hash: $content_hash, perceptual_hash: $perceptual_hash}) {
+    id
+  }
+}
+*/
+#[cynic::schema_for_derives(file = r#"schema.graphql"#, module = "schema")]
+mod queries {
+    use crate::gq::common::scalars::*;
+    use crate::gq::common::schema;
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct UpdateMediaHashesVariables {
+        pub id: Uuid,
+        pub content_hash: String,
+        pub perceptual_hash: Option<String>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(
+        graphql_type = "mutation_root",
+        variables = "UpdateMediaHashesVariables"
+    )]
+    pub struct UpdateMediaHashes {
+        #[arguments(pk_columns: { id: $id }, _set: { content_hash: $content_hash, perceptual_hash: $perceptual_hash })]
+        #[cynic(rename = "update_media_by_pk")]
+        pub update_media_by_pk: Option<Media>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "media")]
+    #[allow(dead_code)]
+    pub struct Media {
+        pub id: Uuid,
+    }
+}
+
+use super::error::{build_errors, HasuraError, NetworkSnafu};
+use snafu::prelude::*;
+
+pub async fn exec(
+    id: uuid::Uuid,
+    content_hash: String,
+    perceptual_hash: Option<String>,
+) -> Result<(), HasuraError> {
+    use cynic::MutationBuilder;
+
+    let vars = queries::UpdateMediaHashesVariables {
+        id: crate::gq::common::scalars::Uuid(id),
+        content_hash,
+        perceptual_hash,
+    };
+
+    let operation = queries::UpdateMediaHashes::build(vars);
+
+    let resp = super::common::run_graphql(operation)
+        .await
+        .context(NetworkSnafu)?;
+
+    resp.data
+        .ok_or_else(|| build_errors(resp.errors))
+        .and_then(|data| match data.update_media_by_pk {
+            Some(_) => Ok(()),
+            None => Err(HasuraError::DataNotFound),
+        })
+}
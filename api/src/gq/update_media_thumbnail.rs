@@ -0,0 +1,61 @@
+/*
+mutation UpdateMediaThumbnail($id: uuid!, $thumbnail: String!) {
+  update_media_by_pk(pk_columns: {id: $id}, _set: {thumbnail: $thumbnail}) {
+    id
+  }
+}
+*/
+#[cynic::schema_for_derives(file = r#"schema.graphql"#, module = "schema")]
+mod queries {
+    use crate::gq::common::scalars::*;
+    use crate::gq::common::schema;
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct UpdateMediaThumbnailVariables {
+        pub id: Uuid,
+        pub thumbnail: String,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(
+        graphql_type = "mutation_root",
+        variables = "UpdateMediaThumbnailVariables"
+    )]
+    pub struct UpdateMediaThumbnail {
+        #[arguments(pk_columns: { id: $id }, _set: { thumbnail: $thumbnail })]
+        #[cynic(rename = "update_media_by_pk")]
+        pub update_media_by_pk: Option<Media>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "media")]
+    #[allow(dead_code)]
+    pub struct Media {
+        pub id: Uuid,
+    }
+}
+
+use super::error::{build_errors, HasuraError, NetworkSnafu};
+use snafu::prelude::*;
+
+pub async fn exec(id: uuid::Uuid, thumbnail: String) -> Result<(), HasuraError> {
+    use cynic::MutationBuilder;
+
+    let vars = queries::UpdateMediaThumbnailVariables {
+        id: crate::gq::common::scalars::Uuid(id),
+        thumbnail,
+    };
+
+    let operation = queries::UpdateMediaThumbnail::build(vars);
+
+    let resp = super::common::run_graphql(operation)
+        .await
+        .context(NetworkSnafu)?;
+
+    resp.data
+        .ok_or_else(|| build_errors(resp.errors))
+        .and_then(|data| match data.update_media_by_pk {
+            Some(_) => Ok(()),
+            None => Err(HasuraError::DataNotFound),
+        })
+}
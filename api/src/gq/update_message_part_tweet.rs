@@ -0,0 +1,67 @@
+/*
+mutation UpdateMessagePartTweet($id: uuid!, $tweet_id: String!) {
+  update_message_part_by_pk(
+    pk_columns: {id: $id}
+    _set: {tweet_id: $tweet_id}
+  ) {
+    id
+  }
+}
+*/
+#[cynic::schema_for_derives(file = r#"schema.graphql"#, module = "schema")]
+mod queries {
+    use crate::gq::common::scalars::*;
+    use crate::gq::common::schema;
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct UpdateMessagePartTweetVariables {
+        pub id: Uuid,
+        pub tweet_id: String,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(
+        graphql_type = "mutation_root",
+        variables = "UpdateMessagePartTweetVariables"
+    )]
+    pub struct UpdateMessagePartTweet {
+        #[arguments(pk_columns: { id: $id }, _set: { tweet_id: $tweet_id })]
+        #[cynic(rename = "update_message_part_by_pk")]
+        pub update_message_part_by_pk: Option<MessagePart>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "message_part")]
+    pub struct MessagePart {
+        pub id: Uuid,
+    }
+}
+
+use super::error::{build_errors, HasuraError, NetworkSnafu};
+use snafu::prelude::*;
+
+// スレッドの途中で失敗しても続きから投稿できるように、投稿済みのツイートのIDを記録する
+pub async fn exec(part_id: uuid::Uuid, tweet_id: String) -> Result<uuid::Uuid, HasuraError> {
+    use cynic::MutationBuilder;
+
+    let vars = queries::UpdateMessagePartTweetVariables {
+        id: crate::gq::common::scalars::Uuid(part_id),
+        tweet_id,
+    };
+
+    let operation = queries::UpdateMessagePartTweet::build(vars);
+
+    let resp = super::common::run_graphql(operation)
+        .await
+        .context(NetworkSnafu)?;
+
+    let id = resp
+        .data
+        .ok_or_else(|| build_errors(resp.errors))?
+        .update_message_part_by_pk
+        .ok_or(HasuraError::DataNotFound)?
+        .id
+        .0;
+
+    Ok(id)
+}
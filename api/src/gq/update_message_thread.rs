@@ -0,0 +1,70 @@
+/*
+mutation UpdateMessageThread($id: uuid!, $thread_tweet_id: String!) {
+  update_message_by_pk(
+    pk_columns: {id: $id}
+    _set: {thread_tweet_id: $thread_tweet_id}
+  ) {
+    id
+  }
+}
+*/
+#[cynic::schema_for_derives(file = r#"schema.graphql"#, module = "schema")]
+mod queries {
+    use crate::gq::common::scalars::*;
+    use crate::gq::common::schema;
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct UpdateMessageThreadVariables {
+        pub id: Uuid,
+        pub thread_tweet_id: String,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(
+        graphql_type = "mutation_root",
+        variables = "UpdateMessageThreadVariables"
+    )]
+    pub struct UpdateMessageThread {
+        #[arguments(pk_columns: { id: $id }, _set: { thread_tweet_id: $thread_tweet_id })]
+        #[cynic(rename = "update_message_by_pk")]
+        pub update_message_by_pk: Option<Message>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "message")]
+    pub struct Message {
+        pub id: Uuid,
+    }
+}
+
+use super::error::{build_errors, HasuraError, NetworkSnafu};
+use snafu::prelude::*;
+
+// スレッドの最初のツイートを投稿したら、そのIDを記録する
+pub async fn exec(
+    message_id: uuid::Uuid,
+    thread_tweet_id: String,
+) -> Result<uuid::Uuid, HasuraError> {
+    use cynic::MutationBuilder;
+
+    let vars = queries::UpdateMessageThreadVariables {
+        id: crate::gq::common::scalars::Uuid(message_id),
+        thread_tweet_id,
+    };
+
+    let operation = queries::UpdateMessageThread::build(vars);
+
+    let resp = super::common::run_graphql(operation)
+        .await
+        .context(NetworkSnafu)?;
+
+    let id = resp
+        .data
+        .ok_or_else(|| build_errors(resp.errors))?
+        .update_message_by_pk
+        .ok_or(HasuraError::DataNotFound)?
+        .id
+        .0;
+
+    Ok(id)
+}
@@ -0,0 +1,70 @@
+/*
+mutation UpdateMessageTweeted($id: uuid!, $last_tweeted_at: timestamptz!) {
+  update_message_by_pk(
+    pk_columns: {id: $id}
+    _set: {tweeted: true, last_tweeted_at: $last_tweeted_at}
+  ) {
+    id
+  }
+}
+*/
+#[cynic::schema_for_derives(file = r#"schema.graphql"#, module = "schema")]
+mod queries {
+    use crate::gq::common::scalars::*;
+    use crate::gq::common::schema;
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct UpdateMessageTweetedVariables {
+        pub id: Uuid,
+        pub last_tweeted_at: Timestamptz,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(
+        graphql_type = "mutation_root",
+        variables = "UpdateMessageTweetedVariables"
+    )]
+    pub struct UpdateMessageTweeted {
+        #[arguments(pk_columns: { id: $id }, _set: { tweeted: true, last_tweeted_at: $last_tweeted_at })]
+        #[cynic(rename = "update_message_by_pk")]
+        pub update_message_by_pk: Option<Message>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "message")]
+    pub struct Message {
+        pub id: Uuid,
+    }
+}
+
+use super::error::{build_errors, HasuraError, NetworkSnafu};
+use snafu::prelude::*;
+use time::OffsetDateTime;
+
+pub async fn exec(
+    message_id: uuid::Uuid,
+    last_tweeted_at: OffsetDateTime,
+) -> Result<uuid::Uuid, HasuraError> {
+    use cynic::MutationBuilder;
+
+    let vars = queries::UpdateMessageTweetedVariables {
+        id: crate::gq::common::scalars::Uuid(message_id),
+        last_tweeted_at: last_tweeted_at.into(),
+    };
+
+    let operation = queries::UpdateMessageTweeted::build(vars);
+
+    let resp = super::common::run_graphql(operation)
+        .await
+        .context(NetworkSnafu)?;
+
+    let id = resp
+        .data
+        .ok_or_else(|| build_errors(resp.errors))?
+        .update_message_by_pk
+        .ok_or(HasuraError::DataNotFound)?
+        .id
+        .0;
+
+    Ok(id)
+}
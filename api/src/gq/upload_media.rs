@@ -1,6 +1,6 @@
 /*
-mutation UploadMedia($id: uuid!, $thumbnail: String!, $user_id: String!) {
-    insert_media_one(object: {id: $id, thumbnail: $thumbnail, user_id: $user_id}) {
+mutation UploadMedia($id: uuid!, $thumbnail: String!, $user_id: String!, $content_type: String, $content_hash: String, $perceptual_hash: String) {
+    insert_media_one(object: {id: $id, thumbnail: $thumbnail, user_id: $user_id, content_type: $content_type, content_hash: $content_hash, perceptual_hash: $perceptual_hash}) {
       id
       thumbnail
       uploaded_at
@@ -18,12 +18,15 @@ mod queries {
         pub id: Uuid,
         pub thumbnail: String,
         pub user_id: String,
+        pub content_type: Option<String>,
+        pub content_hash: Option<String>,
+        pub perceptual_hash: Option<String>,
     }
 
     #[derive(cynic::QueryFragment, Debug)]
     #[cynic(graphql_type = "mutation_root", variables = "UploadMediaVariables")]
     pub struct UploadMedia {
-        #[arguments(object: { id: $id, thumbnail: $thumbnail, user_id: $user_id })]
+        #[arguments(object: { id: $id, thumbnail: $thumbnail, user_id: $user_id, content_type: $content_type, content_hash: $content_hash, perceptual_hash: $perceptual_hash })]
         #[cynic(rename = "insert_media_one")]
         pub insert_media_one: Option<media>,
     }
@@ -47,6 +50,9 @@ pub async fn upload_media(
     user_id: String,
     uuid: uuid::Uuid,
     dataurl: String,
+    content_type: Option<String>,
+    content_hash: Option<String>,
+    perceptual_hash: Option<String>,
 ) -> Result<crate::model::Media, HasuraError> {
     use cynic::MutationBuilder;
 
@@ -54,6 +60,9 @@ pub async fn upload_media(
         id: crate::gq::common::scalars::Uuid(uuid),
         thumbnail: dataurl,
         user_id,
+        content_type,
+        content_hash,
+        perceptual_hash,
     };
 
     let operation = self::queries::UploadMedia::build(input);
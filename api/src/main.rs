@@ -1,20 +1,82 @@
 mod app;
+mod backfill;
 mod config;
 mod error;
+mod feed;
 mod gq;
 mod logger;
-mod minio;
+mod media;
+mod media_cache;
+mod media_dedup;
+mod media_gc;
 mod model;
 mod mpsc;
+mod net;
+mod publisher;
 mod routes;
+mod rss_filter;
 mod scheduler;
+mod selection;
 mod state;
+mod storage;
+mod template;
+mod thumbnail;
 mod twitter;
+mod twitter_text;
+mod watermark;
 
 use std::net::SocketAddr;
 
 #[tokio::main]
 async fn main() {
+    // 次のコマンドは、既存のデータを移行したら終了し、サーバーは起動しない
+    // `migrate-media-store [--dry-run]`: ユーザーごとのバケットを設定したストレージへ移す
+    // `backfill-thumbnails`: 以前のdata URLのサムネイルをストレージに移す
+    // `backfill-media-hashes`: 重複の検出に使うハッシュを、以前にアップロードされたメディアについても計算する
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("migrate-media-store") => {
+            logger::setup();
+
+            let dry_run = args.iter().any(|arg| arg == "--dry-run");
+            match storage::migrate_buckets(dry_run).await {
+                Ok(report) => tracing::info!(
+                    "Migrated media store: {} copied, {} skipped, {} failed",
+                    report.copied,
+                    report.skipped,
+                    report.failed
+                ),
+                Err(err) => tracing::error!("Failed to migrate media store: {:?}", err),
+            }
+            return;
+        }
+        Some("backfill-thumbnails") => {
+            logger::setup();
+
+            match thumbnail::backfill().await {
+                Ok(report) => tracing::info!(
+                    "Moved {} thumbnails to storage, {} failed",
+                    report.done,
+                    report.failed
+                ),
+                Err(err) => tracing::error!("Failed to backfill thumbnails: {:?}", err),
+            }
+            return;
+        }
+        Some("backfill-media-hashes") => {
+            logger::setup();
+
+            match media_dedup::backfill().await {
+                Ok(report) => {
+                    tracing::info!("Hashed {} media, {} failed", report.done, report.failed)
+                }
+                Err(err) => tracing::error!("Failed to backfill media hashes: {:?}", err),
+            }
+            return;
+        }
+        _ => {}
+    }
+
     let (sender, receiver) = tokio::sync::mpsc::channel(8);
 
     let app_state = crate::state::setup_state(sender).await;
@@ -29,6 +91,8 @@ async fn main() {
     //         .await
     // };
 
+    let _media_gc = scheduler::start_media_gc();
+
     let mut actor = mpsc::Actor::new(receiver);
     let feature = tokio::spawn(async move { actor.run().await });
 
@@ -0,0 +1,726 @@
+// 画像の読み込み・サムネイル作成・ツイート用の変換、アップロードされたメディアの検証
+use image::{imageops::FilterType, DynamicImage, ImageFormat, ImageOutputFormat};
+use snafu::prelude::*;
+use std::io::Cursor;
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum Error {
+    GuessFormat {
+        source: std::io::Error,
+    },
+    Decode {
+        source: image::ImageError,
+    },
+    Encode {
+        source: image::ImageError,
+    },
+    #[snafu(display("添付できるメディアは{}件までです（{count}件）", MAX_ATTACHMENTS))]
+    TooManyAttachments {
+        count: usize,
+    },
+    #[snafu(display("GIFアニメや動画は、1件だけで添付する必要があります"))]
+    MixedAttachments,
+    #[snafu(display("ファイルサイズが上限を超えています（{size}/{limit}バイト）"))]
+    TooLarge {
+        size: usize,
+        limit: usize,
+    },
+    #[snafu(display("動画の長さを読み取れません"))]
+    UnknownDuration,
+    #[snafu(display(
+        "動画の長さは{}〜{}秒にしてください（{seconds:.1}秒）",
+        MIN_VIDEO_SECONDS,
+        MAX_VIDEO_SECONDS
+    ))]
+    InvalidDuration {
+        seconds: f64,
+    },
+    #[snafu(display("代替テキストは{}文字までです（{length}文字）", MAX_ALT_TEXT_LENGTH))]
+    AltTextTooLong {
+        length: usize,
+    },
+    #[snafu(display("{content_type}の画像には対応していません。JPEGかPNGに変換してください"))]
+    UnsupportedImage {
+        content_type: String,
+    },
+    #[snafu(display(
+        "{content_type}のファイルはアップロードできません。JPEG、PNG、WebP、GIF、MP4、MOVのいずれかにしてください"
+    ))]
+    UnsupportedContentType {
+        content_type: String,
+    },
+    #[snafu(display("画像を{}バイト以下に縮小できません", MAX_IMAGE_BYTES))]
+    CannotShrink,
+}
+
+pub const THUMBNAIL_SIZE: u32 = 240;
+
+// ストレージに保存するサムネイルの大きさ（一覧用と詳細表示用）
+pub const THUMBNAIL_SIZES: [u32; 2] = [THUMBNAIL_SIZE, 600];
+
+// ツイートに添付する画像の長辺の最大値
+pub const MAX_DIMENSION: u32 = 1200;
+
+// 保存する元画像の長辺の最大値（Twitterはこれより大きい画像を縮小して表示する）
+pub const MAX_ORIGINAL_DIMENSION: u32 = 4096;
+
+// 保存する元画像をJPEGにするときの品質
+const ORIGINAL_JPEG_QUALITY: u8 = 90;
+
+// 1件のツイートに添付できるメディアの数
+pub const MAX_ATTACHMENTS: usize = 4;
+
+// Twitterにアップロードできるファイルサイズと動画の長さ
+pub const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+pub const MAX_GIF_BYTES: usize = 15 * 1024 * 1024;
+pub const MAX_VIDEO_BYTES: usize = 512 * 1024 * 1024;
+pub const MIN_VIDEO_SECONDS: f64 = 0.5;
+
+// 変換前の静止画として受け付けるファイルサイズ（保存時に5MB以下に縮小する）
+pub const MAX_UPLOAD_IMAGE_BYTES: usize = 20 * 1024 * 1024;
+
+// アップロードを受け付けるMIMEタイプ
+pub const ALLOWED_CONTENT_TYPES: [&str; 6] = [
+    "image/jpeg",
+    "image/png",
+    "image/webp",
+    "image/gif",
+    "video/mp4",
+    "video/quicktime",
+];
+
+// libheifフィーチャーを有効にしたときだけ受け付ける（保存時にJPEGかPNGに変換する）
+pub const HEIF_CONTENT_TYPES: [&str; 2] = ["image/heic", "image/heif"];
+pub const MAX_VIDEO_SECONDS: f64 = 140.0;
+
+// 画像の代替テキストの最大文字数
+pub const MAX_ALT_TEXT_LENGTH: usize = 1000;
+
+// 動画はサムネイルを作成できないため、代わりに表示する画像
+pub const VIDEO_THUMBNAIL: &str = "data:image/svg+xml;base64,PHN2ZyB4bWxucz0iaHR0cDovL3d3dy53My5vcmcvMjAwMC9zdmciIHZpZXdCb3g9IjAgMCAyNDAgMjQwIj48cmVjdCB3aWR0aD0iMjQwIiBoZWlnaHQ9IjI0MCIgZmlsbD0iIzMzMyIvPjxwYXRoIGQ9Ik05MCA3MHYxMDBsODAtNTB6IiBmaWxsPSIjZmZmIi8+PC9zdmc+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaKind {
+    Image,
+    Gif,
+    Video,
+}
+
+impl MediaKind {
+    // MIMEタイプが記録されていないメディアは、以前からある静止画として扱う
+    pub fn from_content_type(content_type: Option<&str>) -> Self {
+        match content_type {
+            Some("image/gif") => MediaKind::Gif,
+            Some(content_type) if content_type.starts_with("video/") => MediaKind::Video,
+            _ => MediaKind::Image,
+        }
+    }
+
+    // Twitterの分割アップロードで指定するmedia_category
+    pub fn media_category(&self) -> &'static str {
+        match self {
+            MediaKind::Image => "tweet_image",
+            MediaKind::Gif => "tweet_gif",
+            MediaKind::Video => "tweet_video",
+        }
+    }
+
+    pub fn max_bytes(&self) -> usize {
+        match self {
+            MediaKind::Image => MAX_IMAGE_BYTES,
+            MediaKind::Gif => MAX_GIF_BYTES,
+            MediaKind::Video => MAX_VIDEO_BYTES,
+        }
+    }
+
+    // 静止画は保存時に縮小するので、Twitterの上限より大きなファイルも受け付ける
+    pub fn max_upload_bytes(&self) -> usize {
+        match self {
+            MediaKind::Image => MAX_UPLOAD_IMAGE_BYTES,
+            _ => self.max_bytes(),
+        }
+    }
+}
+
+// MP4として扱うISOBMFFのブランド。AVIFなど、同じ形式の画像を動画と取り違えないようにする
+const MP4_BRANDS: [&[u8]; 12] = [
+    b"isom", b"iso2", b"iso3", b"iso4", b"iso5", b"iso6", b"mp41", b"mp42", b"mmp4", b"avc1",
+    b"M4V ", b"dash",
+];
+
+// ファイルの先頭のバイト列から形式を判定してMIMEタイプを返す
+// HEIC（iPhoneの写真）も同じISOBMFFなので、動画より先に判定する
+pub fn content_type(bytes: &[u8]) -> Result<&'static str, Error> {
+    match bytes.get(4..12) {
+        Some(b"ftypheic" | b"ftypheix" | b"ftyphevc" | b"ftypheim" | b"ftypheis") => {
+            return Ok("image/heic")
+        }
+        Some(b"ftypmif1" | b"ftypmsf1") => return Ok("image/heif"),
+        Some(b"ftypqt  ") => return Ok("video/quicktime"),
+        Some(b"ftypavif" | b"ftypavis") => {
+            return UnsupportedImageSnafu {
+                content_type: "image/avif",
+            }
+            .fail()
+        }
+        Some(brand) if brand.starts_with(b"ftyp") && MP4_BRANDS.contains(&&brand[4..]) => {
+            return Ok("video/mp4")
+        }
+        _ => {}
+    }
+
+    image::guess_format(bytes)
+        .map(mime_type)
+        .context(DecodeSnafu)
+}
+
+// image 0.24.6にはImageFormatからMIMEタイプを得る方法がないので、ここで対応付ける
+fn mime_type(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Jpeg => "image/jpeg",
+        ImageFormat::Png => "image/png",
+        ImageFormat::Gif => "image/gif",
+        ImageFormat::WebP => "image/webp",
+        ImageFormat::Bmp => "image/bmp",
+        ImageFormat::Tiff => "image/tiff",
+        ImageFormat::Ico => "image/x-icon",
+        ImageFormat::Avif => "image/avif",
+        _ => "application/octet-stream",
+    }
+}
+
+// アップロードを受け付ける形式とサイズか確認する
+pub fn validate_upload_request(content_type: &str, size: usize) -> Result<MediaKind, Error> {
+    ensure!(
+        ALLOWED_CONTENT_TYPES.contains(&content_type)
+            || (cfg!(feature = "libheif") && HEIF_CONTENT_TYPES.contains(&content_type)),
+        UnsupportedContentTypeSnafu { content_type }
+    );
+
+    let kind = MediaKind::from_content_type(Some(content_type));
+    ensure!(
+        size <= kind.max_upload_bytes(),
+        TooLargeSnafu {
+            size,
+            limit: kind.max_upload_bytes(),
+        }
+    );
+
+    Ok(kind)
+}
+
+// アップロードされたメディアが、Twitterに投稿できるサイズと長さか確認する
+pub fn validate_upload(bytes: &[u8], kind: MediaKind) -> Result<(), Error> {
+    ensure!(
+        bytes.len() <= kind.max_bytes(),
+        TooLargeSnafu {
+            size: bytes.len(),
+            limit: kind.max_bytes(),
+        }
+    );
+
+    if kind == MediaKind::Video {
+        let seconds = mp4_duration(bytes).context(UnknownDurationSnafu)?;
+        ensure!(
+            (MIN_VIDEO_SECONDS..=MAX_VIDEO_SECONDS).contains(&seconds),
+            InvalidDurationSnafu { seconds }
+        );
+    }
+
+    Ok(())
+}
+
+pub fn validate_alt_text(alt_text: &str) -> Result<(), Error> {
+    let length = alt_text.chars().count();
+    ensure!(
+        length <= MAX_ALT_TEXT_LENGTH,
+        AltTextTooLongSnafu { length }
+    );
+
+    Ok(())
+}
+
+// MP4（QuickTime）のmoov/mvhdボックスから動画の長さ（秒）を読み取る
+pub fn mp4_duration(bytes: &[u8]) -> Option<f64> {
+    let moov = find_box(bytes, b"moov")?;
+    let mvhd = find_box(moov, b"mvhd")?;
+
+    let (timescale, duration) = match mvhd.first()? {
+        0 => (read_u32(mvhd, 12)?, read_u32(mvhd, 16)? as u64),
+        1 => (read_u32(mvhd, 20)?, read_u64(mvhd, 24)?),
+        _ => return None,
+    };
+
+    if timescale == 0 {
+        return None;
+    }
+    Some(duration as f64 / timescale as f64)
+}
+
+// 同じ階層のボックスを順に読み、指定した種類のボックスの中身を返す
+fn find_box<'a>(mut bytes: &'a [u8], box_type: &[u8; 4]) -> Option<&'a [u8]> {
+    while bytes.len() >= 8 {
+        let (header, size) = match read_u32(bytes, 0)? {
+            0 => (8, bytes.len()),
+            1 => (16, usize::try_from(read_u64(bytes, 8)?).ok()?),
+            size => (8, size as usize),
+        };
+        if size < header || size > bytes.len() {
+            return None;
+        }
+
+        if &bytes[4..8] == box_type {
+            return Some(&bytes[header..size]);
+        }
+        bytes = &bytes[size..];
+    }
+
+    None
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(
+        bytes.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+// Twitterの添付ルールを確認する
+// 静止画は4枚まで一緒に添付できるが、GIFアニメや動画は1件だけで添付する
+pub fn validate_attachments(kinds: &[MediaKind]) -> Result<(), Error> {
+    ensure!(
+        kinds.len() <= MAX_ATTACHMENTS,
+        TooManyAttachmentsSnafu { count: kinds.len() }
+    );
+    ensure!(
+        kinds.len() <= 1 || kinds.iter().all(|kind| *kind == MediaKind::Image),
+        MixedAttachmentsSnafu
+    );
+
+    Ok(())
+}
+
+pub fn decode(bytes: &[u8]) -> Result<DynamicImage, Error> {
+    image::io::Reader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .context(GuessFormatSnafu)?
+        .decode()
+        .context(DecodeSnafu)
+}
+
+// HEIC・HEIFをlibheifで読み込む。回転や切り抜きはlibheifがデコード時に反映する
+#[cfg(feature = "libheif")]
+fn decode_heif(bytes: &[u8], content_type: &str) -> Result<DynamicImage, Error> {
+    use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+    let heif_error = |source: libheif_rs::HeifError| {
+        image::ImageError::Decoding(image::error::DecodingError::new(
+            image::error::ImageFormatHint::Name("HEIF".into()),
+            source,
+        ))
+    };
+
+    let context = HeifContext::read_from_bytes(bytes)
+        .map_err(heif_error)
+        .context(DecodeSnafu)?;
+    let handle = context
+        .primary_image_handle()
+        .map_err(heif_error)
+        .context(DecodeSnafu)?;
+    let (chroma, channels) = if handle.has_alpha_channel() {
+        (RgbChroma::Rgba, 4)
+    } else {
+        (RgbChroma::Rgb, 3)
+    };
+    let decoded = LibHeif::new()
+        .decode(&handle, ColorSpace::Rgb(chroma), None)
+        .map_err(heif_error)
+        .context(DecodeSnafu)?;
+
+    let planes = decoded.planes();
+    let plane = planes
+        .interleaved
+        .context(UnsupportedImageSnafu { content_type })?;
+    let row = plane.width as usize * channels;
+    let pixels: Vec<u8> = plane
+        .data
+        .chunks(plane.stride)
+        .take(plane.height as usize)
+        .flat_map(|line| &line[..row])
+        .copied()
+        .collect();
+
+    let image = if channels == 4 {
+        image::RgbaImage::from_raw(plane.width, plane.height, pixels).map(DynamicImage::ImageRgba8)
+    } else {
+        image::RgbImage::from_raw(plane.width, plane.height, pixels).map(DynamicImage::ImageRgb8)
+    };
+    image.context(UnsupportedImageSnafu { content_type })
+}
+
+#[cfg(not(feature = "libheif"))]
+fn decode_heif(_bytes: &[u8], content_type: &str) -> Result<DynamicImage, Error> {
+    UnsupportedImageSnafu { content_type }.fail()
+}
+
+// EXIFのOrientationに従って回転・反転した画像を返す
+pub fn decode_oriented(bytes: &[u8]) -> Result<DynamicImage, Error> {
+    let image = decode(bytes)?;
+
+    Ok(match orientation(bytes) {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    })
+}
+
+// EXIFが無い、または読めない画像は回転しない（1）
+fn orientation(bytes: &[u8]) -> u32 {
+    exif::Reader::new()
+        .read_from_container(&mut Cursor::new(bytes))
+        .ok()
+        .and_then(|exif| {
+            exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0))
+        })
+        .unwrap_or(1)
+}
+
+pub fn encode_jpeg(image: &DynamicImage) -> Result<Vec<u8>, Error> {
+    encode_jpeg_with_quality(image, 85)
+}
+
+fn encode_jpeg_with_quality(image: &DynamicImage, quality: u8) -> Result<Vec<u8>, Error> {
+    let mut bytes: Vec<u8> = Vec::new();
+    DynamicImage::ImageRgb8(image.to_rgb8())
+        .write_to(
+            &mut Cursor::new(&mut bytes),
+            ImageOutputFormat::Jpeg(quality),
+        )
+        .context(EncodeSnafu)?;
+
+    Ok(bytes)
+}
+
+fn encode_png(image: &DynamicImage) -> Result<Vec<u8>, Error> {
+    let mut bytes: Vec<u8> = Vec::new();
+    DynamicImage::ImageRgba8(image.to_rgba8())
+        .write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Png)
+        .context(EncodeSnafu)?;
+
+    Ok(bytes)
+}
+
+pub struct NormalizedImage {
+    pub bytes: Vec<u8>,
+    pub content_type: &'static str,
+    pub image: DynamicImage,
+}
+
+// 保存する元画像を、そのままTwitterに投稿できる形に揃える
+// 向きをEXIFに従って直し、位置情報などのメタデータは再エンコードで取り除く
+pub fn normalize_image(bytes: &[u8]) -> Result<NormalizedImage, Error> {
+    let mut image = match content_type(bytes)? {
+        content_type @ ("image/heic" | "image/heif") => decode_heif(bytes, content_type)?,
+        _ => decode_oriented(bytes)?,
+    };
+    if image.width() > MAX_ORIGINAL_DIMENSION || image.height() > MAX_ORIGINAL_DIMENSION {
+        image = image.resize(
+            MAX_ORIGINAL_DIMENSION,
+            MAX_ORIGINAL_DIMENSION,
+            FilterType::Lanczos3,
+        );
+    }
+
+    encode_normalized(image)
+}
+
+// 透過している画像はPNG、それ以外はJPEGにして、5MBに収まるまで縮小する
+pub fn encode_normalized(mut image: DynamicImage) -> Result<NormalizedImage, Error> {
+    let transparent = image.color().has_alpha() && image.to_rgba8().pixels().any(|p| p[3] < 255);
+
+    loop {
+        let (bytes, content_type) = if transparent {
+            (encode_png(&image)?, "image/png")
+        } else {
+            (
+                encode_jpeg_with_quality(&image, ORIGINAL_JPEG_QUALITY)?,
+                "image/jpeg",
+            )
+        };
+
+        if bytes.len() <= MAX_IMAGE_BYTES {
+            return Ok(NormalizedImage {
+                bytes,
+                content_type,
+                image,
+            });
+        }
+
+        ensure!(
+            image.width().max(image.height()) > THUMBNAIL_SIZE,
+            CannotShrinkSnafu
+        );
+        image = image.resize(
+            image.width() * 3 / 4,
+            image.height() * 3 / 4,
+            FilterType::Lanczos3,
+        );
+    }
+}
+
+// 長辺が指定した大きさに収まるJPEGのサムネイルを作成する
+pub fn thumbnail_jpeg(image: &DynamicImage, size: u32) -> Result<Vec<u8>, Error> {
+    encode_jpeg(&image.thumbnail(size, size))
+}
+
+// 同じファイルのアップロードを見つけるための、内容のSHA-256（16進数）
+pub fn content_hash(bytes: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+// 縮小や再圧縮をした画像でも近い値になるdHash（64ビット）
+// 9x8のグレースケールに縮小し、隣り合う画素の明るさを比べる
+pub fn perceptual_hash(image: &DynamicImage) -> u64 {
+    let small = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+
+    hash
+}
+
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+// 外部から取得した画像を検証し、ツイートに添付できる大きさのJPEGに変換する
+pub fn prepare_for_tweet(bytes: &[u8]) -> Result<Vec<u8>, Error> {
+    let image = decode_oriented(bytes)?;
+
+    let image = if image.width() > MAX_DIMENSION || image.height() > MAX_DIMENSION {
+        image.resize(MAX_DIMENSION, MAX_DIMENSION, FilterType::Lanczos3)
+    } else {
+        image
+    };
+
+    encode_jpeg(&image)
+}
+
+// 添付できるサイズに上限があるサービス向けに、収まるまでJPEGで縮小する
+// 収まっている画像は再エンコードせずにそのまま返す
+pub fn shrink_to_fit(bytes: Vec<u8>, max_bytes: usize) -> Result<Vec<u8>, Error> {
+    if bytes.len() <= max_bytes {
+        return Ok(bytes);
+    }
+
+    let mut image = decode_oriented(&bytes)?;
+    loop {
+        let bytes = encode_jpeg(&image)?;
+        if bytes.len() <= max_bytes {
+            return Ok(bytes);
+        }
+
+        ensure!(
+            image.width().max(image.height()) > THUMBNAIL_SIZE,
+            CannotShrinkSnafu
+        );
+        image = image.resize(
+            image.width() * 3 / 4,
+            image.height() * 3 / 4,
+            FilterType::Lanczos3,
+        );
+    }
+}
+
+#[test]
+fn test_validate_attachments() {
+    use MediaKind::*;
+
+    assert!(validate_attachments(&[]).is_ok());
+    assert!(validate_attachments(&[Image, Image, Image, Image]).is_ok());
+    assert!(validate_attachments(&[Gif]).is_ok());
+    assert!(validate_attachments(&[Video]).is_ok());
+    assert!(matches!(
+        validate_attachments(&[Image, Image, Image, Image, Image]),
+        Err(Error::TooManyAttachments { count: 5 })
+    ));
+    assert!(matches!(
+        validate_attachments(&[Image, Gif]),
+        Err(Error::MixedAttachments)
+    ));
+    assert!(matches!(
+        validate_attachments(&[Video, Video]),
+        Err(Error::MixedAttachments)
+    ));
+}
+
+#[test]
+fn test_validate_upload_request() {
+    assert_eq!(
+        validate_upload_request("image/png", MAX_IMAGE_BYTES + 1).unwrap(),
+        MediaKind::Image
+    );
+    assert_eq!(
+        validate_upload_request("video/mp4", 1024).unwrap(),
+        MediaKind::Video
+    );
+    assert!(matches!(
+        validate_upload_request("image/gif", MAX_GIF_BYTES + 1),
+        Err(Error::TooLarge { .. })
+    ));
+    assert_eq!(
+        validate_upload_request("image/heic", 1024).is_ok(),
+        cfg!(feature = "libheif")
+    );
+    assert!(matches!(
+        validate_upload_request("application/zip", 1024),
+        Err(Error::UnsupportedContentType { .. })
+    ));
+}
+
+#[test]
+fn test_mp4_duration() {
+    fn mp4_box(box_type: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut bytes = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(box_type);
+        bytes.extend_from_slice(body);
+        bytes
+    }
+
+    // version 0のmvhd: timescale 1000、duration 30500（30.5秒）
+    let mut mvhd = vec![0; 12];
+    mvhd.extend_from_slice(&1000u32.to_be_bytes());
+    mvhd.extend_from_slice(&30500u32.to_be_bytes());
+    mvhd.extend_from_slice(&[0; 80]);
+
+    let mut bytes = mp4_box(b"ftyp", b"isom\0\0\0\0isomiso2mp41");
+    bytes.extend(mp4_box(b"free", &[]));
+    bytes.extend(mp4_box(b"moov", &mp4_box(b"mvhd", &mvhd)));
+
+    assert_eq!(content_type(&bytes).unwrap(), "video/mp4");
+    assert_eq!(mp4_duration(&bytes), Some(30.5));
+    assert!(matches!(
+        content_type(&mp4_box(b"ftyp", b"avif\0\0\0\0avifmif1miaf")),
+        Err(Error::UnsupportedImage { .. })
+    ));
+    assert!(content_type(&mp4_box(b"ftyp", b"crx \0\0\0\0crx isom")).is_err());
+    assert!(validate_upload(&bytes, MediaKind::Video).is_ok());
+
+    assert_eq!(mp4_duration(&bytes[..bytes.len() - 10]), None);
+    let large = vec![0; MAX_IMAGE_BYTES + 1];
+    assert!(matches!(
+        validate_upload(&large, MediaKind::Image),
+        Err(Error::TooLarge { .. })
+    ));
+}
+
+#[test]
+fn test_normalize_image() {
+    use image::{GenericImageView, Rgba, RgbaImage};
+
+    // 指定したOrientationのEXIFをJPEGの先頭に差し込む
+    fn with_orientation(jpeg: &[u8], orientation: u16) -> Vec<u8> {
+        let mut tiff = b"MM\0\x2a\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01".to_vec();
+        tiff.extend_from_slice(&orientation.to_be_bytes());
+        tiff.extend_from_slice(&[0; 6]);
+
+        let mut app1 = b"Exif\0\0".to_vec();
+        app1.extend(tiff);
+
+        let mut bytes = jpeg[..2].to_vec();
+        bytes.extend_from_slice(&[0xff, 0xe1]);
+        bytes.extend_from_slice(&((app1.len() + 2) as u16).to_be_bytes());
+        bytes.extend(app1);
+        bytes.extend_from_slice(&jpeg[2..]);
+        bytes
+    }
+
+    // 左上だけ赤い横長の写真
+    let mut photo = RgbaImage::from_pixel(40, 20, Rgba([0, 0, 255, 255]));
+    for x in 0..10 {
+        for y in 0..10 {
+            photo.put_pixel(x, y, Rgba([255, 0, 0, 255]));
+        }
+    }
+    let jpeg = encode_jpeg(&DynamicImage::ImageRgba8(photo)).unwrap();
+    // 6は右に90度回転して表示する
+    let rotated = with_orientation(&jpeg, 6);
+
+    let normalized = normalize_image(&rotated).unwrap();
+    assert_eq!(normalized.content_type, "image/jpeg");
+    assert_eq!(normalized.image.dimensions(), (20, 40));
+    // 回転後は赤い部分が右上に来る
+    assert!(normalized.image.get_pixel(15, 5)[0] > 200);
+    assert_eq!(orientation(&normalized.bytes), 1);
+    assert!(!normalized.bytes.windows(4).any(|window| window == b"Exif"));
+
+    // 透過を含む画像はPNGのまま保存する
+    let mut icon = RgbaImage::from_pixel(8, 8, Rgba([0, 0, 0, 0]));
+    icon.put_pixel(4, 4, Rgba([255, 255, 255, 255]));
+    let png = encode_png(&DynamicImage::ImageRgba8(icon)).unwrap();
+    let normalized = normalize_image(&png).unwrap();
+    assert_eq!(normalized.content_type, "image/png");
+    assert_eq!(normalized.image.get_pixel(0, 0)[3], 0);
+
+    // 不透明なPNGはJPEGにし、大きすぎる画像は縮小する
+    let large = RgbaImage::from_pixel(MAX_ORIGINAL_DIMENSION * 2, 100, Rgba([0, 128, 0, 255]));
+    let png = encode_png(&DynamicImage::ImageRgba8(large)).unwrap();
+    let normalized = normalize_image(&png).unwrap();
+    assert_eq!(normalized.content_type, "image/jpeg");
+    assert_eq!(normalized.image.width(), MAX_ORIGINAL_DIMENSION);
+
+    let heic = b"\0\0\0\x18ftypheic\0\0\0\0mif1heic";
+    assert_eq!(content_type(heic).unwrap(), "image/heic");
+    if cfg!(feature = "libheif") {
+        assert!(matches!(normalize_image(heic), Err(Error::Decode { .. })));
+    } else {
+        assert!(matches!(
+            normalize_image(heic),
+            Err(Error::UnsupportedImage { .. })
+        ));
+    }
+}
+
+#[test]
+fn test_perceptual_hash() {
+    let gradient = DynamicImage::ImageRgb8(image::RgbImage::from_fn(320, 240, |x, y| {
+        image::Rgb([(x * 255 / 320) as u8, (y * 255 / 240) as u8, 128])
+    }));
+    let checker = DynamicImage::ImageRgb8(image::RgbImage::from_fn(320, 240, |x, y| {
+        if (x / 40 + y / 40) % 2 == 0 {
+            image::Rgb([255, 255, 255])
+        } else {
+            image::Rgb([0, 0, 0])
+        }
+    }));
+
+    // 縮小して再圧縮しても、ほとんど同じハッシュになる
+    let resized = decode(&encode_jpeg(&gradient.thumbnail(160, 160)).unwrap()).unwrap();
+    assert!(hamming_distance(perceptual_hash(&gradient), perceptual_hash(&resized)) <= 4);
+    assert!(hamming_distance(perceptual_hash(&gradient), perceptual_hash(&checker)) > 10);
+
+    assert_eq!(content_hash(b"still bott"), content_hash(b"still bott"));
+    assert_ne!(content_hash(b"still bott"), content_hash(b"still bott!"));
+    assert_eq!(content_hash(b"").len(), 64);
+}
@@ -0,0 +1,130 @@
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// アップロード済みのメディアIDを、有効期限内なら次の投稿でも使い回す
+// 期限ぎりぎりのIDは投稿までに切れるかもしれないので、余裕を持って捨てる
+const EXPIRY_MARGIN: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone)]
+struct Entry {
+    twitter_media_id: String,
+    alt_text: Option<String>,
+    expires_at: Instant,
+}
+
+// (メディアのUUID, 投稿するアカウント) → TwitterのメディアID
+static CACHE: Lazy<Mutex<HashMap<(uuid::Uuid, String), Entry>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cached {
+    pub twitter_media_id: String,
+    pub alt_text: Option<String>,
+}
+
+pub fn get(media_id: uuid::Uuid, account: &str) -> Option<Cached> {
+    get_at(media_id, account, Instant::now())
+}
+
+fn get_at(media_id: uuid::Uuid, account: &str, now: Instant) -> Option<Cached> {
+    let mut cache = CACHE.lock().unwrap();
+    let key = (media_id, account.to_string());
+
+    match cache.get(&key) {
+        Some(entry) if entry.expires_at > now + EXPIRY_MARGIN => Some(Cached {
+            twitter_media_id: entry.twitter_media_id.clone(),
+            alt_text: entry.alt_text.clone(),
+        }),
+        Some(_) => {
+            cache.remove(&key);
+            None
+        }
+        None => None,
+    }
+}
+
+// 有効期限が返ってこなかったメディアはキャッシュしない
+pub fn insert(
+    media_id: uuid::Uuid,
+    account: &str,
+    twitter_media_id: String,
+    alt_text: Option<String>,
+    expires_after_secs: Option<u64>,
+) {
+    let expires_after_secs = match expires_after_secs {
+        Some(expires_after_secs) => expires_after_secs,
+        None => return,
+    };
+
+    let mut cache = CACHE.lock().unwrap();
+    let now = Instant::now();
+
+    cache.retain(|_, entry| entry.expires_at > now);
+    cache.insert(
+        (media_id, account.to_string()),
+        Entry {
+            twitter_media_id,
+            alt_text,
+            expires_at: now + Duration::from_secs(expires_after_secs),
+        },
+    );
+}
+
+// キャッシュしたIDに、後から設定し直した代替テキストを記録する
+pub fn set_alt_text(media_id: uuid::Uuid, account: &str, alt_text: Option<String>) {
+    if let Some(entry) = CACHE
+        .lock()
+        .unwrap()
+        .get_mut(&(media_id, account.to_string()))
+    {
+        entry.alt_text = alt_text;
+    }
+}
+
+// メディアが削除されたら、どのアカウントのキャッシュも使わない
+pub fn invalidate(media_id: uuid::Uuid) {
+    CACHE
+        .lock()
+        .unwrap()
+        .retain(|(cached_media_id, _), _| *cached_media_id != media_id);
+}
+
+// 透かしの設定が変わったら、そのアカウントで以前の設定のままアップロードしたIDは使わない
+pub fn invalidate_account(account: &str) {
+    CACHE
+        .lock()
+        .unwrap()
+        .retain(|(_, cached_account), _| cached_account != account);
+}
+
+#[test]
+fn test_media_cache() {
+    let media_id = uuid::Uuid::new_v4();
+    let account = "751625934894084097";
+
+    insert(media_id, account, "1".to_string(), None, None);
+    assert_eq!(get(media_id, account), None);
+
+    insert(media_id, account, "2".to_string(), None, Some(86400));
+    assert_eq!(
+        get(media_id, account).map(|cached| cached.twitter_media_id),
+        Some("2".to_string())
+    );
+    assert_eq!(get(media_id, "other"), None);
+
+    // 期限が近づいたIDは使わない
+    let later = Instant::now() + Duration::from_secs(86400) - EXPIRY_MARGIN;
+    assert_eq!(get_at(media_id, account, later), None);
+
+    insert(media_id, account, "3".to_string(), None, Some(86400));
+    invalidate(media_id);
+    assert_eq!(get(media_id, account), None);
+
+    insert(media_id, account, "4".to_string(), None, Some(86400));
+    insert(media_id, "other", "5".to_string(), None, Some(86400));
+    invalidate_account(account);
+    assert_eq!(get(media_id, account), None);
+    assert!(get(media_id, "other").is_some());
+}
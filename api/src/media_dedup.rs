@@ -0,0 +1,159 @@
+// 同じファイルや似ている画像を見つけるためのハッシュを管理する
+// 同じファイルはsaveMediaで既存のメディアにまとめ、似ている画像は整理のために一覧する
+use crate::storage::store;
+use snafu::prelude::*;
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum Error {
+    Storage {
+        source: crate::storage::Error,
+    },
+    Media {
+        source: crate::media::Error,
+    },
+    Hasura {
+        source: crate::gq::error::HasuraError,
+    },
+}
+
+// 似ているとみなすdHashの距離の既定値（64ビット中）
+pub const DEFAULT_MAX_DISTANCE: u32 = 10;
+
+pub fn format_perceptual_hash(hash: u64) -> String {
+    format!("{:016x}", hash)
+}
+
+pub fn parse_perceptual_hash(hash: &str) -> Option<u64> {
+    u64::from_str_radix(hash, 16).ok()
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SimilarPair {
+    pub media_id: uuid::Uuid,
+    pub similar_media_id: uuid::Uuid,
+    pub distance: u32,
+}
+
+// dHashの距離がmax_distance以下の組み合わせを、近い順に返す
+// targetを指定したときは、そのメディアと似ているものだけを返す
+pub fn similar_pairs(
+    hashes: &[(uuid::Uuid, u64)],
+    target: Option<uuid::Uuid>,
+    max_distance: u32,
+) -> Vec<SimilarPair> {
+    let mut pairs = Vec::new();
+
+    for (i, (a, a_hash)) in hashes.iter().enumerate() {
+        for (b, b_hash) in &hashes[i + 1..] {
+            let (media_id, similar_media_id) = match target {
+                Some(target) if target == *a => (*a, *b),
+                Some(target) if target == *b => (*b, *a),
+                Some(_) => continue,
+                None => (*a, *b),
+            };
+
+            let distance = crate::media::hamming_distance(*a_hash, *b_hash);
+            if distance <= max_distance {
+                pairs.push(SimilarPair {
+                    media_id,
+                    similar_media_id,
+                    distance,
+                });
+            }
+        }
+    }
+
+    pairs.sort_by_key(|pair| pair.distance);
+    pairs
+}
+
+// ハッシュを保存する前にアップロードされたメディアについて、保存済みのファイルから計算する
+// `backfill-media-hashes`コマンドで実行する。計算できなかったメディアはハッシュのないまま残る
+pub async fn backfill() -> Result<crate::backfill::Report, Error> {
+    crate::backfill::run(
+        |limit, skip| async move {
+            crate::gq::list_unhashed_media::exec(limit, skip)
+                .await
+                .map(|media| {
+                    media
+                        .into_iter()
+                        .map(|(media_id, user_id, content_type)| {
+                            (media_id, (user_id, content_type))
+                        })
+                        .collect()
+                })
+        },
+        |media_id, (user_id, content_type): (String, Option<String>)| async move {
+            backfill_one(media_id, &user_id, content_type.as_deref()).await
+        },
+    )
+    .await
+    .context(HasuraSnafu)
+}
+
+async fn backfill_one(
+    media_id: uuid::Uuid,
+    user_id: &str,
+    content_type: Option<&str>,
+) -> Result<(), Error> {
+    let bytes = store()
+        .get(user_id, &media_id.to_string())
+        .await
+        .context(StorageSnafu)?;
+
+    let perceptual_hash = match crate::media::MediaKind::from_content_type(content_type) {
+        crate::media::MediaKind::Video => None,
+        _ => {
+            let image = crate::media::decode_oriented(&bytes).context(MediaSnafu)?;
+            Some(format_perceptual_hash(crate::media::perceptual_hash(
+                &image,
+            )))
+        }
+    };
+
+    crate::gq::update_media_hashes::exec(
+        media_id,
+        crate::media::content_hash(&bytes),
+        perceptual_hash,
+    )
+    .await
+    .context(HasuraSnafu)
+}
+
+#[test]
+fn test_similar_pairs() {
+    let a = uuid::Uuid::new_v4();
+    let b = uuid::Uuid::new_v4();
+    let c = uuid::Uuid::new_v4();
+    let hashes = vec![(a, 0b1111), (b, 0b0111), (c, u64::MAX)];
+
+    assert_eq!(
+        similar_pairs(&hashes, None, DEFAULT_MAX_DISTANCE),
+        vec![SimilarPair {
+            media_id: a,
+            similar_media_id: b,
+            distance: 1,
+        }]
+    );
+    assert_eq!(
+        similar_pairs(&hashes, Some(b), 64),
+        vec![
+            SimilarPair {
+                media_id: b,
+                similar_media_id: a,
+                distance: 1,
+            },
+            SimilarPair {
+                media_id: b,
+                similar_media_id: c,
+                distance: 61,
+            },
+        ]
+    );
+    assert!(similar_pairs(&hashes, Some(c), 0).is_empty());
+
+    assert_eq!(format_perceptual_hash(0xff), "00000000000000ff");
+    assert_eq!(parse_perceptual_hash("00000000000000ff"), Some(0xff));
+    assert_eq!(parse_perceptual_hash("not a hash"), None);
+}
@@ -0,0 +1,209 @@
+// ユーザーごとに保存したオブジェクトとmediaテーブルを突き合わせ、どのメディアからも参照されないオブジェクトを削除する
+// saveMediaが呼ばれなかったアップロードや、delete_imageで削除に失敗したオブジェクトが対象
+use crate::storage::{store, StoredObject};
+use snafu::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum Error {
+    Hasura {
+        source: crate::gq::error::HasuraError,
+    },
+    Storage {
+        source: crate::storage::Error,
+    },
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Plan {
+    // 削除するオブジェクトのキー
+    pub orphaned: Vec<String>,
+    // mediaテーブルにあるのに、オリジナルのオブジェクトが無いメディア
+    pub missing: Vec<uuid::Uuid>,
+}
+
+#[derive(Debug)]
+pub struct Report {
+    pub user_id: String,
+    pub plan: Plan,
+}
+
+// オリジナルは`{media_id}`、サムネイルは`thumbnails/{media_id}/{size}.jpg`に保存されている
+fn media_id_of(key: &str) -> Option<uuid::Uuid> {
+    let id = match key.strip_prefix("thumbnails/") {
+        Some(rest) => rest.split('/').next()?,
+        None => key,
+    };
+
+    uuid::Uuid::parse_str(id).ok()
+}
+
+// アップロード直後でまだsaveMediaが呼ばれていないオブジェクトを消さないように、
+// 猶予期間より新しいオブジェクトや、更新日時が分からないオブジェクトは残す
+// 形式の分からないキーも、このアプリが保存したものとは限らないので残す
+pub fn plan(
+    objects: &[StoredObject],
+    media_ids: &HashSet<uuid::Uuid>,
+    now: time::OffsetDateTime,
+    grace: time::Duration,
+) -> Plan {
+    let orphaned = objects
+        .iter()
+        .filter(|object| {
+            media_id_of(&object.key).is_some_and(|media_id| !media_ids.contains(&media_id))
+        })
+        .filter(|object| {
+            object
+                .last_modified
+                .is_some_and(|last_modified| now - last_modified > grace)
+        })
+        .map(|object| object.key.clone())
+        .collect();
+
+    let originals: HashSet<&str> = objects.iter().map(|object| object.key.as_str()).collect();
+    let mut missing: Vec<uuid::Uuid> = media_ids
+        .iter()
+        .filter(|media_id| !originals.contains(media_id.to_string().as_str()))
+        .copied()
+        .collect();
+    missing.sort();
+
+    Plan { orphaned, missing }
+}
+
+// すべてのユーザーのオブジェクトを確認する
+// dry_runのときは削除せずに、削除する予定のオブジェクトを記録するだけにする
+pub async fn run(dry_run: bool, grace: time::Duration) -> Result<Vec<Report>, Error> {
+    let mut media_ids: HashMap<String, HashSet<uuid::Uuid>> = HashMap::new();
+    for (media_id, user_id) in crate::gq::list_media_owners::exec()
+        .await
+        .context(HasuraSnafu)?
+    {
+        media_ids.entry(user_id).or_default().insert(media_id);
+    }
+
+    let user_ids = crate::gq::list_user_ids::exec()
+        .await
+        .context(HasuraSnafu)?;
+
+    let mut reports = Vec::new();
+    for user_id in user_ids {
+        let media_ids = media_ids.remove(&user_id).unwrap_or_default();
+
+        match collect_user(&user_id, &media_ids, dry_run, grace).await {
+            Ok(Some(report)) => reports.push(report),
+            Ok(None) => {}
+            Err(err) => tracing::error!("Failed to collect media of {}: {:?}", user_id, err),
+        }
+    }
+
+    Ok(reports)
+}
+
+async fn collect_user(
+    user_id: &str,
+    media_ids: &HashSet<uuid::Uuid>,
+    dry_run: bool,
+    grace: time::Duration,
+) -> Result<Option<Report>, Error> {
+    // アップロードしたことのないユーザーは確認しない
+    let objects = store().list(user_id).await.context(StorageSnafu)?;
+    if objects.is_empty() {
+        return Ok(None);
+    }
+
+    let plan = plan(&objects, media_ids, time::OffsetDateTime::now_utc(), grace);
+
+    for media_id in &plan.missing {
+        tracing::warn!(
+            "Object of media {} is missing in the storage of {}",
+            media_id,
+            user_id
+        );
+    }
+
+    for key in &plan.orphaned {
+        if dry_run {
+            tracing::info!("[dry run] Would delete orphaned object {}/{}", user_id, key);
+        } else if let Err(err) = store().delete(user_id, key).await {
+            tracing::warn!(
+                "Failed to delete orphaned object {}/{}: {}",
+                user_id,
+                key,
+                err
+            );
+        } else {
+            tracing::info!("Deleted orphaned object {}/{}", user_id, key);
+        }
+    }
+
+    Ok(Some(Report {
+        user_id: user_id.to_string(),
+        plan,
+    }))
+}
+
+#[test]
+fn test_plan() {
+    use time::macros::datetime;
+
+    let now = datetime!(2023-09-01 12:00 UTC);
+    let old = Some(datetime!(2023-08-30 12:00 UTC));
+    let saved = uuid::Uuid::new_v4();
+    let orphaned = uuid::Uuid::new_v4();
+    let uploading = uuid::Uuid::new_v4();
+    let missing = uuid::Uuid::new_v4();
+
+    let objects = vec![
+        StoredObject {
+            key: saved.to_string(),
+            size: 0,
+            last_modified: old,
+        },
+        StoredObject {
+            key: format!("thumbnails/{}/240.jpg", saved),
+            size: 0,
+            last_modified: old,
+        },
+        StoredObject {
+            key: orphaned.to_string(),
+            size: 0,
+            last_modified: old,
+        },
+        StoredObject {
+            key: format!("thumbnails/{}/600.jpg", orphaned),
+            size: 0,
+            last_modified: old,
+        },
+        // 猶予期間内のアップロード
+        StoredObject {
+            key: uploading.to_string(),
+            size: 0,
+            last_modified: Some(datetime!(2023-09-01 11:30 UTC)),
+        },
+        // 更新日時が分からないオブジェクトと、形式の分からないキー
+        StoredObject {
+            key: uuid::Uuid::new_v4().to_string(),
+            size: 0,
+            last_modified: None,
+        },
+        StoredObject {
+            key: "backup.tar".to_string(),
+            size: 0,
+            last_modified: old,
+        },
+    ];
+    let media_ids = HashSet::from([saved, missing]);
+
+    assert_eq!(
+        plan(&objects, &media_ids, now, time::Duration::hours(24)),
+        Plan {
+            orphaned: vec![
+                orphaned.to_string(),
+                format!("thumbnails/{}/600.jpg", orphaned)
+            ],
+            missing: vec![missing],
+        }
+    );
+}
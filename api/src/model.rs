@@ -1,10 +1,11 @@
-use crate::error::{Error, TwitterSnafu};
+use crate::error::{Error, PublisherSnafu};
+use crate::publisher::{Post, PostOptions, Publisher};
+use crate::selection::Strategy;
 use derive_new::new;
 use random_number::random;
 use serde::{Deserialize, Serialize};
 use snafu::prelude::*;
-use std::future::Future;
-use strum_macros::Display;
+use strum_macros::{Display, EnumString};
 use time::OffsetDateTime;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -45,12 +46,16 @@ pub enum TweetJson {
 #[derive(Serialize, Debug)]
 pub struct Tweet {
     text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply: Option<Reply>,
 }
 
 #[derive(Serialize, Debug)]
 pub struct TweetWithMedia {
     text: String,
     media: MediaIds,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply: Option<Reply>,
 }
 
 #[derive(Serialize, Debug)]
@@ -58,6 +63,37 @@ pub struct MediaIds {
     media_ids: Vec<String>,
 }
 
+#[derive(Serialize, Debug)]
+pub struct Reply {
+    in_reply_to_tweet_id: String,
+}
+
+impl TweetJson {
+    pub fn new(text: String, media_ids: Vec<String>) -> Self {
+        if media_ids.is_empty() {
+            TweetJson::Tweet(Tweet { text, reply: None })
+        } else {
+            TweetJson::TweetWithMedia(TweetWithMedia {
+                text,
+                media: MediaIds { media_ids },
+                reply: None,
+            })
+        }
+    }
+
+    // スレッドの2件目以降は、直前のツイートへの返信として投稿する
+    pub fn reply_to(mut self, tweet_id: String) -> Self {
+        let reply = Some(Reply {
+            in_reply_to_tweet_id: tweet_id,
+        });
+        match &mut self {
+            TweetJson::Tweet(tweet) => tweet.reply = reply,
+            TweetJson::TweetWithMedia(tweet) => tweet.reply = reply,
+        }
+        self
+    }
+}
+
 //
 // Hasura上に登録されているユーザーを表す
 //
@@ -73,9 +109,10 @@ pub struct HasuraUser {
     pub email_confirmed_at: Option<time::OffsetDateTime>,
     pub email_confirm_code: Option<String>,
     pub email_confirm_code_issued_at: Option<time::OffsetDateTime>,
+    pub require_alt_text: bool,
 }
 
-#[derive(Serialize, Debug, Deserialize, Clone, Display)]
+#[derive(Serialize, Debug, Deserialize, Clone, Display, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 
 pub enum Role {
@@ -90,6 +127,7 @@ pub enum Role {
 #[derive(Debug, new, Clone)]
 pub struct ActiveUser {
     pub user_id: String,
+    pub role: Role,
     pub tasks: Vec<EnabledTask>,
     pub rss_tasks: Vec<RssTask>,
 }
@@ -99,49 +137,401 @@ pub struct Message {
     id: uuid::Uuid,
     user_id: String,
     text: String,
-    media_id: Option<uuid::Uuid>,
+    // 添付するメディア（positionの順）
+    media: Vec<MessageMedia>,
     priority: i32,
     created_at: time::OffsetDateTime,
     updated_at: time::OffsetDateTime,
+    // ローテーションの今のサイクルで投稿済みかどうか
+    tweeted: bool,
+    last_tweeted_at: Option<time::OffsetDateTime>,
+    thread_retry: ThreadRetry,
+    // 投稿途中のスレッドの最初のツイートのID
+    thread_tweet_id: Option<String>,
+    // スレッドの2件目以降。空の場合は1件のツイートとして投稿する
+    parts: Vec<MessagePart>,
+}
+
+// メッセージに添付するメディア
+#[derive(Deserialize, Serialize, Debug, new, Clone)]
+pub struct MessageMedia {
+    pub media_id: uuid::Uuid,
+    pub position: i32,
+    pub content_type: Option<String>,
+    pub alt_text: Option<String>,
+}
+
+impl MessageMedia {
+    pub fn has_alt_text(&self) -> bool {
+        self.alt_text
+            .as_deref()
+            .is_some_and(|alt_text| !alt_text.trim().is_empty())
+    }
+}
+
+// スレッドの2件目以降の投稿
+#[derive(Deserialize, Serialize, Debug, new, Clone)]
+pub struct MessagePart {
+    pub id: uuid::Uuid,
+    pub position: i32,
+    pub text: String,
+    pub media_id: Option<uuid::Uuid>,
+    pub alt_text: Option<String>,
+    // 投稿済みの場合はツイートのID
+    pub tweet_id: Option<String>,
+}
+
+impl MessagePart {
+    fn media(&self) -> Vec<MessageMedia> {
+        self.media_id
+            .map(|media_id| MessageMedia::new(media_id, 0, None, self.alt_text.clone()))
+            .into_iter()
+            .collect()
+    }
+}
+
+// Hasuraに記録されているスレッドの投稿状況
+#[derive(Debug, new, Clone)]
+pub struct MessageThread {
+    pub thread_tweet_id: Option<String>,
+    pub parts: Vec<MessagePart>,
+}
+
+// スレッドの投稿が途中で失敗した後、次回の実行でどうするか
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Default, Display, EnumString, Deserialize, Serialize,
+)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ThreadRetry {
+    // 投稿済みの続きから投稿する
+    #[default]
+    Resume,
+    // 投稿済みのツイートを削除して、最初から投稿し直す
+    Cleanup,
+}
+
+impl crate::selection::Candidate for Message {
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    fn created_at(&self) -> OffsetDateTime {
+        self.created_at
+    }
+
+    fn tweeted(&self) -> bool {
+        self.tweeted
+    }
+
+    fn last_tweeted_at(&self) -> Option<OffsetDateTime> {
+        self.last_tweeted_at
+    }
+}
+
+// Hasuraに記録されているメッセージのローテーションの状態
+#[derive(Debug, new, Clone)]
+pub struct MessageRotation {
+    pub message_id: uuid::Uuid,
+    pub tweeted: bool,
+    pub last_tweeted_at: Option<OffsetDateTime>,
 }
 
 impl Message {
-    pub async fn tweet<F, Fut>(&self, token: Token, send_tweet: F) -> Result<String, Error>
-    where
-        F: FnOnce(Token, serde_json::Value) -> Fut,
-        Fut: Future<Output = Result<String, crate::twitter::Error>>,
-    {
+    // 投稿済みのメッセージはHasuraから読み込むので、直接作るのはテストだけ
+    #[cfg(test)]
+    pub fn tweeted_at(mut self, tweeted_at: OffsetDateTime) -> Self {
+        self.tweeted = true;
+        self.last_tweeted_at = Some(tweeted_at);
+        self
+    }
+
+    pub fn reset_rotation(mut self) -> Self {
+        self.tweeted = false;
+        self
+    }
+
+    // 投稿した日時を記録し、ローテーションの今のサイクルで投稿済みにする
+    async fn record_tweeted(&self) {
+        if let Err(err) =
+            crate::gq::update_message_tweeted::exec(self.id, OffsetDateTime::now_utc()).await
+        {
+            tracing::warn!("Ignoring error on updating message tweeted: {}", err);
+        }
+    }
+
+    pub fn sorted_media(&self) -> Vec<MessageMedia> {
+        let mut media = self.media.clone();
+        media.sort_by_key(|media| media.position);
+        media
+    }
+
+    fn media_kinds(&self) -> Vec<crate::media::MediaKind> {
+        self.media
+            .iter()
+            .map(|media| crate::media::MediaKind::from_content_type(media.content_type.as_deref()))
+            .collect()
+    }
+
+    // 添付するメディアの数や組み合わせ、代替テキストの長さが、Twitterのルールに合っているか確認する
+    pub fn validate_media(&self) -> Result<(), Error> {
+        crate::media::validate_attachments(&self.media_kinds())
+            .context(crate::error::MediaSnafu)?;
+
+        self.media
+            .iter()
+            .cloned()
+            .chain(self.parts.iter().flat_map(|part| part.media()))
+            .filter_map(|media| media.alt_text)
+            .try_for_each(|alt_text| crate::media::validate_alt_text(&alt_text))
+            .context(crate::error::MediaSnafu)
+    }
+
+    // 添付したすべての画像（スレッドの2件目以降を含む）に代替テキストがあるかどうか
+    pub fn has_alt_text(&self) -> bool {
+        self.media
+            .iter()
+            .cloned()
+            .chain(self.parts.iter().flat_map(|part| part.media()))
+            .all(|media| media.has_alt_text())
+    }
+
+    pub fn has_thread(&self) -> bool {
+        !self.parts.is_empty()
+    }
+
+    // スレッドの投稿状況はスケジュールの開始後も変わるため、投稿の直前にHasuraから読み込む
+    async fn load_thread(mut self) -> Self {
+        match crate::gq::get_message_thread::exec(self.id).await {
+            Ok(thread) => {
+                self.thread_tweet_id = thread.thread_tweet_id;
+                self.parts = thread.parts;
+            }
+            Err(err) => tracing::warn!("Ignoring error on loading message thread: {}", err),
+        }
+        self.parts.sort_by_key(|part| part.position);
+        self
+    }
+
+    pub async fn tweet(&self, publisher: &dyn Publisher) -> Result<String, Error> {
         tracing::debug!("{:?}", self);
 
-        let json = match self.media_id {
-            Some(media_id) => {
-                let bucket_name = self.user_id.to_string();
+        publisher.validate(&self.text)?;
+        self.validate_media()?;
+        publisher.validate_media(&self.media_kinds())?;
 
-                let bucket = crate::minio::get_or_create_bucket(&bucket_name)
-                    .await
-                    .context(crate::error::MinioSnafu)?;
+        if !self.has_thread() {
+            return self
+                .post(publisher, &self.text, &self.sorted_media(), None)
+                .await;
+        }
 
-                let media_id =
-                    crate::twitter::twitter_v1_media_upload(self.user_id.clone(), media_id, bucket)
-                        .await
-                        .context(crate::error::TwitterSnafu)?;
+        let message = self.clone().load_thread().await;
+        for part in message.parts.iter() {
+            publisher.validate(&part.text)?;
+        }
 
-                TweetJson::TweetWithMedia(TweetWithMedia {
-                    text: self.text.clone(),
-                    media: MediaIds {
-                        media_ids: vec![media_id.to_string()],
-                    },
-                })
+        message.tweet_thread(publisher).await
+    }
+
+    // 1件目のツイートのあとに、2件目以降を直前のツイートへの返信として順に投稿する
+    // 途中で失敗した場合は、投稿済みのツイートのIDを記録して止める
+    async fn tweet_thread(mut self, publisher: &dyn Publisher) -> Result<String, Error> {
+        if self.thread_tweet_id.is_some() && self.thread_retry == ThreadRetry::Cleanup {
+            self.cleanup_thread(publisher).await;
+        }
+
+        let total = self.parts.len() + 1;
+
+        let thread_tweet_id = match self.thread_tweet_id.clone() {
+            Some(thread_tweet_id) => {
+                tracing::info!(
+                    "投稿途中のスレッドの続きから投稿します: {}",
+                    thread_tweet_id
+                );
+                thread_tweet_id
+            }
+            None => {
+                let thread_tweet_id = self
+                    .post(publisher, &self.text, &self.sorted_media(), None)
+                    .await?;
+
+                if let Err(err) =
+                    crate::gq::update_message_thread::exec(self.id, thread_tweet_id.clone()).await
+                {
+                    tracing::warn!("Ignoring error on updating message thread: {}", err);
+                }
+                thread_tweet_id
+            }
+        };
+
+        let mut reply_to = thread_tweet_id.clone();
+        for (index, part) in self.parts.iter().enumerate() {
+            if let Some(tweet_id) = &part.tweet_id {
+                reply_to = tweet_id.clone();
+                continue;
+            }
+
+            let tweet_id = self
+                .post(publisher, &part.text, &part.media(), Some(reply_to))
+                .await
+                .context(crate::error::ThreadInterruptedSnafu {
+                    posted: index + 1,
+                    total,
+                })?;
+
+            if let Err(err) =
+                crate::gq::update_message_part_tweet::exec(part.id, tweet_id.clone()).await
+            {
+                tracing::warn!("Ignoring error on updating message part tweet: {}", err);
+            }
+            reply_to = tweet_id;
+        }
+
+        // 最後まで投稿できたら、次回は新しいスレッドとして投稿する
+        if let Err(err) = crate::gq::reset_message_thread::exec(self.id).await {
+            tracing::warn!("Ignoring error on resetting message thread: {}", err);
+        }
+
+        Ok(thread_tweet_id)
+    }
+
+    // 投稿途中のスレッドのツイートを、後ろから順に削除する
+    // すでに削除されているツイートもあるため、削除の失敗は無視する
+    async fn cleanup_thread(&mut self, publisher: &dyn Publisher) {
+        tracing::info!("投稿途中のスレッドを削除して、最初から投稿し直します");
+
+        let tweet_ids: Vec<String> = self
+            .thread_tweet_id
+            .take()
+            .into_iter()
+            .chain(
+                self.parts
+                    .iter_mut()
+                    .filter_map(|part| part.tweet_id.take()),
+            )
+            .collect();
+
+        for tweet_id in tweet_ids.into_iter().rev() {
+            if let Err(err) = publisher.delete(&tweet_id).await {
+                tracing::warn!("Ignoring error on deleting tweet: {}", err);
+            }
+        }
+
+        if let Err(err) = crate::gq::reset_message_thread::exec(self.id).await {
+            tracing::warn!("Ignoring error on resetting message thread: {}", err);
+        }
+    }
+
+    async fn post(
+        &self,
+        publisher: &dyn Publisher,
+        text: &str,
+        media: &[MessageMedia],
+        reply_to: Option<String>,
+    ) -> Result<String, Error> {
+        // 透かしを設定していれば、アップロードする画像に重ねる
+        let watermark = if media.is_empty() {
+            None
+        } else {
+            crate::watermark::load(&self.user_id)
+                .await
+                .context(crate::error::WatermarkSnafu)?
+        };
+
+        // 複数のメディアは同時にアップロードし、順番どおりに添付する
+        let media_ids = futures::future::try_join_all(
+            media
+                .iter()
+                .map(|media| self.upload_media(publisher, media, watermark.as_ref())),
+        )
+        .await?;
+
+        publisher
+            .post(Post {
+                text: text.to_string(),
+                media_ids,
+                reply_to,
+                card: None,
+            })
+            .await
+            .context(PublisherSnafu)
+    }
+
+    async fn upload_media(
+        &self,
+        publisher: &dyn Publisher,
+        media: &MessageMedia,
+        watermark: Option<&crate::watermark::Watermark>,
+    ) -> Result<String, Error> {
+        let alt_text = media.alt_text.clone().filter(|_| media.has_alt_text());
+
+        // 有効期限内にアップロード済みなら、ストレージから取り直さずにIDを使い回す
+        // 代替テキストを消した場合は、古い代替テキストが付いたIDを使わずにアップロードし直す
+        // TwitterのメディアIDはアップロードしたアカウントでしか使えないので、アカウントごとにキャッシュする
+        let cached = crate::media_cache::get(media.media_id, publisher.account_id())
+            .filter(|_| publisher.reuses_media())
+            .filter(|cached| alt_text.is_some() || cached.alt_text.is_none());
+
+        if let Some(cached) = cached {
+            if let Some(text) = alt_text.as_deref().filter(|_| cached.alt_text != alt_text) {
+                publisher
+                    .describe_media(&cached.twitter_media_id, text)
+                    .await
+                    .context(PublisherSnafu)?;
+
+                crate::media_cache::set_alt_text(
+                    media.media_id,
+                    publisher.account_id(),
+                    alt_text.clone(),
+                );
             }
 
-            None => TweetJson::Tweet(Tweet {
-                text: self.text.clone(),
-            }),
+            return Ok(cached.twitter_media_id);
+        }
+
+        let bytes = crate::storage::store()
+            .get(&self.user_id, &media.media_id.to_string())
+            .await
+            .context(crate::error::StorageSnafu)?;
+        let bytes = match watermark {
+            Some(watermark) => watermark
+                .stamp(bytes)
+                .context(crate::error::WatermarkSnafu)?,
+            None => bytes,
         };
 
-        send_tweet(token, serde_json::to_value(&json).unwrap())
+        let uploaded = publisher
+            .upload_media(bytes, media.media_id.to_string(), alt_text.as_deref())
             .await
-            .context(TwitterSnafu)
+            .context(PublisherSnafu)?;
+
+        if publisher.reuses_media() {
+            crate::media_cache::insert(
+                media.media_id,
+                publisher.account_id(),
+                uploaded.id.clone(),
+                alt_text,
+                uploaded.expires_after_secs,
+            );
+        }
+
+        Ok(uploaded.id)
+    }
+}
+
+// Twitterに送る前に文字数を確認し、確実に拒否される投稿はAPIを呼ばずにエラーにする
+pub fn validate_tweet(text: &str) -> Result<(), Error> {
+    let result = crate::twitter_text::parse_tweet(text);
+
+    if result.valid {
+        Ok(())
+    } else if result.weighted_length > crate::twitter_text::MAX_WEIGHTED_LENGTH {
+        Err(Error::TweetTooLong {
+            weighted_length: result.weighted_length,
+        })
+    } else {
+        Err(Error::InvalidTweet)
     }
 }
 
@@ -152,106 +542,277 @@ pub struct RssTask {
     pub id: uuid::Uuid,
     pub schedule: Schedule,
     pub user_id: String,
+    // 投稿に使うアカウント（連携したアカウントを指定しなければログインに使ったアカウント）
+    pub account_id: String,
+    pub options: PostOptions,
     pub url: String,
     pub random: bool,
     pub last_pub_date: Option<OffsetDateTime>,
     pub template: Option<String>,
+    pub attach_image: bool,
+    pub filters: Vec<crate::rss_filter::FilterRule>,
+    // フィードの確認間隔（分）。未指定の場合はプランの既定値を使う
+    pub interval_minutes: Option<i32>,
+}
+
+#[derive(Debug, Clone, Copy, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum RssHistoryStatus {
+    Posted,
+    Filtered,
+    Failed,
+}
+
+// RSSタスクの実行履歴。フィルタで除外された記事もユーザーが確認できるように記録する
+#[derive(Debug, Clone, new)]
+pub struct RssHistory {
+    pub task_rss_id: uuid::Uuid,
+    pub user_id: String,
+    pub status: RssHistoryStatus,
+    pub title: Option<String>,
+    pub link: Option<String>,
+    pub pub_date: Option<OffsetDateTime>,
+    pub reason: Option<String>,
 }
 
 impl RssTask {
-    async fn run_task<F, Fut>(task: Self, send_tweet: F)
-    where
-        F: FnOnce(Token, serde_json::Value) -> Fut,
-        Fut: Future<Output = Result<String, crate::twitter::Error>>,
-    {
+    async fn run_task(task: Self) {
         tracing::info!("Tyring RSS post from {}", task.url);
 
-        match task.exec_tweet(send_tweet).await {
-            Ok(_) => tracing::info!("RSS post 成功"),
-            Err(err) => tracing::info!("RSS post 失敗 {}", err),
+        match task.exec_tweet().await {
+            Ok(Some(_)) => tracing::info!("RSS post 成功"),
+            Ok(None) => tracing::info!("ツイート対象の記事がありません"),
+            Err(err) => {
+                tracing::info!("RSS post 失敗 {}", err);
+                task.record_history(vec![task.history(
+                    None,
+                    RssHistoryStatus::Failed,
+                    Some(err.to_string()),
+                )])
+                .await;
+            }
         }
     }
 
-    async fn exec_tweet<F, Fut>(&self, send_tweet: F) -> Result<String, Error>
-    where
-        F: FnOnce(Token, serde_json::Value) -> Fut,
-        Fut: Future<Output = Result<String, crate::twitter::Error>>,
-    {
-        let mut feed = RssFeed::new(&self.url).await?;
+    async fn exec_tweet(&self) -> Result<Option<String>, Error> {
+        use crate::rss_filter::{Filters, Verdict};
 
-        let len = feed.items.len();
+        let filters = Filters::compile(&self.filters).context(crate::error::RssFilterSnafu)?;
+
+        let mut feed = RssFeed::new(&self.url).await?;
 
         feed.items.sort_by(|a, b| b.pub_date.cmp(&a.pub_date));
 
+        let mut histories = Vec::new();
+
         let rss_item = if self.random {
-            let index = random!(1, len);
-            feed.items.get(index - 1)
+            let accepted: Vec<&RssItem> = feed
+                .items
+                .iter()
+                .filter(|item| match filters.evaluate(&item.filter_target()) {
+                    Verdict::Accepted => true,
+                    Verdict::Rejected { reason } => {
+                        histories.push(self.history(
+                            Some(item),
+                            RssHistoryStatus::Filtered,
+                            Some(reason),
+                        ));
+                        false
+                    }
+                })
+                .collect();
+
+            if accepted.is_empty() {
+                None
+            } else {
+                let index = random!(1, accepted.len());
+                accepted.get(index - 1).copied()
+            }
         } else {
-            match feed.items.first() {
-                Some(post) => match self.last_pub_date {
-                    Some(last_pub_date) => {
-                        if post.pub_date > last_pub_date {
-                            Some(post)
-                        } else {
-                            None
-                        }
+            // 前回以降に公開された記事のうち、フィルタを通過した最新の記事を対象とする
+            let candidates = feed
+                .items
+                .iter()
+                .take_while(|item| match self.last_pub_date {
+                    Some(last_pub_date) => item.pub_date > last_pub_date,
+                    None => true,
+                });
+
+            let mut found = None;
+            for item in candidates {
+                match filters.evaluate(&item.filter_target()) {
+                    Verdict::Accepted => {
+                        found = Some(item);
+                        break;
                     }
-                    None => Some(post),
-                },
-                None => None,
+                    Verdict::Rejected { reason } => histories.push(self.history(
+                        Some(item),
+                        RssHistoryStatus::Filtered,
+                        Some(reason),
+                    )),
+                }
             }
+
+            // 新しい記事がすべて除外された場合も、次回に同じ記事を再び判定しないようにする
+            if found.is_none() && !histories.is_empty() {
+                self.update_last_pub_date(feed.pub_date).await;
+            }
+
+            found
+        };
+
+        self.record_history(histories).await;
+
+        let rss_item = match rss_item {
+            Some(rss_item) => rss_item,
+            None => return Ok(None),
         };
 
-        let rss_item = rss_item.whatever_context(
-            "スケージュールされていますが、ツイート対象のメッセージがありません",
-        )?;
+        let message = rss_item
+            .to_message(&feed.title, self.template.clone())
+            .context(crate::error::TemplateSnafu)?;
 
-        let message = rss_item.to_message(self.template.clone());
+        let publisher = crate::publisher::connect(&self.account_id, &self.options)
+            .await
+            .context(PublisherSnafu)?;
+
+        // 長すぎる記事で止まらないように、投稿できない記事は失敗として記録して次に進む
+        if let Err(err) = publisher.validate(&message) {
+            tracing::warn!("{}: {}", err, message);
+            self.update_last_pub_date(rss_item.pub_date).await;
+            self.record_history(vec![self.history(
+                Some(rss_item),
+                RssHistoryStatus::Failed,
+                Some(err.to_string()),
+            )])
+            .await;
+
+            return Ok(None);
+        }
 
         tracing::info!("これから次のメッセージをツイートします");
         tracing::info!("{}", message);
 
-        let token = crate::gq::load_session::load_session(
+        let image = if self.attach_image {
+            match rss_item
+                .upload_image(publisher.as_ref(), &self.user_id)
+                .await
+            {
+                Ok(Some(media_id)) => Some(media_id),
+                Ok(None) => {
+                    tracing::info!("記事に添付できる画像が見つかりませんでした");
+                    None
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        "画像の添付に失敗したため、テキストのみでツイートします: {}",
+                        err
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        // リンクカードを埋め込むサービスでは、記事の画像をカードのサムネイルにする
+        let (media_ids, card) = if publisher.link_cards() {
+            (vec![], Some(rss_item.link_card(image)))
+        } else {
+            (image.into_iter().collect(), None)
+        };
+
+        let resp = publisher
+            .post(Post {
+                text: message,
+                media_ids,
+                reply_to: None,
+                card,
+            })
+            .await
+            .context(PublisherSnafu)?;
+
+        self.update_last_pub_date(feed.pub_date).await;
+
+        self.record_history(vec![self.history(
+            Some(rss_item),
+            RssHistoryStatus::Posted,
+            None,
+        )])
+        .await;
+
+        Ok(Some(resp))
+    }
+
+    fn history(
+        &self,
+        item: Option<&RssItem>,
+        status: RssHistoryStatus,
+        reason: Option<String>,
+    ) -> RssHistory {
+        RssHistory::new(
+            self.id,
             self.user_id.clone(),
-            crate::state::oauth_client(),
+            status,
+            item.map(|item| item.title.clone()),
+            item.map(|item| item.link.clone()),
+            item.map(|item| item.pub_date),
+            reason,
         )
-        .await
-        .context(crate::error::GraphqlSnafu)?;
+    }
 
-        let json = TweetJson::Tweet(Tweet { text: message });
+    async fn record_history(&self, histories: Vec<RssHistory>) {
+        if histories.is_empty() {
+            return;
+        }
 
-        let resp = send_tweet(token, serde_json::to_value(&json).unwrap())
-            .await
-            .context(TwitterSnafu)?;
+        if let Err(err) = crate::gq::insert_task_rss_history::exec(histories).await {
+            tracing::warn!("Ignoring error on recording RSS history: {}", err);
+        }
+    }
 
-        if crate::gq::update_last_pub_date_task_rss::exec(self.id, feed.pub_date)
+    async fn update_last_pub_date(&self, last_pub_date: OffsetDateTime) {
+        if crate::gq::update_last_pub_date_task_rss::exec(self.id, last_pub_date)
             .await
             .is_err()
         {
             tracing::warn!("Ignoring error on updating last_pub_date");
         }
-
-        Ok(resp)
     }
 
-    pub fn to_schedule2(task: Self, scheduler: &mut clokwerk::AsyncScheduler) {
+    pub fn to_schedule2(
+        task: Self,
+        limits: &crate::config::PlanLimits,
+        scheduler: &mut clokwerk::AsyncScheduler,
+    ) {
         use clokwerk::Job;
 
         let jobs = if task.random {
             task.schedule.to_asyncjob(scheduler)
         } else {
             // 一定時間ごとにRSSフィードをチェックするようにスケジュールする
-            // Basicプランでは4時間おき
-            // Premiumプランでは1時間おき？
+            // 間隔はプランごとの既定値、またはプランで許可された範囲内のタスクごとの指定
             let tweet_at = format_tweet_at(task.schedule.tweet_at.clone());
+            let interval = limits.rss_interval_minutes(task.interval_minutes);
+
+            if task
+                .interval_minutes
+                .is_some_and(|minutes| minutes as u32 != interval)
+            {
+                tracing::info!(
+                    "RSS task {} interval is limited to {} minutes by the plan",
+                    task.id,
+                    interval
+                );
+            }
 
             scheduler
                 .every(clokwerk::Interval::Days(1))
                 .at(&tweet_at)
-                .and_every(clokwerk::Interval::Hours(4)) // set the interval to 4 hours
+                .and_every(clokwerk::Interval::Minutes(interval))
         };
         let task_cloned: RssTask = task.clone();
-        jobs.run(move || Self::run_task(task_cloned.clone(), crate::twitter::send_tweet_impl));
+        jobs.run(move || Self::run_task(task_cloned.clone()));
     }
 }
 
@@ -326,40 +887,41 @@ impl Schedule {
 pub struct EnabledTask {
     pub schedule: Schedule,
     pub user_id: String,
+    // 投稿に使うアカウント（連携したアカウントを指定しなければログインに使ったアカウント）
+    pub account_id: String,
+    pub options: PostOptions,
     // pub tag_id: Option<uuid::Uuid>,
     pub messages: Vec<Message>,
-    pub random: bool,
+    pub strategy: Strategy,
+    // 代替テキストのない画像を添付したメッセージを投稿しないかどうか
+    pub require_alt_text: bool,
 }
 
 impl EnabledTask {
-    async fn run_task(mut task: Self) {
+    async fn run_task(task: Self) {
         tracing::info!("TWEEET ");
 
-        let len = task.messages.len();
-        if len > 0 {
-            task.messages
-                .sort_by(|a, b: &Message| b.created_at.cmp(&a.created_at));
-
-            let message = if task.random {
-                let index = random!(1, len);
+        let messages = match task.strategy {
+            Strategy::Rotation { .. } => task.load_rotation().await,
+            _ => task.messages.clone(),
+        };
 
-                task.messages.get(index - 1)
-            } else {
-                task.messages.first()
-            };
+        let len = messages.len();
+        if len > 0 {
+            let message = task.strategy.select(&messages, &mut rand::thread_rng());
 
             if let Some(message) = message {
                 tracing::info!("これから次のメッセージをツイートします");
                 tracing::info!("{:?}", message);
 
-                let client = crate::state::oauth_client();
-                match crate::gq::load_session::load_session(task.user_id.clone(), client).await {
-                    Ok(token) => {
-                        match message.tweet(token, crate::twitter::send_tweet_impl).await {
-                            Ok(_) => tracing::info!("ツイート成功！"),
-                            Err(err) => tracing::error!("Tweetの実行に失敗しました: {:?}", err),
+                match crate::publisher::connect(&task.account_id, &task.options).await {
+                    Ok(publisher) => match message.tweet(publisher.as_ref()).await {
+                        Ok(_) => {
+                            tracing::info!("ツイート成功！");
+                            message.record_tweeted().await;
                         }
-                    }
+                        Err(err) => tracing::error!("Tweetの実行に失敗しました: {:?}", err),
+                    },
                     Err(err) => {
                         tracing::error!(
                             "セッション情報の読み込み時にエラーが発生しました: {:?}",
@@ -375,7 +937,61 @@ impl EnabledTask {
         }
     }
 
-    pub fn to_schedule2(task: Self, scheduler: &mut clokwerk::AsyncScheduler) {
+    // 投稿済みの状態はスケジュールの開始後も変わるため、実行のたびにHasuraから読み込む
+    // すべて投稿し終えていれば、投稿済みの印を消して次のサイクルを始める
+    async fn load_rotation(&self) -> Vec<Message> {
+        let ids: Vec<uuid::Uuid> = self.messages.iter().map(|message| message.id).collect();
+
+        let rotations = match crate::gq::list_message_rotation::exec(ids.clone()).await {
+            Ok(rotations) => rotations,
+            Err(err) => {
+                tracing::warn!("Ignoring error on loading message rotation: {}", err);
+                return self.messages.clone();
+            }
+        };
+
+        let messages: Vec<Message> = self
+            .messages
+            .iter()
+            .cloned()
+            .map(|mut message| {
+                if let Some(rotation) = rotations.iter().find(|r| r.message_id == message.id) {
+                    message.tweeted = rotation.tweeted;
+                    message.last_tweeted_at = rotation.last_tweeted_at;
+                }
+                message
+            })
+            .collect();
+
+        if !self.strategy.cycle_completed(&messages) {
+            return messages;
+        }
+
+        tracing::info!("すべてのメッセージを投稿したので、ローテーションを最初から始めます");
+        if let Err(err) = crate::gq::reset_message_rotation::exec(ids).await {
+            tracing::warn!("Ignoring error on resetting message rotation: {}", err);
+        }
+
+        messages.into_iter().map(Message::reset_rotation).collect()
+    }
+
+    pub fn to_schedule2(mut task: Self, scheduler: &mut clokwerk::AsyncScheduler) {
+        // 投稿時刻になってから失敗しないように、添付できないメディアは登録時に知らせる
+        for message in task.messages.iter() {
+            if let Err(err) = message.validate_media() {
+                tracing::warn!("Message {} cannot be tweeted: {}", message.id, err);
+            }
+        }
+
+        if task.require_alt_text {
+            task.messages.retain(|message| {
+                if !message.has_alt_text() {
+                    tracing::info!("Message {} is skipped: alt text is required", message.id);
+                }
+                message.has_alt_text()
+            });
+        }
+
         let jobs = task.schedule.to_asyncjob(scheduler);
 
         let task_cloned: EnabledTask = task.clone();
@@ -402,75 +1018,227 @@ pub struct RssFeed {
 
 #[derive(Deserialize, Serialize, Debug, new, Clone)]
 pub struct RssItem {
-    title: String,
-    link: String,
-    pub_date: OffsetDateTime,
+    pub title: String,
+    pub link: String,
+    pub pub_date: OffsetDateTime,
+    pub description: Option<String>,
+    pub author: Option<String>,
+    pub categories: Vec<String>,
+    pub image_url: Option<String>,
 }
 
 impl RssItem {
-    fn to_message(&self, template: Option<String>) -> String {
-        match template {
-            Some(template) => template
-                .replace("{title}", &self.title)
-                .replace("{pub_date}", &self.pub_date.to_string())
-                .replace("{url}", &self.link)
-                .replace("\\n", "\n"),
-
-            None => format!("{}\n\n{}", self.title, self.link),
+    fn to_message(
+        &self,
+        feed_title: &str,
+        template: Option<String>,
+    ) -> Result<String, crate::template::Error> {
+        use crate::template::{Template, DEFAULT_TEMPLATE};
+
+        let template = Template::parse(template.as_deref().unwrap_or(DEFAULT_TEMPLATE))?;
+
+        Ok(template.render(&self.to_context(feed_title)))
+    }
+
+    // フィードに画像が含まれていなければ、記事のog:imageを探す
+    async fn upload_image(
+        &self,
+        publisher: &dyn Publisher,
+        user_id: &str,
+    ) -> Result<Option<String>, Error> {
+        let image_url = match &self.image_url {
+            Some(image_url) => Some(image_url.clone()),
+            None => find_og_image(&self.link).await,
+        };
+
+        let image_url = match image_url {
+            Some(image_url) => image_url,
+            None => return Ok(None),
+        };
+
+        let bytes = download_image(&image_url).await?;
+        let mut bytes =
+            crate::media::prepare_for_tweet(&bytes).context(crate::error::MediaSnafu)?;
+
+        // 透かしを重ねられなかった場合は、透かしの無い画像を投稿しないようにエラーにする
+        if let Some(watermark) = crate::watermark::load(user_id)
+            .await
+            .context(crate::error::WatermarkSnafu)?
+        {
+            bytes = watermark
+                .stamp(bytes)
+                .context(crate::error::WatermarkSnafu)?;
+        }
+
+        let uploaded = publisher
+            .upload_media(bytes, format!("{}.jpg", uuid::Uuid::new_v4()), None)
+            .await
+            .context(PublisherSnafu)?;
+
+        Ok(Some(uploaded.id))
+    }
+
+    fn link_card(&self, thumb: Option<String>) -> crate::publisher::LinkCard {
+        let description = self.description.clone().unwrap_or_default();
+
+        crate::publisher::LinkCard {
+            uri: self.link.clone(),
+            title: self.title.clone(),
+            description: crate::template::strip_html(&description),
+            thumb,
+        }
+    }
+
+    fn filter_target(&self) -> crate::rss_filter::Target<'_> {
+        crate::rss_filter::Target {
+            title: &self.title,
+            description: self.description.as_deref().unwrap_or_default(),
+            categories: &self.categories,
         }
     }
+
+    pub fn to_context(&self, feed_title: &str) -> crate::template::Context {
+        let description = self.description.clone().unwrap_or_default();
+
+        crate::template::Context::new()
+            .text("title", self.title.clone())
+            .text("url", self.link.clone())
+            .text("summary", crate::template::strip_html(&description))
+            .text("description", description)
+            .text("author", self.author.clone().unwrap_or_default())
+            .list("categories", self.categories.clone())
+            .text("feed_title", feed_title)
+            .date("pub_date", self.pub_date)
+    }
 }
 
 impl RssFeed {
     async fn new(url: &str) -> Result<Self, Error> {
-        use rss::Channel;
-
-        let client = reqwest::Client::new();
-        let resp = client
-            .get(url)
-            .header(
-                "User-Agent",
-                "Mozilla/5.0 (Windows NT 6.3; Trident/7.0; rv 11.0) like Gecko",
-            )
-            .send()
-            .await
-            .whatever_context("Failed to get RSS feeds")?;
-
-        let bytes = resp
-            .bytes()
+        let feed = crate::feed::fetch(url)
             .await
-            .whatever_context("Failed to read RSS response as bytes")?;
-        let channel = Channel::read_from(&bytes[..])
-            .whatever_context("Failed to read bytes as RSS channel")?;
+            .context(crate::error::FeedSnafu)?;
 
-        let pub_date = time::OffsetDateTime::parse(
-            &channel.pub_date.unwrap(),
-            &time::format_description::well_known::Rfc2822,
-        )
-        .whatever_context("Failed parse pub_date")?;
+        for problem in &feed.problems {
+            tracing::warn!("{}: {}", url, problem);
+        }
 
-        let items = channel
-            .items
-            .into_iter()
-            .map(|item| {
-                let pub_date = time::OffsetDateTime::parse(
-                    &item.pub_date.unwrap(),
-                    &time::format_description::well_known::Rfc2822,
-                )
-                .unwrap();
-                RssItem::new(item.title.unwrap(), item.link.unwrap(), pub_date)
-            })
-            .collect();
+        // フィードに日付がない場合は最新の記事の日付を使う
+        let pub_date = feed
+            .pub_date
+            .or_else(|| feed.items.iter().map(|item| item.pub_date).max())
+            .unwrap_or_else(OffsetDateTime::now_utc);
 
         Ok(Self {
-            title: channel.title,
-            link: channel.link,
+            title: feed.title,
+            link: feed.link,
             pub_date,
-            items,
+            items: feed.items,
         })
     }
 }
 
+// 記事に添付できる画像の最大サイズ
+const MAX_IMAGE_BYTES: usize = 10 * 1024 * 1024;
+
+// og:imageを探す記事のHTMLの最大サイズ
+const MAX_HTML_BYTES: usize = 2 * 1024 * 1024;
+
+async fn find_og_image(link: &str) -> Option<String> {
+    let url = crate::net::ensure_public_url(link).ok()?;
+    let resp = crate::net::client()
+        .get(url)
+        .header("User-Agent", crate::feed::USER_AGENT)
+        .send()
+        .await
+        .ok()?;
+    let bytes = crate::net::read_limited(resp, MAX_HTML_BYTES).await.ok()?;
+    let html = String::from_utf8_lossy(&bytes);
+
+    let image_url = og_image_from_html(&html)?;
+
+    reqwest::Url::parse(link)
+        .and_then(|base| base.join(&image_url))
+        .map(|url| url.to_string())
+        .ok()
+}
+
+fn og_image_from_html(html: &str) -> Option<String> {
+    use once_cell::sync::Lazy;
+    use regex::Regex;
+
+    static META: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?is)<meta\b[^>]*>").unwrap());
+    static ATTR: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r#"(?is)([a-z:_-]+)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap());
+
+    META.find_iter(html).find_map(|meta| {
+        let mut property = None;
+        let mut content = None;
+
+        for attr in ATTR.captures_iter(meta.as_str()) {
+            let value = attr.get(2).or_else(|| attr.get(3)).map(|v| v.as_str());
+            match attr[1].to_ascii_lowercase().as_str() {
+                "property" | "name" => property = value,
+                "content" => content = value,
+                _ => {}
+            }
+        }
+
+        match property {
+            Some("og:image") | Some("og:image:url") => content.map(|c| c.trim().to_string()),
+            _ => None,
+        }
+    })
+}
+
+async fn download_image(url: &str) -> Result<Vec<u8>, Error> {
+    let url = crate::net::ensure_public_url(url).whatever_context("Invalid image URL")?;
+    let resp = crate::net::client()
+        .get(url)
+        .header("User-Agent", crate::feed::USER_AGENT)
+        .send()
+        .await
+        .whatever_context("Failed to download image")?;
+
+    crate::net::read_limited(resp, MAX_IMAGE_BYTES)
+        .await
+        .whatever_context("Failed to read image response")
+}
+
+#[test]
+fn test_og_image_from_html() {
+    let html = r#"<html><head>
+        <meta name="description" content="desc">
+        <meta content="/images/ring.jpg" property="og:image" />
+    </head></html>"#;
+
+    assert_eq!(
+        og_image_from_html(html),
+        Some("/images/ring.jpg".to_string())
+    );
+    assert_eq!(
+        og_image_from_html("<meta property='og:title' content='x'>"),
+        None
+    );
+}
+
+#[test]
+fn test_tweet_json_reply_to() {
+    let json = TweetJson::new("2/2".to_string(), vec!["100".to_string()]).reply_to("1".into());
+
+    assert_eq!(
+        serde_json::to_value(&json).unwrap(),
+        serde_json::json!({
+            "text": "2/2",
+            "media": { "media_ids": ["100"] },
+            "reply": { "in_reply_to_tweet_id": "1" },
+        })
+    );
+    assert_eq!(
+        serde_json::to_value(TweetJson::new("1/2".to_string(), vec![])).unwrap(),
+        serde_json::json!({ "text": "1/2" })
+    );
+}
+
 #[tokio::test]
 async fn test_get_rss_feed() -> Result<(), Error> {
     let url = "https://shichimaru.com/rss/blog";
@@ -110,7 +110,7 @@ impl Actor {
                 match scheduler::start_task_for_user(user_id.clone()).await {
                     Ok(handle) => {
                         tracing::info!("Restared schedule for the user: {}", user_id);
-                        self.workers.insert(user_id, handle).unwrap();
+                        self.workers.insert(user_id, handle);
                     }
                     Err(err) => {
                         tracing::error!("Error when starting scheduler: {}", err);
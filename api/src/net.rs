@@ -0,0 +1,170 @@
+// 記事の画像など、ユーザーが指定した外部のURLからの取得に共通する処理
+// サーバーから内部のネットワークにアクセスできないように、公開されていないアドレスには接続しない
+use snafu::prelude::*;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum Error {
+    #[snafu(display("URLにアクセスできませんでした: {source}"))]
+    Read { source: reqwest::Error },
+
+    #[snafu(display("応答が大きすぎます（{limit}バイトまで）"))]
+    TooLarge { limit: usize },
+
+    #[snafu(display("公開されていないアドレスにはアクセスできません: {host}"))]
+    PrivateAddress { host: String },
+}
+
+// リダイレクトを辿る回数の上限
+const MAX_REDIRECTS: usize = 10;
+
+// インターネットに公開されたアドレスかどうか
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ipv4(ip),
+            None => is_public_ipv6(ip),
+        },
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        // キャリアグレードNAT（100.64.0.0/10）
+        || (a == 100 && (64..128).contains(&b)))
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // ユニークローカル（fc00::/7）とリンクローカル（fe80::/10）
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80)
+}
+
+// URLのホストが、名前解決しなくても内部のものとわかる場合はfalse
+// ドメイン名の解決結果は、clientの名前解決で確認する
+pub fn is_public_url(url: &reqwest::Url) -> bool {
+    if !matches!(url.scheme(), "http" | "https") {
+        return false;
+    }
+
+    let host = match url.host_str() {
+        Some(host) => host.trim_start_matches('[').trim_end_matches(']'),
+        None => return false,
+    };
+
+    match host.parse::<IpAddr>() {
+        Ok(ip) => is_public_ip(ip),
+        Err(_) => {
+            let domain = host.trim_end_matches('.').to_ascii_lowercase();
+            !(domain == "localhost"
+                || domain.ends_with(".localhost")
+                || domain.ends_with(".local")
+                || domain.ends_with(".internal"))
+        }
+    }
+}
+
+// 公開されたアドレスにだけ接続する名前解決
+struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: hyper::client::connect::dns::Name) -> reqwest::dns::Resolving {
+        type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let addrs: Vec<_> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| is_public_ip(addr.ip()))
+                .collect();
+
+            if addrs.is_empty() {
+                return Err(Box::new(Error::PrivateAddress { host }) as BoxError);
+            }
+
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+// ユーザーが指定したURLを取得するクライアント
+// 名前解決とリダイレクト先の両方で、公開されていないアドレスへの接続を拒否する
+pub fn client() -> reqwest::Client {
+    use reqwest::redirect::Policy;
+
+    let redirect = Policy::custom(|attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            attempt.error("too many redirects")
+        } else if !is_public_url(attempt.url()) {
+            let host = attempt.url().host_str().unwrap_or_default().to_string();
+            attempt.error(Error::PrivateAddress { host })
+        } else {
+            attempt.follow()
+        }
+    });
+
+    reqwest::Client::builder()
+        .dns_resolver(std::sync::Arc::new(PublicResolver))
+        .redirect(redirect)
+        .build()
+        .expect("TLSの初期化に失敗することはないはずです")
+}
+
+// clientで取得する前に、URLの形式とホストを確認する
+pub fn ensure_public_url(url: &str) -> Result<reqwest::Url, Error> {
+    let parsed = reqwest::Url::parse(url).ok();
+
+    match parsed {
+        Some(parsed) if is_public_url(&parsed) => Ok(parsed),
+        _ => PrivateAddressSnafu { host: url }.fail(),
+    }
+}
+
+// 応答の本文を読み込む。上限を超える場合は、すべて読み込む前にやめる
+pub async fn read_limited(mut resp: reqwest::Response, limit: usize) -> Result<Vec<u8>, Error> {
+    if let Some(length) = resp.content_length() {
+        ensure!(length <= limit as u64, TooLargeSnafu { limit });
+    }
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = resp.chunk().await.context(ReadSnafu)? {
+        ensure!(bytes.len() + chunk.len() <= limit, TooLargeSnafu { limit });
+        bytes.extend_from_slice(&chunk);
+    }
+
+    Ok(bytes)
+}
+
+#[test]
+fn test_is_public_url() {
+    let public = |url: &str| is_public_url(&reqwest::Url::parse(url).unwrap());
+
+    assert!(public("https://example.com/feed.xml"));
+    assert!(public("http://93.184.216.34/"));
+    assert!(!public("http://localhost:8080/"));
+    assert!(!public("http://127.0.0.1/"));
+    assert!(!public("http://10.0.0.1/"));
+    assert!(!public("http://192.168.1.1/"));
+    assert!(!public("http://169.254.169.254/latest/meta-data/"));
+    assert!(!public("http://100.64.0.1/"));
+    assert!(!public("http://[::1]/"));
+    assert!(!public("http://[fd00::1]/"));
+    assert!(!public("http://[::ffff:127.0.0.1]/"));
+    assert!(!public("file:///etc/passwd"));
+}
@@ -0,0 +1,914 @@
+// BlueskyのPDSにXRPCで投稿する
+// OAuthの代わりにアプリパスワードでセッションを作り、アクセストークンとリフレッシュトークンを保存する
+use super::{BlueskySnafu, Post, Publisher, UploadedMedia};
+use crate::media::MediaKind;
+use crate::model::Token;
+use async_session::async_trait;
+use serde::{Deserialize, Serialize};
+use snafu::prelude::*;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum Error {
+    BlueskyNetwork {
+        source: reqwest::Error,
+    },
+    DecodeBlueskyResponse {
+        source: reqwest::Error,
+    },
+    #[snafu(display("Bluesky responded with {status}: {error} {message}"))]
+    BlueskyApi {
+        status: u16,
+        error: String,
+        message: String,
+    },
+    BlueskyMedia {
+        source: crate::media::Error,
+    },
+    // 動画の埋め込みには別の手順が要るので、今は静止画だけを添付する
+    #[snafu(display("Bluesky does not accept {content_type} as an image"))]
+    UnsupportedMedia {
+        content_type: String,
+    },
+    #[snafu(display("Invalid post id {post_id}"))]
+    InvalidPostId {
+        post_id: String,
+    },
+    #[snafu(display("Media {media_id} is not uploaded by this publisher"))]
+    UnknownMedia {
+        media_id: String,
+    },
+}
+
+// ハンドルにサービスを指定しなかった場合のPDS
+pub const DEFAULT_SERVICE: &str = "https://bsky.social";
+
+// アクセストークン（accessJwt）の有効期限。期限が近づいたらリフレッシュトークンで更新する
+pub const ACCESS_TOKEN_LIFETIME: std::time::Duration = std::time::Duration::from_secs(2 * 60 * 60);
+
+const COLLECTION: &str = "app.bsky.feed.post";
+
+// 本文は書記素クラスタ（見た目の1文字）で数える
+const MAX_GRAPHEMES: usize = 300;
+
+// 添付できる画像1枚の上限
+const MAX_BLOB_BYTES: usize = 1_000_000;
+
+// createSessionとrefreshSessionの応答
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Session {
+    pub access_jwt: String,
+    pub refresh_jwt: String,
+    pub did: String,
+    pub handle: String,
+}
+
+impl Session {
+    pub fn into_token(self, account_id: String) -> Token {
+        Token {
+            id: account_id,
+            access_token: self.access_jwt,
+            refresh_token: Some(self.refresh_jwt),
+            issued_at: time::OffsetDateTime::now_utc(),
+            expires_in: Some(ACCESS_TOKEN_LIFETIME),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: String,
+    #[serde(default)]
+    message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StrongRef {
+    uri: String,
+    cid: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ReplyRef {
+    root: StrongRef,
+    parent: StrongRef,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetRecordResponse {
+    uri: String,
+    cid: String,
+    value: PostRecordValue,
+}
+
+#[derive(Debug, Deserialize)]
+struct PostRecordValue {
+    reply: Option<ReplyRef>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UploadBlobResponse {
+    blob: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PostRecord {
+    #[serde(rename = "$type")]
+    type_: &'static str,
+    text: String,
+    created_at: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    facets: Vec<Facet>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    embed: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply: Option<ReplyRef>,
+}
+
+// リンクやハッシュタグの位置は、UTF-8のバイト数で指定する
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Facet {
+    pub index: ByteSlice,
+    pub features: Vec<Feature>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ByteSlice {
+    pub byte_start: usize,
+    pub byte_end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "$type")]
+pub enum Feature {
+    #[serde(rename = "app.bsky.richtext.facet#link")]
+    Link { uri: String },
+    #[serde(rename = "app.bsky.richtext.facet#tag")]
+    Tag { tag: String },
+}
+
+// 文末の句読点や括弧は、リンクやハッシュタグに含めない
+fn trim_trailing_punctuation(text: &str) -> &str {
+    const PUNCTUATION: &str = ".,;:!?)\"'。、」）！？";
+
+    text.trim_end_matches(|c: char| PUNCTUATION.contains(c))
+}
+
+pub fn facets(text: &str) -> Vec<Facet> {
+    use once_cell::sync::Lazy;
+    use regex::Regex;
+
+    // twitter_text::URLのパスと同じASCIIの文字だけを含め、日本語などが続いたらそこで終える
+    static LINK: Lazy<Regex> = Lazy::new(|| Regex::new(r"https?://[!#-;=?-~]+").unwrap());
+    static TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?:^|\s)([#＃][^\s#＃]+)").unwrap());
+
+    let links = LINK.find_iter(text).map(|link| {
+        let uri = trim_trailing_punctuation(link.as_str());
+        Facet {
+            index: ByteSlice {
+                byte_start: link.start(),
+                byte_end: link.start() + uri.len(),
+            },
+            features: vec![Feature::Link {
+                uri: uri.to_string(),
+            }],
+        }
+    });
+
+    let tags = TAG.captures_iter(text).filter_map(|captures| {
+        let tag = captures.get(1)?;
+        let text = trim_trailing_punctuation(tag.as_str());
+        let mut chars = text.chars();
+        let hash = chars.next()?;
+        let name = chars.as_str();
+
+        // 数字だけのものはハッシュタグとして扱わない
+        if name.is_empty() || name.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+
+        Some(Facet {
+            index: ByteSlice {
+                byte_start: tag.start(),
+                byte_end: tag.start() + hash.len_utf8() + name.len(),
+            },
+            features: vec![Feature::Tag {
+                tag: name.to_string(),
+            }],
+        })
+    });
+
+    let mut facets: Vec<Facet> = links.chain(tags).collect();
+    facets.sort_by_key(|facet| facet.index.byte_start);
+    facets
+}
+
+pub fn text_length(text: &str) -> usize {
+    use unicode_segmentation::UnicodeSegmentation;
+
+    text.graphemes(true).count()
+}
+
+// at://{did}/{collection}/{rkey} を分解する
+fn parse_at_uri(uri: &str) -> Option<(&str, &str, &str)> {
+    let mut parts = uri.strip_prefix("at://")?.splitn(3, '/');
+    Some((parts.next()?, parts.next()?, parts.next()?))
+}
+
+async fn decode<T>(resp: reqwest::Response) -> Result<T, Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let status = resp.status();
+    if status.is_success() {
+        resp.json::<T>().await.context(DecodeBlueskyResponseSnafu)
+    } else {
+        let (error, message) = match resp.json::<ErrorResponse>().await {
+            Ok(resp) => (resp.error, resp.message),
+            Err(_) => (
+                status.canonical_reason().unwrap_or_default().to_string(),
+                String::new(),
+            ),
+        };
+
+        Err(Error::BlueskyApi {
+            status: status.as_u16(),
+            error,
+            message,
+        })
+    }
+}
+
+// ハンドル（またはメールアドレス）とアプリパスワードでログインする
+pub async fn create_session(
+    service: &str,
+    identifier: &str,
+    password: &str,
+) -> Result<Session, Error> {
+    let client = reqwest::Client::new();
+    let resp = client
+        .post(format!("{}/xrpc/com.atproto.server.createSession", service))
+        .json(&serde_json::json!({ "identifier": identifier, "password": password }))
+        .send()
+        .await
+        .context(BlueskyNetworkSnafu)?;
+
+    decode(resp).await
+}
+
+// 使ったリフレッシュトークンは無効になるので、返ってきた新しいトークンを保存し直す
+pub async fn refresh_session(service: &str, refresh_token: &str) -> Result<Session, Error> {
+    let client = reqwest::Client::new();
+    let resp = client
+        .post(format!(
+            "{}/xrpc/com.atproto.server.refreshSession",
+            service
+        ))
+        .bearer_auth(refresh_token)
+        .send()
+        .await
+        .context(BlueskyNetworkSnafu)?;
+
+    decode(resp).await
+}
+
+struct Image {
+    blob: serde_json::Value,
+    alt: String,
+}
+
+pub struct BlueskyPublisher {
+    service: String,
+    did: String,
+    token: Token,
+    client: reqwest::Client,
+    // アップロードしたblobは投稿のレコードに埋め込むので、投稿するまで覚えておく
+    images: Mutex<HashMap<String, Image>>,
+}
+
+impl BlueskyPublisher {
+    pub fn new(service: String, did: String, token: Token) -> Self {
+        BlueskyPublisher {
+            service,
+            did,
+            token,
+            client: reqwest::Client::new(),
+            images: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn url(&self, method: &str) -> String {
+        format!("{}/xrpc/{}", self.service, method)
+    }
+
+    async fn procedure<T>(&self, method: &str, body: &serde_json::Value) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let resp = self
+            .client
+            .post(self.url(method))
+            .bearer_auth(&self.token.access_token)
+            .json(body)
+            .send()
+            .await
+            .context(BlueskyNetworkSnafu)?;
+
+        decode(resp).await
+    }
+
+    // 返信にはスレッドの最初の投稿と直前の投稿の両方のURIとCIDが必要なので、直前の投稿から読み取る
+    async fn reply_ref(&self, parent_uri: &str) -> Result<ReplyRef, Error> {
+        let (repo, collection, rkey) = parse_at_uri(parent_uri).context(InvalidPostIdSnafu {
+            post_id: parent_uri,
+        })?;
+
+        let resp = self
+            .client
+            .get(self.url("com.atproto.repo.getRecord"))
+            .bearer_auth(&self.token.access_token)
+            .query(&[("repo", repo), ("collection", collection), ("rkey", rkey)])
+            .send()
+            .await
+            .context(BlueskyNetworkSnafu)?;
+
+        let record = decode::<GetRecordResponse>(resp).await?;
+        let parent = StrongRef {
+            uri: record.uri,
+            cid: record.cid,
+        };
+
+        Ok(ReplyRef {
+            root: record
+                .value
+                .reply
+                .map(|reply| reply.root)
+                .unwrap_or_else(|| parent.clone()),
+            parent,
+        })
+    }
+
+    fn take_image(&self, media_id: &str) -> Result<Image, Error> {
+        self.images
+            .lock()
+            .unwrap()
+            .remove(media_id)
+            .context(UnknownMediaSnafu { media_id })
+    }
+
+    fn embed(&self, post: &Post) -> Result<Option<serde_json::Value>, Error> {
+        if !post.media_ids.is_empty() {
+            let images = post
+                .media_ids
+                .iter()
+                .map(|media_id| {
+                    self.take_image(media_id)
+                        .map(|image| serde_json::json!({ "image": image.blob, "alt": image.alt }))
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+
+            return Ok(Some(serde_json::json!({
+                "$type": "app.bsky.embed.images",
+                "images": images,
+            })));
+        }
+
+        match &post.card {
+            Some(card) => {
+                let mut external = serde_json::json!({
+                    "uri": card.uri,
+                    "title": card.title,
+                    "description": card.description,
+                });
+                if let Some(thumb) = &card.thumb {
+                    external["thumb"] = self.take_image(thumb)?.blob;
+                }
+
+                Ok(Some(serde_json::json!({
+                    "$type": "app.bsky.embed.external",
+                    "external": external,
+                })))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+#[async_trait]
+impl Publisher for BlueskyPublisher {
+    fn account_id(&self) -> &str {
+        &self.token.id
+    }
+
+    fn link_cards(&self) -> bool {
+        true
+    }
+
+    fn validate(&self, text: &str) -> Result<(), crate::error::Error> {
+        super::validate_length(text, text_length(text), MAX_GRAPHEMES)
+    }
+
+    // 画像の埋め込みに入れられるのは静止画だけ
+    fn validate_media(&self, kinds: &[MediaKind]) -> Result<(), crate::error::Error> {
+        if kinds.iter().all(|kind| *kind == MediaKind::Image) {
+            Ok(())
+        } else {
+            Err(crate::error::Error::UnsupportedMedia)
+        }
+    }
+
+    async fn upload_media(
+        &self,
+        bytes: Vec<u8>,
+        _file_name: String,
+        alt_text: Option<&str>,
+    ) -> Result<UploadedMedia, super::Error> {
+        // スレッドの2件目以降のメディアは形式が記録されていないので、ここでも確認する
+        let content_type = crate::media::content_type(&bytes)
+            .context(BlueskyMediaSnafu)
+            .context(BlueskySnafu)?;
+        if MediaKind::from_content_type(Some(content_type)) != MediaKind::Image {
+            return UnsupportedMediaSnafu { content_type }
+                .fail()
+                .context(BlueskySnafu);
+        }
+
+        let bytes = crate::media::shrink_to_fit(bytes, MAX_BLOB_BYTES)
+            .context(BlueskyMediaSnafu)
+            .context(BlueskySnafu)?;
+        let content_type = crate::media::content_type(&bytes).unwrap_or("image/jpeg");
+
+        let resp = self
+            .client
+            .post(self.url("com.atproto.repo.uploadBlob"))
+            .bearer_auth(&self.token.access_token)
+            .header(reqwest::header::CONTENT_TYPE, content_type)
+            .body(bytes)
+            .send()
+            .await
+            .context(BlueskyNetworkSnafu)
+            .context(BlueskySnafu)?;
+
+        let blob = decode::<UploadBlobResponse>(resp)
+            .await
+            .context(BlueskySnafu)?
+            .blob;
+
+        let id = blob["ref"]["$link"]
+            .as_str()
+            .map(|link| link.to_string())
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+        self.images.lock().unwrap().insert(
+            id.clone(),
+            Image {
+                blob,
+                alt: alt_text.unwrap_or_default().to_string(),
+            },
+        );
+
+        Ok(UploadedMedia {
+            id,
+            expires_after_secs: None,
+        })
+    }
+
+    async fn describe_media(&self, media_id: &str, alt_text: &str) -> Result<(), super::Error> {
+        let mut images = self.images.lock().unwrap();
+        let image = images
+            .get_mut(media_id)
+            .context(UnknownMediaSnafu { media_id })
+            .context(BlueskySnafu)?;
+        image.alt = alt_text.to_string();
+
+        Ok(())
+    }
+
+    // 投稿したレコードのURI（at://...）を返す
+    async fn post(&self, post: Post) -> Result<String, super::Error> {
+        let embed = self.embed(&post).context(BlueskySnafu)?;
+        let reply = match &post.reply_to {
+            Some(parent_uri) => Some(self.reply_ref(parent_uri).await.context(BlueskySnafu)?),
+            None => None,
+        };
+
+        let record = PostRecord {
+            type_: COLLECTION,
+            facets: facets(&post.text),
+            text: post.text,
+            created_at: time::OffsetDateTime::now_utc()
+                .format(&time::format_description::well_known::Rfc3339)
+                .unwrap(),
+            embed,
+            reply,
+        };
+
+        let created = self
+            .procedure::<StrongRef>(
+                "com.atproto.repo.createRecord",
+                &serde_json::json!({
+                    "repo": self.did,
+                    "collection": COLLECTION,
+                    "record": record,
+                }),
+            )
+            .await
+            .context(BlueskySnafu)?;
+
+        Ok(created.uri)
+    }
+
+    async fn delete(&self, post_id: &str) -> Result<(), super::Error> {
+        let (repo, collection, rkey) = parse_at_uri(post_id)
+            .context(InvalidPostIdSnafu { post_id })
+            .context(BlueskySnafu)?;
+
+        self.procedure::<serde_json::Value>(
+            "com.atproto.repo.deleteRecord",
+            &serde_json::json!({ "repo": repo, "collection": collection, "rkey": rkey }),
+        )
+        .await
+        .map(|_| ())
+        .context(BlueskySnafu)
+    }
+}
+
+#[test]
+fn test_facets() {
+    let text = "新着記事 https://example.com/a. #お知らせ #123 #rust!";
+
+    assert_eq!(
+        serde_json::to_value(facets(text)).unwrap(),
+        serde_json::json!([
+            {
+                "index": { "byteStart": 13, "byteEnd": 34 },
+                "features": [{ "$type": "app.bsky.richtext.facet#link", "uri": "https://example.com/a" }],
+            },
+            {
+                "index": { "byteStart": 36, "byteEnd": 49 },
+                "features": [{ "$type": "app.bsky.richtext.facet#tag", "tag": "お知らせ" }],
+            },
+            {
+                "index": { "byteStart": 55, "byteEnd": 60 },
+                "features": [{ "$type": "app.bsky.richtext.facet#tag", "tag": "rust" }],
+            },
+        ])
+    );
+    assert_eq!(&text[13..34], "https://example.com/a");
+    assert_eq!(&text[36..49], "#お知らせ");
+
+    assert!(facets("https://example.com/#anchor").len() == 1);
+
+    // 日本語の文中のURLは、URLに使えない文字の手前で終える
+    let text = "記事https://example.com/a。続き";
+    assert_eq!(
+        facets(text),
+        vec![Facet {
+            index: ByteSlice {
+                byte_start: 6,
+                byte_end: 27,
+            },
+            features: vec![Feature::Link {
+                uri: "https://example.com/a".to_string(),
+            }],
+        }]
+    );
+    assert_eq!(text_length("👨‍👩‍👧"), 1);
+}
+
+#[tokio::test]
+async fn test_bluesky_publisher() {
+    use axum::{
+        body::Bytes,
+        extract::{Query, State},
+        http::{HeaderMap, StatusCode},
+        routing::{get, post},
+        Json, Router,
+    };
+    use std::sync::Arc;
+
+    const DID: &str = "did:plc:bott";
+
+    #[derive(Default)]
+    struct Recorded {
+        authorizations: Vec<String>,
+        blobs: Vec<(String, usize)>,
+        records: Vec<serde_json::Value>,
+        deleted: Vec<String>,
+    }
+
+    type Shared = Arc<Mutex<Recorded>>;
+
+    fn record_authorization(state: &Shared, headers: &HeaderMap) {
+        let authorization = headers
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        state.lock().unwrap().authorizations.push(authorization);
+    }
+
+    fn session() -> Json<serde_json::Value> {
+        Json(serde_json::json!({
+            "accessJwt": "access",
+            "refreshJwt": "refresh",
+            "did": DID,
+            "handle": "bott.example.com",
+        }))
+    }
+
+    async fn server_create_session(
+        Json(body): Json<serde_json::Value>,
+    ) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+        if body["password"] == "app-password" {
+            Ok(session())
+        } else {
+            Err((
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({
+                    "error": "AuthenticationRequired",
+                    "message": "Invalid identifier or password",
+                })),
+            ))
+        }
+    }
+
+    async fn server_refresh_session(headers: HeaderMap) -> Json<serde_json::Value> {
+        assert_eq!(headers["authorization"], "Bearer refresh");
+        session()
+    }
+
+    async fn upload_blob(
+        State(state): State<Shared>,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> Json<serde_json::Value> {
+        record_authorization(&state, &headers);
+        let content_type = headers["content-type"].to_str().unwrap().to_string();
+
+        let mut state = state.lock().unwrap();
+        state.blobs.push((content_type.clone(), body.len()));
+        Json(serde_json::json!({
+            "blob": {
+                "$type": "blob",
+                "ref": { "$link": format!("blob{}", state.blobs.len()) },
+                "mimeType": content_type,
+                "size": body.len(),
+            }
+        }))
+    }
+
+    async fn create_record(
+        State(state): State<Shared>,
+        headers: HeaderMap,
+        Json(body): Json<serde_json::Value>,
+    ) -> (StatusCode, Json<serde_json::Value>) {
+        record_authorization(&state, &headers);
+        assert_eq!(body["repo"], DID);
+        assert_eq!(body["collection"], COLLECTION);
+
+        if body["record"]["text"] == "" {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "error": "InvalidRequest",
+                    "message": "Record/text must not be empty",
+                })),
+            );
+        }
+
+        let mut state = state.lock().unwrap();
+        let rkey = state.records.len();
+        state.records.push(body["record"].clone());
+        (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "uri": format!("at://{}/{}/{}", DID, COLLECTION, rkey),
+                "cid": format!("cid{}", rkey),
+            })),
+        )
+    }
+
+    async fn get_record(
+        State(state): State<Shared>,
+        Query(query): Query<HashMap<String, String>>,
+    ) -> Json<serde_json::Value> {
+        let rkey: usize = query["rkey"].parse().unwrap();
+        let state = state.lock().unwrap();
+        Json(serde_json::json!({
+            "uri": format!("at://{}/{}/{}", query["repo"], query["collection"], rkey),
+            "cid": format!("cid{}", rkey),
+            "value": state.records[rkey],
+        }))
+    }
+
+    async fn delete_record(
+        State(state): State<Shared>,
+        Json(body): Json<serde_json::Value>,
+    ) -> Json<serde_json::Value> {
+        let rkey = body["rkey"].as_str().unwrap().to_string();
+        state.lock().unwrap().deleted.push(rkey);
+        Json(serde_json::json!({}))
+    }
+
+    let state = Shared::default();
+    let app = Router::new()
+        .route(
+            "/xrpc/com.atproto.server.createSession",
+            post(server_create_session),
+        )
+        .route(
+            "/xrpc/com.atproto.server.refreshSession",
+            post(server_refresh_session),
+        )
+        .route("/xrpc/com.atproto.repo.uploadBlob", post(upload_blob))
+        .route("/xrpc/com.atproto.repo.createRecord", post(create_record))
+        .route("/xrpc/com.atproto.repo.getRecord", get(get_record))
+        .route("/xrpc/com.atproto.repo.deleteRecord", post(delete_record))
+        .with_state(state.clone());
+
+    let service = super::stand_in_server(app);
+
+    // アプリパスワードでのログインと、トークンの更新
+    match create_session(&service, "bott.example.com", "wrong").await {
+        Err(Error::BlueskyApi { status, error, .. }) => {
+            assert_eq!(status, 401);
+            assert_eq!(error, "AuthenticationRequired");
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+    let session = create_session(&service, "bott.example.com", "app-password")
+        .await
+        .unwrap();
+    let session = refresh_session(&service, &session.refresh_jwt)
+        .await
+        .unwrap();
+
+    let account_id = super::account_id(super::Provider::Bluesky, &service, &session.did);
+    let account = super::Account {
+        provider: super::Provider::Bluesky,
+        instance: Some(service.clone()),
+        token: session.into_token(account_id),
+    };
+    let publisher = super::from_account(account, &super::PostOptions::default()).unwrap();
+    assert!(publisher.link_cards());
+
+    let png = || {
+        let mut png = std::io::Cursor::new(Vec::new());
+        image::DynamicImage::ImageRgb8(image::RgbImage::new(2, 2))
+            .write_to(&mut png, image::ImageOutputFormat::Png)
+            .unwrap();
+        png.into_inner()
+    };
+
+    // GIFアニメや動画は、静止画に変換したり画像として埋め込んだりしない
+    assert!(publisher.validate_media(&[MediaKind::Image]).is_ok());
+    assert!(publisher.validate_media(&[MediaKind::Gif]).is_err());
+    assert!(publisher.validate_media(&[MediaKind::Video]).is_err());
+
+    let mut gif = std::io::Cursor::new(Vec::new());
+    image::DynamicImage::ImageRgba8(image::RgbaImage::new(2, 2))
+        .write_to(&mut gif, image::ImageOutputFormat::Gif)
+        .unwrap();
+    assert!(matches!(
+        publisher
+            .upload_media(gif.into_inner(), "anime.gif".to_string(), None)
+            .await,
+        Err(super::Error::Bluesky {
+            source: Error::UnsupportedMedia { .. }
+        })
+    ));
+
+    // 画像を添付した投稿と、それへの返信
+    let uploaded = publisher
+        .upload_media(png(), "logo.png".to_string(), None)
+        .await
+        .unwrap();
+    publisher
+        .describe_media(&uploaded.id, "ロゴ")
+        .await
+        .unwrap();
+
+    let first = publisher
+        .post(Post {
+            text: "1/3 #お知らせ".to_string(),
+            media_ids: vec![uploaded.id],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let second = publisher
+        .post(Post {
+            text: "2/3".to_string(),
+            reply_to: Some(first.clone()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    publisher
+        .post(Post {
+            text: "3/3".to_string(),
+            reply_to: Some(second.clone()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    // RSSの記事はリンクカードにする
+    let thumb = publisher
+        .upload_media(png(), "thumb.jpg".to_string(), None)
+        .await
+        .unwrap();
+    publisher
+        .post(Post {
+            text: "新着記事 https://example.com/a".to_string(),
+            card: Some(super::LinkCard {
+                uri: "https://example.com/a".to_string(),
+                title: "記事".to_string(),
+                description: "概要".to_string(),
+                thumb: Some(thumb.id),
+            }),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    publisher.delete(&second).await.unwrap();
+
+    match publisher.post(Post::default()).await {
+        Err(super::Error::Bluesky {
+            source: Error::BlueskyApi { status, error, .. },
+        }) => {
+            assert_eq!(status, 400);
+            assert_eq!(error, "InvalidRequest");
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    let recorded = state.lock().unwrap();
+    let records = &recorded.records;
+    assert_eq!(records.len(), 4);
+
+    assert_eq!(records[0]["$type"], COLLECTION);
+    assert_eq!(
+        records[0]["facets"],
+        serde_json::json!([{
+            "index": { "byteStart": 4, "byteEnd": 17 },
+            "features": [{ "$type": "app.bsky.richtext.facet#tag", "tag": "お知らせ" }],
+        }])
+    );
+    assert_eq!(records[0]["embed"]["$type"], "app.bsky.embed.images");
+    assert_eq!(records[0]["embed"]["images"][0]["alt"], "ロゴ");
+    assert_eq!(
+        records[0]["embed"]["images"][0]["image"]["ref"]["$link"],
+        "blob1"
+    );
+    assert!(records[0].get("reply").is_none());
+
+    let root = serde_json::json!({ "uri": first, "cid": "cid0" });
+    assert_eq!(
+        records[1]["reply"],
+        serde_json::json!({ "root": root, "parent": root })
+    );
+    assert_eq!(records[2]["reply"]["root"], root);
+    assert_eq!(
+        records[2]["reply"]["parent"],
+        serde_json::json!({ "uri": second, "cid": "cid1" })
+    );
+
+    assert_eq!(
+        records[3]["embed"],
+        serde_json::json!({
+            "$type": "app.bsky.embed.external",
+            "external": {
+                "uri": "https://example.com/a",
+                "title": "記事",
+                "description": "概要",
+                "thumb": {
+                    "$type": "blob",
+                    "ref": { "$link": "blob2" },
+                    "mimeType": "image/png",
+                    "size": recorded.blobs[1].1,
+                },
+            },
+        })
+    );
+    assert_eq!(
+        records[3]["facets"][0]["features"][0]["uri"],
+        "https://example.com/a"
+    );
+
+    assert_eq!(recorded.deleted, vec!["1".to_string()]);
+    assert!(recorded
+        .authorizations
+        .iter()
+        .all(|authorization| authorization == "Bearer access"));
+
+    assert!(publisher.validate(&"あ".repeat(MAX_GRAPHEMES)).is_ok());
+    assert!(publisher.validate(&"あ".repeat(MAX_GRAPHEMES + 1)).is_err());
+}
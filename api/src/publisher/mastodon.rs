@@ -0,0 +1,573 @@
+// MastodonのREST APIで投稿する
+// OAuthのアプリはインスタンスごとに登録が必要なので、最初に連携するときに登録してHasuraに保存する
+use super::{MastodonSnafu, Post, PostOptions, Publisher, UploadedMedia, Visibility};
+use crate::model::Token;
+use async_session::async_trait;
+use reqwest::multipart;
+use serde::{Deserialize, Serialize};
+use snafu::prelude::*;
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum Error {
+    MastodonNetwork {
+        source: reqwest::Error,
+    },
+    DecodeMastodonResponse {
+        source: reqwest::Error,
+    },
+    #[snafu(display("Mastodon API responded with {status}: {message}"))]
+    MastodonApi {
+        status: u16,
+        message: String,
+    },
+    MediaProcessingTimeout,
+}
+
+// 投稿とメディアのアップロード、連携したアカウントの確認に必要な権限
+pub const SCOPES: &str = "read:accounts write:statuses write:media";
+
+// Mastodonの既定の上限。インスタンスの設定で変えられるが、ほとんどはこのまま
+const MAX_CHARACTERS: usize = 500;
+
+// URLは実際の長さに関係なく23文字として数えられる
+const URL_LENGTH: usize = 23;
+
+// メディアの変換を待つ回数の上限と間隔
+const MAX_STATUS_CHECKS: usize = 60;
+const STATUS_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct App {
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MastodonAccount {
+    pub id: String,
+    pub username: String,
+    pub display_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct MediaAttachment {
+    id: String,
+    // 変換中はnull
+    url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Status {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+#[derive(Debug, Serialize)]
+struct StatusParams {
+    status: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    media_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    in_reply_to_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    spoiler_text: Option<String>,
+    visibility: Visibility,
+}
+
+pub async fn register_app(instance: &str, redirect_uri: &str) -> Result<App, Error> {
+    let client = reqwest::Client::new();
+    let resp = client
+        .post(format!("{}/api/v1/apps", instance))
+        .json(&serde_json::json!({
+            "client_name": "Still Bott",
+            "redirect_uris": redirect_uri,
+            "scopes": SCOPES,
+        }))
+        .send()
+        .await
+        .context(MastodonNetworkSnafu)?;
+
+    decode(resp).await
+}
+
+pub fn authorize_url(instance: &str, app: &App, redirect_uri: &str, state: &str) -> String {
+    let mut url = super::instance_url(instance, "/oauth/authorize");
+    url.query_pairs_mut()
+        .append_pair("client_id", &app.client_id)
+        .append_pair("response_type", "code")
+        .append_pair("redirect_uri", redirect_uri)
+        .append_pair("scope", SCOPES)
+        .append_pair("state", state);
+
+    url.to_string()
+}
+
+// Mastodonのアクセストークンには有効期限がない
+pub async fn exchange_code(
+    instance: &str,
+    app: &App,
+    code: &str,
+    redirect_uri: &str,
+) -> Result<String, Error> {
+    let client = reqwest::Client::new();
+    let resp = client
+        .post(format!("{}/oauth/token", instance))
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("client_id", &app.client_id),
+            ("client_secret", &app.client_secret),
+            ("redirect_uri", redirect_uri),
+            ("scope", SCOPES),
+            ("code", code),
+        ])
+        .send()
+        .await
+        .context(MastodonNetworkSnafu)?;
+
+    decode::<TokenResponse>(resp)
+        .await
+        .map(|token| token.access_token)
+}
+
+pub async fn verify_credentials(
+    instance: &str,
+    access_token: &str,
+) -> Result<MastodonAccount, Error> {
+    let client = reqwest::Client::new();
+    let resp = client
+        .get(format!("{}/api/v1/accounts/verify_credentials", instance))
+        .bearer_auth(access_token)
+        .send()
+        .await
+        .context(MastodonNetworkSnafu)?;
+
+    decode(resp).await
+}
+
+async fn decode<T>(resp: reqwest::Response) -> Result<T, Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let status = resp.status();
+    if status.is_success() {
+        resp.json::<T>().await.context(DecodeMastodonResponseSnafu)
+    } else {
+        let message = match resp.json::<ErrorResponse>().await {
+            Ok(error) => error.error,
+            Err(_) => status.canonical_reason().unwrap_or_default().to_string(),
+        };
+
+        Err(Error::MastodonApi {
+            status: status.as_u16(),
+            message,
+        })
+    }
+}
+
+// URLを23文字として数えた文字数
+pub fn text_length(text: &str) -> usize {
+    use once_cell::sync::Lazy;
+    use regex::Regex;
+
+    static URL: Lazy<Regex> = Lazy::new(|| Regex::new(r"https?://[^\s]+").unwrap());
+
+    let urls = URL.find_iter(text).count();
+    let without_urls = URL.replace_all(text, "");
+
+    without_urls.chars().count() + urls * URL_LENGTH
+}
+
+pub struct MastodonPublisher {
+    instance: String,
+    token: Token,
+    options: PostOptions,
+    client: reqwest::Client,
+}
+
+impl MastodonPublisher {
+    pub fn new(instance: String, token: Token, options: PostOptions) -> Self {
+        MastodonPublisher {
+            instance,
+            token,
+            options,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.instance, path)
+    }
+
+    // 大きな画像や動画はインスタンス側で非同期に変換されるので、URLが付くまで待つ
+    async fn wait_for_processing(&self, media_id: &str) -> Result<(), Error> {
+        for _ in 0..MAX_STATUS_CHECKS {
+            tokio::time::sleep(STATUS_CHECK_INTERVAL).await;
+
+            let resp = self
+                .client
+                .get(self.url(&format!("/api/v1/media/{}", media_id)))
+                .bearer_auth(&self.token.access_token)
+                .send()
+                .await
+                .context(MastodonNetworkSnafu)?;
+
+            let media = decode::<MediaAttachment>(resp).await?;
+            if media.url.is_some() {
+                return Ok(());
+            }
+
+            tracing::debug!("Waiting for Mastodon media processing {}", media_id);
+        }
+
+        Err(Error::MediaProcessingTimeout)
+    }
+}
+
+#[async_trait]
+impl Publisher for MastodonPublisher {
+    fn account_id(&self) -> &str {
+        &self.token.id
+    }
+
+    fn validate(&self, text: &str) -> Result<(), crate::error::Error> {
+        super::validate_length(text, text_length(text), MAX_CHARACTERS)
+    }
+
+    async fn upload_media(
+        &self,
+        bytes: Vec<u8>,
+        file_name: String,
+        alt_text: Option<&str>,
+    ) -> Result<UploadedMedia, super::Error> {
+        let content_type = crate::media::content_type(&bytes).unwrap_or("application/octet-stream");
+        let file = multipart::Part::bytes(bytes)
+            .file_name(file_name)
+            .mime_str(content_type)
+            .unwrap();
+
+        let form = multipart::Form::new().part("file", file);
+        let form = match alt_text {
+            Some(alt_text) => form.text("description", alt_text.to_string()),
+            None => form,
+        };
+
+        let resp = self
+            .client
+            .post(self.url("/api/v2/media"))
+            .bearer_auth(&self.token.access_token)
+            .multipart(form)
+            .send()
+            .await
+            .context(MastodonNetworkSnafu)
+            .context(MastodonSnafu)?;
+
+        let media = decode::<MediaAttachment>(resp)
+            .await
+            .context(MastodonSnafu)?;
+
+        if media.url.is_none() {
+            self.wait_for_processing(&media.id)
+                .await
+                .context(MastodonSnafu)?;
+        }
+
+        // 投稿に添付したメディアは、別の投稿には添付できない
+        Ok(UploadedMedia {
+            id: media.id,
+            expires_after_secs: None,
+        })
+    }
+
+    async fn describe_media(&self, media_id: &str, alt_text: &str) -> Result<(), super::Error> {
+        let resp = self
+            .client
+            .put(self.url(&format!("/api/v1/media/{}", media_id)))
+            .bearer_auth(&self.token.access_token)
+            .json(&serde_json::json!({ "description": alt_text }))
+            .send()
+            .await
+            .context(MastodonNetworkSnafu)
+            .context(MastodonSnafu)?;
+
+        decode::<serde_json::Value>(resp)
+            .await
+            .map(|_| ())
+            .context(MastodonSnafu)
+    }
+
+    async fn post(&self, post: Post) -> Result<String, super::Error> {
+        let params = StatusParams {
+            status: post.text,
+            media_ids: post.media_ids,
+            in_reply_to_id: post.reply_to,
+            spoiler_text: self.options.content_warning.clone(),
+            visibility: self.options.visibility,
+        };
+
+        let resp = self
+            .client
+            .post(self.url("/api/v1/statuses"))
+            .bearer_auth(&self.token.access_token)
+            .json(&params)
+            .send()
+            .await
+            .context(MastodonNetworkSnafu)
+            .context(MastodonSnafu)?;
+
+        decode::<Status>(resp)
+            .await
+            .map(|status| status.id)
+            .context(MastodonSnafu)
+    }
+
+    async fn delete(&self, post_id: &str) -> Result<(), super::Error> {
+        let resp = self
+            .client
+            .delete(self.url(&format!("/api/v1/statuses/{}", post_id)))
+            .bearer_auth(&self.token.access_token)
+            .send()
+            .await
+            .context(MastodonNetworkSnafu)
+            .context(MastodonSnafu)?;
+
+        decode::<serde_json::Value>(resp)
+            .await
+            .map(|_| ())
+            .context(MastodonSnafu)
+    }
+}
+
+#[test]
+fn test_text_length() {
+    assert_eq!(text_length("こんにちは"), 5);
+    assert_eq!(
+        text_length("新しい記事 https://example.com/articles/a-very-long-slug-for-the-article"),
+        6 + URL_LENGTH
+    );
+}
+
+#[tokio::test]
+async fn test_mastodon_publisher() {
+    use axum::{
+        extract::{Multipart, Path, State},
+        http::{HeaderMap, StatusCode},
+        routing::{delete, get, post},
+        Json, Router,
+    };
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Recorded {
+        authorizations: Vec<String>,
+        descriptions: Vec<String>,
+        statuses: Vec<serde_json::Value>,
+        deleted: Vec<String>,
+    }
+
+    type Shared = Arc<Mutex<Recorded>>;
+
+    fn record_authorization(state: &Shared, headers: &HeaderMap) {
+        let authorization = headers
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        state.lock().unwrap().authorizations.push(authorization);
+    }
+
+    async fn apps(Json(body): Json<serde_json::Value>) -> Json<serde_json::Value> {
+        assert_eq!(body["scopes"], SCOPES);
+        Json(serde_json::json!({ "client_id": "client", "client_secret": "secret" }))
+    }
+
+    async fn token(body: String) -> Json<serde_json::Value> {
+        assert!(body.contains("code=authorized"));
+        assert!(body.contains("client_secret=secret"));
+        Json(serde_json::json!({ "access_token": "access", "token_type": "Bearer" }))
+    }
+
+    async fn server_verify_credentials(
+        State(state): State<Shared>,
+        headers: HeaderMap,
+    ) -> Json<serde_json::Value> {
+        record_authorization(&state, &headers);
+        Json(serde_json::json!({ "id": "1", "username": "bott", "display_name": "Bott" }))
+    }
+
+    // 変換中として応答し、確認されたら変換済みとして応答する
+    async fn upload(
+        State(state): State<Shared>,
+        headers: HeaderMap,
+        mut multipart: Multipart,
+    ) -> (StatusCode, Json<serde_json::Value>) {
+        record_authorization(&state, &headers);
+        while let Some(field) = multipart.next_field().await.unwrap() {
+            if field.name() == Some("description") {
+                let description = field.text().await.unwrap();
+                state.lock().unwrap().descriptions.push(description);
+            }
+        }
+        (
+            StatusCode::ACCEPTED,
+            Json(serde_json::json!({ "id": "10", "url": null })),
+        )
+    }
+
+    async fn media(Path(id): Path<String>) -> Json<serde_json::Value> {
+        Json(serde_json::json!({ "id": id, "url": "https://files.example.com/10.png" }))
+    }
+
+    async fn statuses(
+        State(state): State<Shared>,
+        headers: HeaderMap,
+        Json(body): Json<serde_json::Value>,
+    ) -> (StatusCode, Json<serde_json::Value>) {
+        record_authorization(&state, &headers);
+
+        if body["status"] == "" {
+            return (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(serde_json::json!({ "error": "Validation failed: Text can't be blank" })),
+            );
+        }
+
+        let mut state = state.lock().unwrap();
+        state.statuses.push(body);
+        let id = 100 + state.statuses.len() - 1;
+        (
+            StatusCode::OK,
+            Json(serde_json::json!({ "id": id.to_string() })),
+        )
+    }
+
+    async fn delete_status(
+        State(state): State<Shared>,
+        Path(id): Path<String>,
+    ) -> Json<serde_json::Value> {
+        state.lock().unwrap().deleted.push(id.clone());
+        Json(serde_json::json!({ "id": id }))
+    }
+
+    let state = Shared::default();
+    let app = Router::new()
+        .route("/api/v1/apps", post(apps))
+        .route("/oauth/token", post(token))
+        .route(
+            "/api/v1/accounts/verify_credentials",
+            get(server_verify_credentials),
+        )
+        .route("/api/v2/media", post(upload))
+        .route("/api/v1/media/:id", get(media))
+        .route("/api/v1/statuses", post(statuses))
+        .route("/api/v1/statuses/:id", delete(delete_status))
+        .with_state(state.clone());
+
+    let instance = super::stand_in_server(app);
+
+    // アプリの登録から連携したアカウントの確認まで
+    let redirect_uri = "https://bott.example.com/auth/mastodon/authorized";
+    let app = register_app(&instance, redirect_uri).await.unwrap();
+    assert_eq!(app.client_id, "client");
+    let url = authorize_url(&instance, &app, redirect_uri, "state");
+    assert!(url.contains("scope=read%3Aaccounts"));
+    assert!(url.contains("state=state"));
+
+    let access_token = exchange_code(&instance, &app, "authorized", redirect_uri)
+        .await
+        .unwrap();
+    let account = verify_credentials(&instance, &access_token).await.unwrap();
+    assert_eq!(account.username, "bott");
+
+    let token = Token {
+        id: super::account_id(super::Provider::Mastodon, &instance, &account.id),
+        access_token,
+        refresh_token: None,
+        issued_at: time::OffsetDateTime::now_utc(),
+        expires_in: None,
+    };
+    let options = PostOptions::new(Some("unlisted"), Some("ネタバレ".to_string()));
+    let publisher = MastodonPublisher::new(instance.clone(), token, options);
+
+    // 画像を添付した投稿と、それへの返信
+    let mut png = std::io::Cursor::new(Vec::new());
+    image::DynamicImage::ImageRgb8(image::RgbImage::new(2, 2))
+        .write_to(&mut png, image::ImageOutputFormat::Png)
+        .unwrap();
+    let uploaded = publisher
+        .upload_media(png.into_inner(), "logo.png".to_string(), Some("ロゴ"))
+        .await
+        .unwrap();
+    assert_eq!(uploaded.id, "10");
+    assert!(!publisher.reuses_media());
+
+    let first = publisher
+        .post(Post {
+            text: "1/2".to_string(),
+            media_ids: vec![uploaded.id],
+            reply_to: None,
+            card: None,
+        })
+        .await
+        .unwrap();
+    let second = publisher
+        .post(Post {
+            text: "2/2".to_string(),
+            media_ids: vec![],
+            reply_to: Some(first.clone()),
+            card: None,
+        })
+        .await
+        .unwrap();
+    publisher.delete(&second).await.unwrap();
+
+    // APIのエラーは、応答のメッセージとともに返す
+    match publisher.post(Post::default()).await {
+        Err(super::Error::Mastodon {
+            source: Error::MastodonApi { status, message },
+        }) => {
+            assert_eq!(status, 422);
+            assert!(message.contains("blank"));
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    let recorded = state.lock().unwrap();
+    assert_eq!(
+        recorded.statuses,
+        vec![
+            serde_json::json!({
+                "status": "1/2",
+                "media_ids": ["10"],
+                "spoiler_text": "ネタバレ",
+                "visibility": "unlisted",
+            }),
+            serde_json::json!({
+                "status": "2/2",
+                "in_reply_to_id": "100",
+                "spoiler_text": "ネタバレ",
+                "visibility": "unlisted",
+            }),
+        ]
+    );
+    assert_eq!(recorded.deleted, vec!["101".to_string()]);
+    assert_eq!(recorded.descriptions, vec!["ロゴ".to_string()]);
+    assert!(recorded
+        .authorizations
+        .iter()
+        .all(|authorization| authorization == "Bearer access"));
+
+    assert!(publisher.validate("こんにちは").is_ok());
+    assert!(publisher
+        .validate(&"あ".repeat(MAX_CHARACTERS + 1))
+        .is_err());
+}
@@ -0,0 +1,578 @@
+// MisskeyのAPIで投稿する
+// アクセストークンはMiAuthでインスタンスごとに発行してもらい、リクエストの本文の`i`で送る
+use super::{MisskeySnafu, Post, PostOptions, Publisher, UploadedMedia, Visibility};
+use crate::model::Token;
+use async_session::async_trait;
+use reqwest::multipart;
+use serde::{Deserialize, Serialize};
+use snafu::prelude::*;
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum Error {
+    MisskeyNetwork {
+        source: reqwest::Error,
+    },
+    DecodeMisskeyResponse {
+        source: reqwest::Error,
+    },
+    #[snafu(display("Misskey responded with {status}: {code} {message}"))]
+    MisskeyApi {
+        status: u16,
+        code: String,
+        message: String,
+    },
+    // トークンが無効になったか、必要な権限がない
+    #[snafu(display("Misskey rejected the access token: {message}"))]
+    Unauthorized {
+        message: String,
+    },
+    #[snafu(display("Misskey rate limit exceeded"))]
+    RateLimited,
+    #[snafu(display("MiAuth session is not authorized"))]
+    MiAuthDenied,
+}
+
+// 投稿とドライブへのアップロード、連携したアカウントの確認に必要な権限
+pub const PERMISSIONS: &str = "read:account,write:notes,write:drive";
+
+// ノートの本文の上限（maxNoteTextLength）の既定値
+const MAX_CHARACTERS: usize = 3000;
+
+// ドライブのファイルに有効期限はないが、ユーザーがドライブから削除してもわからないので、
+// 使い回すのはこの期間だけにする
+const DRIVE_FILE_REUSE_SECS: u64 = 7 * 24 * 60 * 60;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MisskeyUser {
+    pub id: String,
+    pub username: String,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MiAuthResponse {
+    ok: bool,
+    token: Option<String>,
+    user: Option<MisskeyUser>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ErrorBody,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorBody {
+    #[serde(default)]
+    code: String,
+    #[serde(default)]
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DriveFile {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CreatedNote {
+    created_note: Note,
+}
+
+#[derive(Debug, Deserialize)]
+struct Note {
+    id: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct NoteParams<'a> {
+    i: &'a str,
+    text: String,
+    visibility: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cw: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    file_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_id: Option<String>,
+}
+
+// Misskeyの公開範囲の名前に合わせる
+fn visibility(visibility: Visibility) -> &'static str {
+    match visibility {
+        Visibility::Public => "public",
+        Visibility::Unlisted => "home",
+        Visibility::Private => "followers",
+        // 宛先のないspecifiedのノートは誰にも見えないので、フォロワー限定にする
+        Visibility::Direct => "followers",
+    }
+}
+
+// エラーの応答を、再試行やトークンの再発行の判断に使える種類に分ける
+async fn check(resp: reqwest::Response) -> Result<reqwest::Response, Error> {
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(resp);
+    }
+
+    let error = match resp.json::<ErrorResponse>().await {
+        Ok(resp) => resp.error,
+        Err(_) => ErrorBody {
+            code: String::new(),
+            message: status.canonical_reason().unwrap_or_default().to_string(),
+        },
+    };
+
+    match (status.as_u16(), error.code.as_str()) {
+        (429, _) | (_, "RATE_LIMIT_EXCEEDED") => Err(Error::RateLimited),
+        (401, _) | (403, _) | (_, "CREDENTIAL_REQUIRED") | (_, "AUTHENTICATION_FAILED") => {
+            Err(Error::Unauthorized {
+                message: error.message,
+            })
+        }
+        (status, _) => Err(Error::MisskeyApi {
+            status,
+            code: error.code,
+            message: error.message,
+        }),
+    }
+}
+
+async fn decode<T>(resp: reqwest::Response) -> Result<T, Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    check(resp)
+        .await?
+        .json::<T>()
+        .await
+        .context(DecodeMisskeyResponseSnafu)
+}
+
+// MiAuthの認可画面のURL。sessionは連携のたびに新しく作ったUUID
+pub fn authorize_url(instance: &str, session: &str, callback: &str) -> String {
+    let mut url = super::instance_url(instance, &format!("/miauth/{}", session));
+    url.query_pairs_mut()
+        .append_pair("name", "Still Bott")
+        .append_pair("callback", callback)
+        .append_pair("permission", PERMISSIONS);
+
+    url.to_string()
+}
+
+// 認可されたセッションのアクセストークンと、連携したアカウントを受け取る
+// Misskeyのアクセストークンには有効期限がない
+pub async fn check_session(instance: &str, session: &str) -> Result<(String, MisskeyUser), Error> {
+    let client = reqwest::Client::new();
+    let resp = client
+        .post(format!("{}/api/miauth/{}/check", instance, session))
+        .json(&serde_json::json!({}))
+        .send()
+        .await
+        .context(MisskeyNetworkSnafu)?;
+
+    match decode::<MiAuthResponse>(resp).await? {
+        MiAuthResponse {
+            ok: true,
+            token: Some(token),
+            user: Some(user),
+        } => Ok((token, user)),
+        _ => Err(Error::MiAuthDenied),
+    }
+}
+
+pub struct MisskeyPublisher {
+    instance: String,
+    token: Token,
+    options: PostOptions,
+    client: reqwest::Client,
+}
+
+impl MisskeyPublisher {
+    pub fn new(instance: String, token: Token, options: PostOptions) -> Self {
+        MisskeyPublisher {
+            instance,
+            token,
+            options,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn url(&self, endpoint: &str) -> String {
+        format!("{}/api/{}", self.instance, endpoint)
+    }
+
+    async fn call(
+        &self,
+        endpoint: &str,
+        body: serde_json::Value,
+    ) -> Result<reqwest::Response, Error> {
+        let mut body = body;
+        body["i"] = serde_json::Value::String(self.token.access_token.clone());
+
+        let resp = self
+            .client
+            .post(self.url(endpoint))
+            .json(&body)
+            .send()
+            .await
+            .context(MisskeyNetworkSnafu)?;
+
+        check(resp).await
+    }
+}
+
+#[async_trait]
+impl Publisher for MisskeyPublisher {
+    fn account_id(&self) -> &str {
+        &self.token.id
+    }
+
+    fn validate(&self, text: &str) -> Result<(), crate::error::Error> {
+        super::validate_length(text, text.chars().count(), MAX_CHARACTERS)
+    }
+
+    // ドライブのファイルは別のノートにも添付できるので、投稿のたびにアップロードしてドライブを埋めないようにする
+    // ノートに添付したファイルを削除するとノートからも消えるので、投稿した後も削除しない
+    fn reuses_media(&self) -> bool {
+        true
+    }
+
+    async fn upload_media(
+        &self,
+        bytes: Vec<u8>,
+        file_name: String,
+        alt_text: Option<&str>,
+    ) -> Result<UploadedMedia, super::Error> {
+        let content_type = crate::media::content_type(&bytes).unwrap_or("application/octet-stream");
+        let file = multipart::Part::bytes(bytes)
+            .file_name(file_name.clone())
+            .mime_str(content_type)
+            .unwrap();
+
+        let form = multipart::Form::new()
+            .text("i", self.token.access_token.clone())
+            .text("name", file_name)
+            .part("file", file);
+        // Misskeyでは、ファイルのコメントが代替テキストとして表示される
+        let form = match alt_text {
+            Some(alt_text) => form.text("comment", alt_text.to_string()),
+            None => form,
+        };
+
+        let resp = self
+            .client
+            .post(self.url("drive/files/create"))
+            .multipart(form)
+            .send()
+            .await
+            .context(MisskeyNetworkSnafu)
+            .context(MisskeySnafu)?;
+
+        let file = decode::<DriveFile>(resp).await.context(MisskeySnafu)?;
+
+        Ok(UploadedMedia {
+            id: file.id,
+            expires_after_secs: Some(DRIVE_FILE_REUSE_SECS),
+        })
+    }
+
+    async fn describe_media(&self, media_id: &str, alt_text: &str) -> Result<(), super::Error> {
+        self.call(
+            "drive/files/update",
+            serde_json::json!({ "fileId": media_id, "comment": alt_text }),
+        )
+        .await
+        .map(|_| ())
+        .context(MisskeySnafu)
+    }
+
+    async fn post(&self, post: Post) -> Result<String, super::Error> {
+        let params = NoteParams {
+            i: &self.token.access_token,
+            text: post.text,
+            visibility: visibility(self.options.visibility),
+            cw: self.options.content_warning.clone(),
+            file_ids: post.media_ids,
+            reply_id: post.reply_to,
+        };
+
+        let resp = self
+            .client
+            .post(self.url("notes/create"))
+            .json(&params)
+            .send()
+            .await
+            .context(MisskeyNetworkSnafu)
+            .context(MisskeySnafu)?;
+
+        decode::<CreatedNote>(resp)
+            .await
+            .map(|created| created.created_note.id)
+            .context(MisskeySnafu)
+    }
+
+    async fn delete(&self, post_id: &str) -> Result<(), super::Error> {
+        self.call("notes/delete", serde_json::json!({ "noteId": post_id }))
+            .await
+            .map(|_| ())
+            .context(MisskeySnafu)
+    }
+}
+
+#[tokio::test]
+async fn test_misskey_publisher() {
+    use axum::{
+        extract::{Multipart, Path, State},
+        http::StatusCode,
+        routing::post,
+        Json, Router,
+    };
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Recorded {
+        tokens: Vec<String>,
+        files: Vec<(String, Option<String>)>,
+        comments: Vec<(String, String)>,
+        notes: Vec<serde_json::Value>,
+        deleted: Vec<String>,
+    }
+
+    type Shared = Arc<Mutex<Recorded>>;
+
+    fn error(status: StatusCode, code: &str) -> (StatusCode, Json<serde_json::Value>) {
+        (
+            status,
+            Json(serde_json::json!({
+                "error": { "message": code.to_lowercase(), "code": code, "id": "x", "kind": "client" }
+            })),
+        )
+    }
+
+    async fn miauth_check(Path(session): Path<String>) -> Json<serde_json::Value> {
+        if session == "authorized" {
+            Json(serde_json::json!({
+                "ok": true,
+                "token": "access",
+                "user": { "id": "9abc", "username": "bott", "name": null },
+            }))
+        } else {
+            Json(serde_json::json!({ "ok": false }))
+        }
+    }
+
+    async fn files_create(
+        State(state): State<Shared>,
+        mut multipart: Multipart,
+    ) -> Json<serde_json::Value> {
+        let mut name = String::new();
+        let mut comment = None;
+        while let Some(field) = multipart.next_field().await.unwrap() {
+            match field.name().unwrap_or_default().to_string().as_str() {
+                "i" => {
+                    let token = field.text().await.unwrap();
+                    state.lock().unwrap().tokens.push(token);
+                }
+                "name" => name = field.text().await.unwrap(),
+                "comment" => comment = Some(field.text().await.unwrap()),
+                _ => {}
+            }
+        }
+
+        let mut state = state.lock().unwrap();
+        state.files.push((name, comment));
+        Json(serde_json::json!({ "id": format!("file{}", state.files.len()) }))
+    }
+
+    async fn files_update(
+        State(state): State<Shared>,
+        Json(body): Json<serde_json::Value>,
+    ) -> Json<serde_json::Value> {
+        let mut state = state.lock().unwrap();
+        state.tokens.push(body["i"].as_str().unwrap().to_string());
+        state.comments.push((
+            body["fileId"].as_str().unwrap().to_string(),
+            body["comment"].as_str().unwrap().to_string(),
+        ));
+        Json(serde_json::json!({ "id": body["fileId"] }))
+    }
+
+    async fn notes_create(
+        State(state): State<Shared>,
+        Json(body): Json<serde_json::Value>,
+    ) -> (StatusCode, Json<serde_json::Value>) {
+        match body["text"].as_str().unwrap_or_default() {
+            "" => return error(StatusCode::BAD_REQUEST, "CONTENT_REQUIRED"),
+            "rate limited" => return error(StatusCode::TOO_MANY_REQUESTS, "RATE_LIMIT_EXCEEDED"),
+            "revoked" => return error(StatusCode::UNAUTHORIZED, "AUTHENTICATION_FAILED"),
+            _ => {}
+        }
+
+        let mut state = state.lock().unwrap();
+        state.tokens.push(body["i"].as_str().unwrap().to_string());
+        state.notes.push(body);
+        let id = format!("note{}", state.notes.len());
+        (
+            StatusCode::OK,
+            Json(serde_json::json!({ "createdNote": { "id": id } })),
+        )
+    }
+
+    async fn notes_delete(
+        State(state): State<Shared>,
+        Json(body): Json<serde_json::Value>,
+    ) -> StatusCode {
+        let mut state = state.lock().unwrap();
+        state.tokens.push(body["i"].as_str().unwrap().to_string());
+        state
+            .deleted
+            .push(body["noteId"].as_str().unwrap().to_string());
+        StatusCode::NO_CONTENT
+    }
+
+    let state = Shared::default();
+    let app = Router::new()
+        .route("/api/miauth/:session/check", post(miauth_check))
+        .route("/api/drive/files/create", post(files_create))
+        .route("/api/drive/files/update", post(files_update))
+        .route("/api/notes/create", post(notes_create))
+        .route("/api/notes/delete", post(notes_delete))
+        .with_state(state.clone());
+
+    let instance = super::stand_in_server(app);
+
+    // MiAuthでのアクセストークンの受け取り
+    let callback = "https://bott.example.com/auth/misskey/authorized";
+    let url = authorize_url(&instance, "authorized", callback);
+    assert!(url.starts_with(&format!("{}/miauth/authorized?", instance)));
+    assert!(url.contains("permission=read%3Aaccount%2Cwrite%3Anotes%2Cwrite%3Adrive"));
+
+    assert!(matches!(
+        check_session(&instance, "denied").await,
+        Err(Error::MiAuthDenied)
+    ));
+    let (access_token, user) = check_session(&instance, "authorized").await.unwrap();
+    assert_eq!(user.username, "bott");
+
+    let token = Token {
+        id: super::account_id(super::Provider::Misskey, &instance, &user.id),
+        access_token,
+        refresh_token: None,
+        issued_at: time::OffsetDateTime::now_utc(),
+        expires_in: None,
+    };
+    let account = super::Account {
+        provider: super::Provider::Misskey,
+        instance: Some(instance.clone()),
+        token,
+    };
+    let options = PostOptions::new(Some("unlisted"), Some("ネタバレ".to_string()));
+    let publisher = super::from_account(account, &options).unwrap();
+
+    // 画像を添付したノートと、それへの返信
+    let mut png = std::io::Cursor::new(Vec::new());
+    image::DynamicImage::ImageRgb8(image::RgbImage::new(2, 2))
+        .write_to(&mut png, image::ImageOutputFormat::Png)
+        .unwrap();
+    let uploaded = publisher
+        .upload_media(png.into_inner(), "logo.png".to_string(), Some("ロゴ"))
+        .await
+        .unwrap();
+    assert_eq!(uploaded.id, "file1");
+    assert_eq!(uploaded.expires_after_secs, Some(DRIVE_FILE_REUSE_SECS));
+    assert!(publisher.reuses_media());
+    publisher
+        .describe_media(&uploaded.id, "新しいロゴ")
+        .await
+        .unwrap();
+
+    let first = publisher
+        .post(Post {
+            text: "1/2".to_string(),
+            media_ids: vec![uploaded.id],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let second = publisher
+        .post(Post {
+            text: "2/2".to_string(),
+            reply_to: Some(first.clone()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    publisher.delete(&second).await.unwrap();
+
+    // エラーの応答は、種類ごとに分けて返す
+    let post_error = |text: &str| {
+        let post = Post {
+            text: text.to_string(),
+            ..Default::default()
+        };
+        let publisher = &publisher;
+        async move {
+            match publisher.post(post).await {
+                Err(super::Error::Misskey { source }) => source,
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+    };
+    assert!(matches!(
+        post_error("rate limited").await,
+        Error::RateLimited
+    ));
+    assert!(matches!(
+        post_error("revoked").await,
+        Error::Unauthorized { .. }
+    ));
+    match post_error("").await {
+        Error::MisskeyApi { status, code, .. } => {
+            assert_eq!(status, 400);
+            assert_eq!(code, "CONTENT_REQUIRED");
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+
+    let recorded = state.lock().unwrap();
+    assert_eq!(
+        recorded.notes,
+        vec![
+            serde_json::json!({
+                "i": "access",
+                "text": "1/2",
+                "visibility": "home",
+                "cw": "ネタバレ",
+                "fileIds": ["file1"],
+            }),
+            serde_json::json!({
+                "i": "access",
+                "text": "2/2",
+                "visibility": "home",
+                "cw": "ネタバレ",
+                "replyId": "note1",
+            }),
+        ]
+    );
+    assert_eq!(
+        recorded.files,
+        vec![("logo.png".to_string(), Some("ロゴ".to_string()))]
+    );
+    assert_eq!(
+        recorded.comments,
+        vec![("file1".to_string(), "新しいロゴ".to_string())]
+    );
+    assert_eq!(recorded.deleted, vec!["note2".to_string()]);
+    assert!(recorded.tokens.iter().all(|token| token == "access"));
+
+    assert_eq!(visibility(Visibility::Direct), "followers");
+
+    assert!(publisher.validate("こんにちは").is_ok());
+    assert!(publisher
+        .validate(&"あ".repeat(MAX_CHARACTERS + 1))
+        .is_err());
+}
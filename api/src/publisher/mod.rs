@@ -0,0 +1,329 @@
+// 連携したアカウントへの投稿を、投稿先のサービスの違いを意識せずに行う
+// スレッドの組み立てや透かし、メディアIDの使い回しはmodel.rsで行い、ここでは送るだけにする
+pub mod bluesky;
+pub mod mastodon;
+pub mod misskey;
+mod twitter;
+
+pub use self::bluesky::BlueskyPublisher;
+pub use self::mastodon::MastodonPublisher;
+pub use self::misskey::MisskeyPublisher;
+pub use self::twitter::TwitterPublisher;
+
+use crate::media::MediaKind;
+use crate::model::Token;
+use async_session::async_trait;
+use serde::Serialize;
+use snafu::prelude::*;
+use strum_macros::{Display, EnumString};
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum Error {
+    #[snafu(display("{source}"))]
+    Twitter {
+        source: crate::twitter::Error,
+    },
+    Mastodon {
+        source: mastodon::Error,
+    },
+    Bluesky {
+        source: bluesky::Error,
+    },
+    Misskey {
+        source: misskey::Error,
+    },
+    Hasura {
+        source: crate::gq::error::HasuraError,
+    },
+    #[snafu(display("Instance is not set for account {account_id}"))]
+    InstanceNotFound {
+        account_id: String,
+    },
+    #[snafu(display("Invalid account id {account_id}"))]
+    InvalidAccountId {
+        account_id: String,
+    },
+}
+
+// session.providerに保存する投稿先のサービス
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum Provider {
+    #[default]
+    Twitter,
+    Mastodon,
+    Bluesky,
+    Misskey,
+}
+
+// 投稿の公開範囲。公開範囲のないTwitterとBlueskyでは無視する
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Display, EnumString, Serialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    #[default]
+    Public,
+    Unlisted,
+    Private,
+    Direct,
+}
+
+// タスクごとに指定する投稿の設定
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PostOptions {
+    pub visibility: Visibility,
+    // 本文を折りたたんで表示するときの注意書き（CW）
+    pub content_warning: Option<String>,
+}
+
+impl PostOptions {
+    pub fn new(visibility: Option<&str>, content_warning: Option<String>) -> Self {
+        PostOptions {
+            visibility: visibility
+                .and_then(|visibility| visibility.parse().ok())
+                .unwrap_or_default(),
+            content_warning: content_warning.filter(|cw| !cw.trim().is_empty()),
+        }
+    }
+}
+
+// 連携したアカウントと、投稿に使うトークン
+#[derive(Debug, Clone)]
+pub struct Account {
+    pub provider: Provider,
+    // MastodonなどのインスタンスのURL
+    pub instance: Option<String>,
+    pub token: Token,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Post {
+    pub text: String,
+    // upload_mediaで返されたID
+    pub media_ids: Vec<String>,
+    // 返信先の投稿のID
+    pub reply_to: Option<String>,
+    // 記事のリンクカード。link_cardsがtrueのサービスでだけ使う
+    pub card: Option<LinkCard>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LinkCard {
+    pub uri: String,
+    pub title: String,
+    pub description: String,
+    // upload_mediaで返されたサムネイルのID
+    pub thumb: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct UploadedMedia {
+    pub id: String,
+    // 別の投稿に添付できる残り秒数
+    pub expires_after_secs: Option<u64>,
+}
+
+#[async_trait]
+pub trait Publisher: Send + Sync {
+    // 投稿に使うアカウントのID（メディアIDのキャッシュのキーにも使う）
+    fn account_id(&self) -> &str;
+
+    // アップロードしたメディアを、有効期限内なら別の投稿にも添付できるかどうか
+    fn reuses_media(&self) -> bool {
+        false
+    }
+
+    // URLからカードを作らないサービスでは、投稿にリンクカードを埋め込む
+    fn link_cards(&self) -> bool {
+        false
+    }
+
+    // 確実に拒否される投稿は、APIを呼ばずにエラーにする
+    fn validate(&self, text: &str) -> Result<(), crate::error::Error>;
+
+    // 添付できない種類のメディアは、アップロードする前にエラーにする
+    fn validate_media(&self, _kinds: &[MediaKind]) -> Result<(), crate::error::Error> {
+        Ok(())
+    }
+
+    async fn upload_media(
+        &self,
+        bytes: Vec<u8>,
+        file_name: String,
+        alt_text: Option<&str>,
+    ) -> Result<UploadedMedia, Error>;
+
+    // アップロード済みのメディアの代替テキストを設定し直す
+    async fn describe_media(&self, media_id: &str, alt_text: &str) -> Result<(), Error>;
+
+    // 投稿したIDを返す
+    async fn post(&self, post: Post) -> Result<String, Error>;
+
+    async fn delete(&self, post_id: &str) -> Result<(), Error>;
+}
+
+// タスクの投稿先のアカウントのトークンを読み込み、サービスに合わせた投稿の方法を返す
+pub async fn connect(account_id: &str, options: &PostOptions) -> Result<Box<dyn Publisher>, Error> {
+    let account =
+        crate::gq::load_session::load_session(account_id.to_string(), crate::state::oauth_client())
+            .await
+            .context(HasuraSnafu)?;
+
+    from_account(account, options)
+}
+
+pub fn from_account(account: Account, options: &PostOptions) -> Result<Box<dyn Publisher>, Error> {
+    match account.provider {
+        Provider::Twitter => Ok(Box::new(TwitterPublisher::new(account.token))),
+        Provider::Mastodon => {
+            let instance = account.instance.context(InstanceNotFoundSnafu {
+                account_id: account.token.id.clone(),
+            })?;
+
+            Ok(Box::new(MastodonPublisher::new(
+                instance,
+                account.token,
+                options.clone(),
+            )))
+        }
+        Provider::Bluesky => {
+            let service = account.instance.context(InstanceNotFoundSnafu {
+                account_id: account.token.id.clone(),
+            })?;
+            let did = remote_id(&account.token.id)
+                .context(InvalidAccountIdSnafu {
+                    account_id: account.token.id.clone(),
+                })?
+                .to_string();
+
+            Ok(Box::new(BlueskyPublisher::new(service, did, account.token)))
+        }
+        Provider::Misskey => {
+            let instance = account.instance.context(InstanceNotFoundSnafu {
+                account_id: account.token.id.clone(),
+            })?;
+
+            Ok(Box::new(MisskeyPublisher::new(
+                instance,
+                account.token,
+                options.clone(),
+            )))
+        }
+    }
+}
+
+// Twitter以外のアカウントのID。TwitterのユーザーIDと重ならないように、サービスとインスタンスを付ける
+pub fn account_id(provider: Provider, instance: &str, remote_id: &str) -> String {
+    let host = reqwest::Url::parse(instance)
+        .ok()
+        .and_then(|url| {
+            url.host_str().map(|host| match url.port() {
+                Some(port) => format!("{}:{}", host, port),
+                None => host.to_string(),
+            })
+        })
+        .unwrap_or_else(|| instance.to_string());
+
+    format!("{}:{}@{}", provider, remote_id, host)
+}
+
+// account_idで付けたサービスとインスタンスを取り除いた、サービス上のID
+pub fn remote_id(account_id: &str) -> Option<&str> {
+    let (_, rest) = account_id.split_once(':')?;
+    let (remote_id, _) = rest.rsplit_once('@')?;
+    Some(remote_id)
+}
+
+// 空の投稿と、文字数の上限を超える投稿をエラーにする
+// lengthは、サービスごとの数え方で数えた本文の長さ
+pub fn validate_length(text: &str, length: usize, max: usize) -> Result<(), crate::error::Error> {
+    if text.trim().is_empty() {
+        Err(crate::error::Error::InvalidTweet)
+    } else if length > max {
+        Err(crate::error::Error::PostTooLong { length, max })
+    } else {
+        Ok(())
+    }
+}
+
+// インスタンスのURLにパスをつなげる
+pub fn instance_url(instance: &str, path: &str) -> reqwest::Url {
+    reqwest::Url::parse(&format!("{}{}", instance, path))
+        .expect("インスタンスのURLは保存する前にnormalize_instanceで確認しています")
+}
+
+// 連携先のサービスの代わりに、テストの中で受け取ったリクエストを記録して応答を返すサーバーを起動する
+// 空いているポートで待ち受けて、そのURLを返す
+#[cfg(test)]
+pub fn stand_in_server(app: axum::Router) -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service()),
+    );
+
+    url
+}
+
+// 入力されたインスタンスを https://example.com の形式にそろえる
+// サーバーから接続するので、httpsで公開されたホストだけを受け付ける
+pub fn normalize_instance(instance: &str) -> Option<String> {
+    let instance = instance.trim();
+    let instance = if instance.contains("://") {
+        instance.to_string()
+    } else {
+        format!("https://{}", instance)
+    };
+
+    let url = reqwest::Url::parse(&instance).ok()?;
+    match url.scheme() {
+        "https" if crate::net::is_public_url(&url) => Some(url.origin().ascii_serialization()),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_account_id() {
+    assert_eq!(
+        account_id(Provider::Mastodon, "https://mastodon.social", "109"),
+        "mastodon:109@mastodon.social"
+    );
+    assert_eq!(
+        account_id(Provider::Mastodon, "http://127.0.0.1:3000", "1"),
+        "mastodon:1@127.0.0.1:3000"
+    );
+    assert_eq!(
+        remote_id("bluesky:did:plc:abc@bsky.social"),
+        Some("did:plc:abc")
+    );
+    assert_eq!(remote_id("751625934894084097"), None);
+
+    assert_eq!(
+        normalize_instance(" mastodon.social/about "),
+        Some("https://mastodon.social".to_string())
+    );
+    assert_eq!(
+        normalize_instance("https://Mastodon.Social/"),
+        Some("https://mastodon.social".to_string())
+    );
+    assert_eq!(normalize_instance("ftp://mastodon.social"), None);
+    assert_eq!(normalize_instance("http://mastodon.social"), None);
+    assert_eq!(normalize_instance("https://127.0.0.1:3000"), None);
+    assert_eq!(normalize_instance("https://[::1]"), None);
+    assert_eq!(normalize_instance("https://169.254.169.254"), None);
+    assert_eq!(normalize_instance("https://192.168.1.10"), None);
+    assert_eq!(normalize_instance("localhost:3000"), None);
+
+    assert_eq!(
+        PostOptions::new(Some("unlisted"), None).visibility,
+        Visibility::Unlisted
+    );
+    assert_eq!(PostOptions::new(None, None).visibility, Visibility::Public);
+    assert_eq!(
+        PostOptions::new(None, Some(" ".to_string())).content_warning,
+        None
+    );
+}
@@ -0,0 +1,74 @@
+use super::{Post, Publisher, TwitterSnafu, UploadedMedia};
+use crate::model::{Token, TweetJson};
+use async_session::async_trait;
+use snafu::prelude::*;
+
+pub struct TwitterPublisher {
+    token: Token,
+}
+
+impl TwitterPublisher {
+    pub fn new(token: Token) -> Self {
+        TwitterPublisher { token }
+    }
+}
+
+#[async_trait]
+impl Publisher for TwitterPublisher {
+    fn account_id(&self) -> &str {
+        &self.token.id
+    }
+
+    // 有効期限内のメディアIDは、別のツイートにも添付できる
+    fn reuses_media(&self) -> bool {
+        true
+    }
+
+    fn validate(&self, text: &str) -> Result<(), crate::error::Error> {
+        crate::model::validate_tweet(text)
+    }
+
+    async fn upload_media(
+        &self,
+        bytes: Vec<u8>,
+        file_name: String,
+        alt_text: Option<&str>,
+    ) -> Result<UploadedMedia, super::Error> {
+        let uploaded = crate::twitter::twitter_v2_media_upload_bytes(&self.token, bytes, file_name)
+            .await
+            .context(TwitterSnafu)?;
+
+        if let Some(alt_text) = alt_text {
+            self.describe_media(&uploaded.id, alt_text).await?;
+        }
+
+        Ok(UploadedMedia {
+            id: uploaded.id,
+            expires_after_secs: uploaded.expires_after_secs,
+        })
+    }
+
+    async fn describe_media(&self, media_id: &str, alt_text: &str) -> Result<(), super::Error> {
+        crate::twitter::twitter_v2_media_metadata(&self.token, media_id, alt_text)
+            .await
+            .context(TwitterSnafu)
+    }
+
+    async fn post(&self, post: Post) -> Result<String, super::Error> {
+        let json = TweetJson::new(post.text, post.media_ids);
+        let json = match post.reply_to {
+            Some(tweet_id) => json.reply_to(tweet_id),
+            None => json,
+        };
+
+        crate::twitter::send_tweet_impl(self.token.clone(), serde_json::to_value(&json).unwrap())
+            .await
+            .context(TwitterSnafu)
+    }
+
+    async fn delete(&self, post_id: &str) -> Result<(), super::Error> {
+        crate::twitter::delete_tweet_impl(self.token.clone(), post_id.to_string())
+            .await
+            .context(TwitterSnafu)
+    }
+}
@@ -7,6 +7,7 @@ use axum::{
     Json, Router,
 };
 
+use crate::storage::store;
 use crate::twitter::TwitterUser;
 use http::StatusCode;
 use mail_send::{mail_builder::MessageBuilder, SmtpClientBuilder};
@@ -24,6 +25,19 @@ pub fn create_route() -> Router<crate::state::AppState> {
         .route("/api/v1/confirm_email", post(confirm_email))
         .route("/api/v1/minio_get_upload_url", post(minio_get_upload_url))
         .route("/api/v1/save_media", post(save_media))
+        .route("/api/v1/validate_template", post(validate_template))
+        .route("/api/v1/validate_feed", post(validate_feed))
+        .route("/api/v1/validate_rss_filter", post(validate_rss_filter))
+        .route("/api/v1/validate_tweet", post(validate_tweet))
+        .route(
+            "/api/v1/validate_message_media",
+            post(validate_message_media),
+        )
+        .route("/api/v1/get_thumbnail_urls", post(get_thumbnail_urls))
+        .route("/api/v1/get_storage_usage", post(get_storage_usage))
+        .route("/api/v1/find_similar_media", post(find_similar_media))
+        .route("/api/v1/events/user_role_changed", post(user_role_changed))
+        .route("/api/v1/events/watermark_changed", post(watermark_changed))
         .layer(middleware::from_fn(action_auth_middleware))
 }
 
@@ -95,6 +109,23 @@ struct SessionVariables {
     x_hasura_role: String,
 }
 
+// Hasuraのイベントトリガーから送られるデータ
+#[derive(Deserialize, Debug)]
+struct EventPayload<T> {
+    event: Event<T>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Event<T> {
+    data: EventData<T>,
+}
+
+#[derive(Deserialize, Debug)]
+struct EventData<T> {
+    old: Option<T>,
+    new: Option<T>,
+}
+
 #[derive(Serialize, Debug)]
 struct ErrorResponse {
     message: String,
@@ -140,6 +171,24 @@ impl IntoResponse for ApiError {
                 self.to_string(),
             ),
 
+            ApiError::InvalidMedia { source } => (source.to_string(), String::from("InvalidMedia")),
+            ApiError::StorageQuotaExceeded { used, quota } => (
+                format!(
+                    "メディアを保存できる容量の上限を超えています（{}MB/{}MB）",
+                    used / 1024 / 1024,
+                    quota / 1024 / 1024
+                ),
+                String::from("StorageQuotaExceeded"),
+            ),
+            ApiError::InvalidThumbnailSize { size } => (
+                format!(
+                    "サムネイルの大きさは{:?}のいずれかです（{}）",
+                    crate::media::THUMBNAIL_SIZES,
+                    size
+                ),
+                self.to_string(),
+            ),
+
             ApiError::Generic { message, source } => (message, format!("{:?}", source)),
             _ => (self.to_string(), self.to_string()),
         };
@@ -176,7 +225,7 @@ enum ApiError {
     },
 
     FailedGetPresignedPost {
-        source: s3::error::S3Error,
+        source: crate::storage::Error,
     },
 
     FailedToSaveMedia {
@@ -193,6 +242,31 @@ enum ApiError {
 
     UpdateEmailProhibited,
 
+    InvalidMedia {
+        source: crate::media::Error,
+    },
+
+    InvalidThumbnailSize {
+        size: u32,
+    },
+
+    StorageQuotaExceeded {
+        used: u64,
+        quota: u64,
+    },
+
+    StorageUsage {
+        source: crate::storage::Error,
+    },
+
+    Storage {
+        source: crate::storage::Error,
+    },
+
+    Thumbnail {
+        source: crate::thumbnail::Error,
+    },
+
     #[snafu(whatever, display("{message}"))]
     Generic {
         message: String,
@@ -351,8 +425,14 @@ fn can_confirm_email(issued_at: OffsetDateTime, now: OffsetDateTime) -> bool {
     now - issued_at <= time::Duration::minutes(10)
 }
 
+// アップロードURLの有効期限（秒）
+const UPLOAD_URL_EXPIRY_SECS: u32 = 15 * 60;
+
 #[derive(Deserialize, Debug)]
-struct MinioGetUploadUrlInput {}
+struct MinioGetUploadUrlInput {
+    content_type: String,
+    size: u64,
+}
 
 #[derive(Serialize, Debug)]
 struct MinioGetUploadUrlOutput {
@@ -360,50 +440,121 @@ struct MinioGetUploadUrlOutput {
     #[serde(rename = "mediaId")]
     media_id: uuid::Uuid,
 }
+
+// アップロードするファイルの形式とサイズを、URLを発行する前に確認する
+// presigned PUTではサイズを制限できないので、最終的な確認はsave_mediaで行う
 async fn minio_get_upload_url(
     action: Json<ActionPayload<MinioGetUploadUrlInput>>,
 ) -> Result<Json<MinioGetUploadUrlOutput>, ApiError> {
-    let bucket_name = action.session_variables.x_hasura_user_id.clone();
-    let bucket = get_or_create_bucket(&bucket_name).await?;
+    let user_id = action.session_variables.x_hasura_user_id.clone();
+    let args = &action.input.args;
+    let size = args.size;
+
+    crate::media::validate_upload_request(&args.content_type, size as usize)
+        .context(InvalidMediaSnafu)?;
+
+    let limits = plan_limits(user_id.clone()).await?;
+    let used = store().usage(&user_id).await.context(StorageUsageSnafu)?;
+    ensure!(
+        limits.fits_storage_quota(used, size),
+        StorageQuotaExceededSnafu {
+            used,
+            quota: limits.storage_quota_bytes,
+        }
+    );
+
     let media_id = uuid::Uuid::new_v4();
-    let url = bucket
-        .presign_put(media_id.to_string(), 86400, None)
+    let url = store()
+        .presign_put(
+            &user_id,
+            &media_id.to_string(),
+            UPLOAD_URL_EXPIRY_SECS,
+            Some(&args.content_type),
+        )
+        .await
         .context(FailedGetPresignedPostSnafu)?;
 
     Ok(Json(MinioGetUploadUrlOutput { url, media_id }))
 }
 
-async fn get_or_create_bucket(bucket_name: &str) -> Result<s3::Bucket, ApiError> {
-    let region = s3::region::Region::Custom {
-        region: "ap-northeast-1".to_owned(),
-        endpoint: crate::config::CONFIG.minio_endpoint.clone(),
-    };
+#[derive(Debug, Serialize)]
+struct StorageUsageOutput {
+    used_bytes: u64,
+    quota_bytes: u64,
+}
 
-    let credentials =
-        s3::creds::Credentials::default().whatever_context("Failed to generate S3 Credentials")?;
+// メディアの保存に使っている容量と、プランの上限を返す
+async fn get_storage_usage(
+    payload: Json<ActionPayload<BooleanInput>>,
+) -> Result<Json<StorageUsageOutput>, ApiError> {
+    let user_id = payload.session_variables.x_hasura_user_id.clone();
 
-    let bucket = s3::bucket::Bucket::new(bucket_name, region.clone(), credentials.clone())
-        .whatever_context("Failed generate S3 Bucket")?
-        .with_path_style();
+    let limits = plan_limits(user_id.clone()).await?;
+    let used = store().usage(&user_id).await.context(StorageUsageSnafu)?;
 
-    // もし既存のバケットが存在しない場合には新たに作成する
-    if bucket.head_object("/").await.is_err() {
-        let config = s3::BucketConfiguration::default();
-        match s3::bucket::Bucket::create_with_path_style(bucket_name, region, credentials, config)
-            .await
-        {
-            Ok(resp) => {
-                tracing::info!("Bucket {} created", bucket_name);
-            }
-            Err(err) => {
-                tracing::error!("Failed to create bucket {}", bucket_name);
-                tracing::error!("{:?}", err);
-                whatever!("Failed to create bucke {}", bucket_name);
-            }
-        };
-    }
+    Ok(Json(StorageUsageOutput {
+        used_bytes: used,
+        quota_bytes: limits.storage_quota_bytes,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct FindSimilarMediaInput {
+    media_id: Option<uuid::Uuid>,
+    max_distance: Option<u32>,
+}
 
-    Ok(bucket)
+#[derive(Debug, Serialize)]
+struct SimilarMediaOutput {
+    media_id: uuid::Uuid,
+    similar_media_id: uuid::Uuid,
+    distance: u32,
+}
+
+// ライブラリを整理するために、似ている画像の組み合わせを近い順に返す
+// media_idを指定したときは、その画像と似ているものだけを返す
+async fn find_similar_media(
+    payload: Json<ActionPayload<FindSimilarMediaInput>>,
+) -> Result<Json<Vec<SimilarMediaOutput>>, ApiError> {
+    let user_id = payload.session_variables.x_hasura_user_id.clone();
+    let max_distance = payload
+        .input
+        .args
+        .max_distance
+        .unwrap_or(crate::media_dedup::DEFAULT_MAX_DISTANCE);
+
+    let hashes: Vec<(uuid::Uuid, u64)> = crate::gq::list_media_hashes::exec(user_id)
+        .await
+        .context(HasuraSnafu)?
+        .into_iter()
+        .filter_map(|(media_id, hash)| {
+            Some((media_id, crate::media_dedup::parse_perceptual_hash(&hash)?))
+        })
+        .collect();
+
+    let pairs =
+        crate::media_dedup::similar_pairs(&hashes, payload.input.args.media_id, max_distance);
+
+    Ok(Json(
+        pairs
+            .into_iter()
+            .map(|pair| SimilarMediaOutput {
+                media_id: pair.media_id,
+                similar_media_id: pair.similar_media_id,
+                distance: pair.distance,
+            })
+            .collect(),
+    ))
+}
+
+// JWTのロールはクライアントが選べるので、Hasuraに保存されたロールでプランの制限を決める
+async fn plan_limits(user_id: String) -> Result<crate::config::PlanLimits, ApiError> {
+    let user = crate::gq::get_self::get_self(user_id)
+        .await
+        .context(HasuraSnafu)?
+        .ok_or(ApiError::UserNotFound)?;
+
+    Ok(crate::config::CONFIG.plan_limits(&user.role))
 }
 
 #[derive(Deserialize, Debug)]
@@ -421,21 +572,22 @@ struct DeleteImageOutput {
 async fn delete_image(
     payload: Json<ActionPayload<DeleteImageInput>>,
 ) -> Result<Json<DeleteImageOutput>, ApiError> {
-    let bucket_name = payload.session_variables.x_hasura_user_id.clone();
-    let bucket = get_or_create_bucket(&bucket_name).await?;
+    let user_id = payload.session_variables.x_hasura_user_id.clone();
 
     // Ignore if
-    let status = bucket
-        .delete_object(payload.input.args.media_id.to_string())
-        .await
-        .map(|response| response.status_code());
+    let status = store()
+        .delete(&user_id, &payload.input.args.media_id.to_string())
+        .await;
     if status.is_err() {
         tracing::warn!(
-            "media {} was not found on minio, skipping",
+            "media {} was not found on the storage, skipping",
             payload.input.args.media_id.clone()
         );
     }
 
+    crate::thumbnail::delete(&user_id, payload.input.args.media_id).await;
+    crate::media_cache::invalidate(payload.input.args.media_id);
+
     crate::gq::delete_media::exec(payload.input.args.media_id.clone())
         .await
         .map(|_| {
@@ -460,49 +612,121 @@ struct SaveMediaOutput {
     thumbnail: String,
     #[serde(with = "time::serde::rfc3339")]
     uploaded_at: OffsetDateTime,
+    // 同じ内容のメディアがあったため、アップロードしたファイルの代わりに既存のメディアを返したかどうか
+    duplicate: bool,
 }
 
-// ここではmedia_idをパラメータとして受け取り、minioに保存された画像データを取得し、
+// ここではmedia_idをパラメータとして受け取り、ストレージに保存された画像データを取得し、
 // サムネイルを作成した上で、Hasuraのmediaテーブルにuser_idと紐づけて保存する。
 // 保存されたmediaを戻す
 async fn save_media(
     payload: Json<ActionPayload<SaveMediaInput>>,
 ) -> Result<Json<SaveMediaOutput>, ApiError> {
-    let bucket_name = payload.session_variables.x_hasura_user_id.clone();
-    let bucket = get_or_create_bucket(&bucket_name).await?;
+    let user_id = payload.session_variables.x_hasura_user_id.clone();
 
-    let object = bucket
-        .get_object(payload.input.args.media_id.to_string())
+    let bytes = store()
+        .get(&user_id, &payload.input.args.media_id.to_string())
         .await
         .whatever_context(format!(
-            "Failed to get the image `{}` from minio",
+            "Failed to get the image `{}` from the storage",
             payload.input.args.media_id
         ))?;
 
-    let image_original = image::io::Reader::new(std::io::Cursor::new(object.bytes()))
-        .with_guessed_format()
-        .whatever_context("Failed to read bytes as an image")?
-        .decode()
-        .whatever_context("Failed to decode as image")?;
+    // 投稿時に失敗しないように、Twitterに投稿できないファイルはここで拒否する
+    // 静止画は向きやサイズを整えたものに置き換えるので、Twitterの上限より大きくてもよい
+    let validated = crate::media::content_type(&bytes).and_then(|content_type| {
+        match crate::media::validate_upload_request(content_type, bytes.len())? {
+            crate::media::MediaKind::Image => crate::media::normalize_image(&bytes)
+                .map(|normalized| (normalized.content_type, Some(normalized))),
+            kind => crate::media::validate_upload(&bytes, kind).map(|_| (content_type, None)),
+        }
+    });
 
-    let image_thumb = image_original.thumbnail(240, 240).to_rgb8();
+    let (content_type, normalized) = match validated {
+        Ok(validated) => validated,
+        Err(source) => {
+            delete_rejected_media(&user_id, payload.input.args.media_id).await;
+            return Err(ApiError::InvalidMedia { source });
+        }
+    };
 
-    let mut bytes: Vec<u8> = Vec::new();
-    image_thumb
-        .write_to(
-            &mut std::io::Cursor::new(&mut bytes),
-            image::ImageOutputFormat::Jpeg(85),
-        )
-        .whatever_context("Failed to generate thumbnail")?;
+    // 保存するファイルと同じ内容のメディアが既にあれば、コピーを保存せずにそのメディアを返す
+    let content_hash = crate::media::content_hash(
+        normalized
+            .as_ref()
+            .map_or(&bytes[..], |normalized| &normalized.bytes[..]),
+    );
+    let existing = crate::gq::find_media_by_hash::exec(user_id.clone(), content_hash.clone())
+        .await
+        .context(HasuraSnafu)?;
+    if let Some(existing) = existing.filter(|media| media.id != payload.input.args.media_id) {
+        delete_rejected_media(&user_id, payload.input.args.media_id).await;
+
+        return Ok(Json(SaveMediaOutput {
+            id: existing.id,
+            user_id: existing.user_id,
+            thumbnail: existing.thumbnail,
+            uploaded_at: existing.uploaded_at,
+            duplicate: true,
+        }));
+    }
 
-    use base64::Engine as _;
-    let base64 = base64::engine::general_purpose::STANDARD.encode(bytes);
-    let thumbnail = format!("data:image/jpeg;base64,{}", base64);
+    // アップロードされたファイルを除いた容量に、保存するファイルを加えて上限に収まるか確認する
+    let limits = plan_limits(user_id.clone()).await?;
+    let used = store()
+        .usage(&user_id)
+        .await
+        .context(StorageUsageSnafu)?
+        .saturating_sub(bytes.len() as u64);
+    let adding = normalized
+        .as_ref()
+        .map_or(bytes.len(), |normalized| normalized.bytes.len());
+    if !limits.fits_storage_quota(used, adding as u64) {
+        delete_rejected_media(&user_id, payload.input.args.media_id).await;
+        return StorageQuotaExceededSnafu {
+            used,
+            quota: limits.storage_quota_bytes,
+        }
+        .fail();
+    }
+
+    let image = match normalized {
+        Some(normalized) => {
+            store()
+                .put(
+                    &user_id,
+                    &payload.input.args.media_id.to_string(),
+                    &normalized.bytes,
+                    normalized.content_type,
+                )
+                .await
+                .context(StorageSnafu)?;
+
+            Some(normalized.image)
+        }
+        None if content_type.starts_with("video/") => None,
+        None => Some(crate::media::decode(&bytes).whatever_context("Failed to decode as image")?),
+    };
+
+    let perceptual_hash = image.as_ref().map(|image| {
+        crate::media_dedup::format_perceptual_hash(crate::media::perceptual_hash(image))
+    });
+
+    // サムネイルはストレージに保存し、mediaテーブルにはそれを配信するパスだけを保存する
+    let thumbnail = match image {
+        Some(image) => crate::thumbnail::save(&user_id, payload.input.args.media_id, &image)
+            .await
+            .context(ThumbnailSnafu)?,
+        None => crate::media::VIDEO_THUMBNAIL.to_string(),
+    };
 
     let media = crate::gq::upload_media::upload_media(
-        bucket_name.clone(),
+        user_id.clone(),
         payload.input.args.media_id.clone(),
         thumbnail.clone(),
+        Some(content_type.to_string()),
+        Some(content_hash),
+        perceptual_hash,
     )
     .await
     .context(FailedToSaveMediaSnafu)?;
@@ -512,9 +736,329 @@ async fn save_media(
         user_id: media.user_id,
         thumbnail,
         uploaded_at: media.uploaded_at,
+        duplicate: false,
     }))
 }
 
+async fn delete_rejected_media(user_id: &str, media_id: uuid::Uuid) {
+    if let Err(err) = store().delete(user_id, &media_id.to_string()).await {
+        tracing::warn!("Ignoring error on deleting rejected media: {}", err);
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ValidateTemplateInput {
+    template: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ValidateTemplateOutput {
+    valid: bool,
+    errors: Vec<TemplateErrorOutput>,
+    preview: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct TemplateErrorOutput {
+    position: usize,
+    message: String,
+}
+
+// RSSタスクのテンプレートを保存前に検証し、問題がなければサンプル記事でのプレビューを返す
+async fn validate_template(
+    payload: Json<ActionPayload<ValidateTemplateInput>>,
+) -> Result<Json<ValidateTemplateOutput>, ApiError> {
+    use crate::template::{Context, Template};
+
+    let source = &payload.input.args.template;
+    let errors: Vec<TemplateErrorOutput> = Template::validate(source)
+        .into_iter()
+        .map(|err| TemplateErrorOutput {
+            position: err.position(),
+            message: err.to_string(),
+        })
+        .collect();
+
+    let preview = Template::parse(source)
+        .ok()
+        .map(|template| template.render(&Context::sample()));
+
+    Ok(Json(ValidateTemplateOutput {
+        valid: errors.is_empty(),
+        errors,
+        preview,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct ValidateFeedInput {
+    url: String,
+    template: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ValidateFeedOutput {
+    valid: bool,
+    url: String,
+    format: Option<String>,
+    title: Option<String>,
+    errors: Vec<String>,
+    items: Vec<FeedPreviewItem>,
+}
+
+#[derive(Debug, Serialize)]
+struct FeedPreviewItem {
+    title: String,
+    url: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub_date: OffsetDateTime,
+    message: Option<String>,
+}
+
+// プレビューに表示する記事の数
+const FEED_PREVIEW_ITEMS: usize = 5;
+
+// RSSタスクのURLを保存前に検証し、最新の記事をテンプレートで整形したプレビューを返す
+// HTMLページのURLが指定された場合は、ページ内で見つかったフィードのURLを返す
+async fn validate_feed(
+    payload: Json<ActionPayload<ValidateFeedInput>>,
+) -> Result<Json<ValidateFeedOutput>, ApiError> {
+    use crate::template::{Template, DEFAULT_TEMPLATE};
+
+    let args = &payload.input.args;
+
+    let feed = match crate::feed::discover(args.url.trim()).await {
+        Ok(feed) => feed,
+        Err(err) => {
+            return Ok(Json(ValidateFeedOutput {
+                valid: false,
+                url: args.url.clone(),
+                format: None,
+                title: None,
+                errors: vec![err.to_string()],
+                items: vec![],
+            }))
+        }
+    };
+
+    let mut errors = feed.problems.clone();
+    if feed.items.is_empty() {
+        errors.push(String::from("フィードに記事がありません"));
+    }
+
+    let template = match Template::parse(args.template.as_deref().unwrap_or(DEFAULT_TEMPLATE)) {
+        Ok(template) => Some(template),
+        Err(err) => {
+            errors.push(format!("テンプレート: {}", err));
+            None
+        }
+    };
+
+    let items = feed
+        .latest_items(FEED_PREVIEW_ITEMS)
+        .into_iter()
+        .map(|item| FeedPreviewItem {
+            title: item.title.clone(),
+            url: item.link.clone(),
+            pub_date: item.pub_date,
+            message: template
+                .as_ref()
+                .map(|template| template.render(&item.to_context(&feed.title))),
+        })
+        .collect();
+
+    Ok(Json(ValidateFeedOutput {
+        valid: errors.is_empty(),
+        url: feed.url.clone(),
+        format: Some(feed.format.to_string()),
+        title: Some(feed.title.clone()),
+        errors,
+        items,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct ValidateRssFilterInput {
+    action: String,
+    field: Option<String>,
+    pattern: String,
+    regex: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+struct ValidateRssFilterOutput {
+    valid: bool,
+    errors: Vec<String>,
+}
+
+// RSSタスクのフィルタを保存前に検証する
+// 不正な正規表現を保存すると、タスクを実行するたびにフィルタのコンパイルで失敗する
+async fn validate_rss_filter(
+    payload: Json<ActionPayload<ValidateRssFilterInput>>,
+) -> Result<Json<ValidateRssFilterOutput>, ApiError> {
+    let args = &payload.input.args;
+
+    // 省略した場合は、task_rss_filterテーブルの既定値と同じ
+    let rule = crate::rss_filter::FilterRule::new(
+        args.action.clone(),
+        args.field.clone().unwrap_or_else(|| String::from("any")),
+        args.pattern.clone(),
+        args.regex.unwrap_or(false),
+    );
+    let errors = match rule.validate() {
+        Ok(()) => vec![],
+        Err(err) => vec![err.to_string()],
+    };
+
+    Ok(Json(ValidateRssFilterOutput {
+        valid: errors.is_empty(),
+        errors,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct ValidateTweetInput {
+    text: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ValidateTweetOutput {
+    valid: bool,
+    weighted_length: usize,
+    permillage: usize,
+    max_weighted_length: usize,
+}
+
+// メッセージを保存する前に、Twitterと同じ数え方で文字数を確認する
+async fn validate_tweet(
+    payload: Json<ActionPayload<ValidateTweetInput>>,
+) -> Result<Json<ValidateTweetOutput>, ApiError> {
+    let result = crate::twitter_text::parse_tweet(&payload.input.args.text);
+
+    Ok(Json(ValidateTweetOutput {
+        valid: result.valid,
+        weighted_length: result.weighted_length,
+        permillage: result.permillage,
+        max_weighted_length: crate::twitter_text::MAX_WEIGHTED_LENGTH,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct ValidateMessageMediaInput {
+    media_ids: Vec<uuid::Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+struct ValidateMessageMediaOutput {
+    valid: bool,
+    errors: Vec<String>,
+}
+
+// メッセージを保存する前に、添付するメディアの数や組み合わせ、代替テキストを確認する
+async fn validate_message_media(
+    payload: Json<ActionPayload<ValidateMessageMediaInput>>,
+) -> Result<Json<ValidateMessageMediaOutput>, ApiError> {
+    let user_id = payload.session_variables.x_hasura_user_id.clone();
+    let media_ids = payload.input.args.media_ids.clone();
+
+    let require_alt_text = crate::gq::get_self::get_self(user_id.clone())
+        .await
+        .context(HasuraSnafu)?
+        .is_some_and(|user| user.require_alt_text);
+
+    let media = crate::gq::list_media::exec(user_id, media_ids.clone())
+        .await
+        .context(HasuraSnafu)?;
+
+    let mut errors = Vec::new();
+    let mut kinds = Vec::new();
+    for media_id in media_ids.iter() {
+        let media = match media.iter().find(|media| media.media_id == *media_id) {
+            Some(media) => media,
+            None => {
+                errors.push(format!("メディアが見つかりません: {}", media_id));
+                continue;
+            }
+        };
+
+        kinds.push(crate::media::MediaKind::from_content_type(
+            media.content_type.as_deref(),
+        ));
+
+        if let Some(Err(err)) = media
+            .alt_text
+            .as_deref()
+            .map(crate::media::validate_alt_text)
+        {
+            errors.push(err.to_string());
+        }
+        if require_alt_text && !media.has_alt_text() {
+            errors.push(format!("代替テキストが設定されていません: {}", media_id));
+        }
+    }
+
+    if let Err(err) = crate::media::validate_attachments(&kinds) {
+        errors.push(err.to_string());
+    }
+
+    Ok(Json(ValidateMessageMediaOutput {
+        valid: errors.is_empty(),
+        errors,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct GetThumbnailUrlsInput {
+    media_ids: Vec<uuid::Uuid>,
+    size: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+struct ThumbnailUrlOutput {
+    media_id: uuid::Uuid,
+    url: String,
+    expires_in: u32,
+}
+
+// ストレージに保存したサムネイルを、期限付きのURLで返す
+async fn get_thumbnail_urls(
+    payload: Json<ActionPayload<GetThumbnailUrlsInput>>,
+) -> Result<Json<Vec<ThumbnailUrlOutput>>, ApiError> {
+    let size = payload
+        .input
+        .args
+        .size
+        .unwrap_or(crate::media::THUMBNAIL_SIZE);
+    ensure!(
+        crate::thumbnail::is_valid_size(size),
+        InvalidThumbnailSizeSnafu { size }
+    );
+
+    let user_id = payload.session_variables.x_hasura_user_id.clone();
+
+    let media = crate::gq::list_media::exec(user_id.clone(), payload.input.args.media_ids.clone())
+        .await
+        .context(HasuraSnafu)?;
+
+    let mut urls = Vec::with_capacity(media.len());
+    for media in media {
+        let url = match crate::media::MediaKind::from_content_type(media.content_type.as_deref()) {
+            crate::media::MediaKind::Video => crate::media::VIDEO_THUMBNAIL.to_string(),
+            _ => crate::thumbnail::presign(&user_id, media.media_id, size)
+                .await
+                .context(ThumbnailSnafu)?,
+        };
+
+        urls.push(ThumbnailUrlOutput {
+            media_id: media.media_id,
+            url,
+            expires_in: crate::thumbnail::URL_EXPIRY_SECS,
+        });
+    }
+
+    Ok(Json(urls))
+}
+
 // OBSOLETE
 #[allow(dead_code)]
 async fn upload(
@@ -549,38 +1093,6 @@ async fn upload(
 
         let image_thumb = image_original.thumbnail(240, 240).to_rgb8();
 
-        let bucket_name = user.id.clone();
-        let region = s3::region::Region::Custom {
-            region: "ap-northeast-1".to_owned(),
-            endpoint: crate::config::CONFIG.minio_endpoint.clone(),
-        };
-
-        let credentials = s3::creds::Credentials::default().unwrap();
-        let bucket = s3::bucket::Bucket::new(&bucket_name, region.clone(), credentials.clone())
-            .unwrap()
-            .with_path_style();
-
-        // もし既存のバケットが存在しない場合には新たに作成する
-        if bucket.head_object("/").await.is_err() {
-            let config = s3::BucketConfiguration::default();
-            match s3::bucket::Bucket::create_with_path_style(
-                &bucket_name,
-                region,
-                credentials,
-                config,
-            )
-            .await
-            {
-                Ok(_) => {
-                    tracing::info!("New bucket is created for user {}", user.id);
-                }
-                Err(err) => {
-                    tracing::debug!("{:?}", err);
-                    return Err(StatusCode::INTERNAL_SERVER_ERROR);
-                }
-            };
-        }
-
         let mut bytes: Vec<u8> = Vec::new();
         image_resized
             .write_to(
@@ -592,21 +1104,14 @@ async fn upload(
                 StatusCode::INTERNAL_SERVER_ERROR
             })?;
 
-        // このフラグでminioへのアップロード済みかどうかを判断し、データベースに保存する？
-        let _uploaded = match bucket.put_object(uuid.to_string(), &bytes).await {
-            Ok(resp) => {
-                if resp.status_code() != 200 {
-                    tracing::debug!(
-                        "minioへファイルアップロード時に200以外のステータスが返されました: {:?}",
-                        resp
-                    );
-                    false
-                } else {
-                    true
-                }
-            }
+        // このフラグでストレージへのアップロード済みかどうかを判断し、データベースに保存する？
+        let _uploaded = match store()
+            .put(&user.id, &uuid.to_string(), &bytes, "image/jpeg")
+            .await
+        {
+            Ok(()) => true,
             Err(err) => {
-                tracing::debug!("minioへファイルアップロード時にエラー: {:?}", err);
+                tracing::debug!("ストレージへファイルアップロード時にエラー: {:?}", err);
                 false
             }
         };
@@ -626,7 +1131,19 @@ async fn upload(
         let base64 = base64::engine::general_purpose::STANDARD.encode(bytes);
         let dataurl = format!("data:image/jpeg;base64,{}", base64);
 
-        match crate::gq::upload_media::upload_media(user.id, uuid.clone(), dataurl).await {
+        // 保存する画像はJPEGに変換している
+        let content_type = Some("image/jpeg".to_string());
+
+        match crate::gq::upload_media::upload_media(
+            user.id,
+            uuid.clone(),
+            dataurl,
+            content_type,
+            None,
+            None,
+        )
+        .await
+        {
             Ok(media) => Ok(Json(media)),
             _ => Err(StatusCode::BAD_REQUEST),
         }
@@ -656,6 +1173,57 @@ async fn restart_scheduler(
     Ok(Json(BooleanOutput { result }))
 }
 
+#[derive(Deserialize, Debug)]
+struct UserRoleRow {
+    id: String,
+    role: Role,
+}
+
+// ユーザーのプランが変更された場合、新しいプランの制限でスケジュールをやり直す
+async fn user_role_changed(
+    State(actor_handle): State<crate::mpsc::ActorHandle>,
+    payload: Json<EventPayload<UserRoleRow>>,
+) -> Result<Json<BooleanOutput>, ApiError> {
+    let data = &payload.event.data;
+    let user = data.new.as_ref().ok_or(ApiError::UserNotFound)?;
+
+    if data.old.as_ref().map(|old| &old.role) == Some(&user.role) {
+        return Ok(Json(BooleanOutput { result: false }));
+    }
+
+    tracing::info!("Role of the user {} has changed to {}", user.id, user.role);
+
+    let result = actor_handle.restart_task_for_user(user.id.clone()).await;
+    Ok(Json(BooleanOutput { result }))
+}
+
+#[derive(Deserialize, Debug)]
+struct WatermarkRow {
+    user_id: String,
+}
+
+// 透かしの設定が変わったら、以前の設定で重ねた画像のメディアIDを使い回さない
+async fn watermark_changed(
+    payload: Json<EventPayload<WatermarkRow>>,
+) -> Result<Json<BooleanOutput>, ApiError> {
+    let data = &payload.event.data;
+    let watermark = data
+        .new
+        .as_ref()
+        .or(data.old.as_ref())
+        .ok_or(ApiError::UserNotFound)?;
+
+    // 透かしはユーザーごとの設定なので、連携したどのアカウントでアップロードしたIDも使わない
+    let accounts = crate::gq::list_accounts::exec(watermark.user_id.clone())
+        .await
+        .context(HasuraSnafu)?;
+    for account in accounts.iter() {
+        crate::media_cache::invalidate_account(account);
+    }
+
+    Ok(Json(BooleanOutput { result: true }))
+}
+
 async fn action_auth_middleware<B>(
     request: http::Request<B>,
     next: axum::middleware::Next<B>,
@@ -678,10 +1246,3 @@ where
         Err(StatusCode::UNAUTHORIZED)
     }
 }
-
-#[tokio::test]
-async fn create_bucket_test() {
-    let bucket = get_or_create_bucket("test").await.unwrap();
-
-    assert_eq!(bucket.name, "test");
-}
@@ -7,7 +7,7 @@ use axum::{
         rejection::TypedHeaderRejectionReason, FromRef, FromRequestParts, Query, State, TypedHeader,
     },
     response::{IntoResponse, Redirect, Response},
-    routing::get,
+    routing::{get, post},
     Json, RequestPartsExt, Router,
 };
 use axum_extra::extract::{
@@ -15,6 +15,8 @@ use axum_extra::extract::{
     SignedCookieJar,
 };
 
+use crate::gq::error::HasuraError;
+use crate::publisher::{bluesky, mastodon, misskey, Account, Provider};
 use crate::twitter::{Data, TwitterUser};
 use http::{
     header::{self, SET_COOKIE},
@@ -49,12 +51,26 @@ pub enum Error {
 /// CONSTANTS
 static COOKIE_NAME: &str = "SESSION";
 static CODE_VERIFIER: &str = "CODE_VERIFIER";
+// アカウントの連携中に、連携先のユーザーを覚えておくクッキー
+static LINK_ACCOUNT: &str = "LINK_ACCOUNT";
+// Mastodonの認可の途中で、連携先のインスタンスとstateを覚えておくクッキー
+static MASTODON_INSTANCE: &str = "MASTODON_INSTANCE";
+static MASTODON_STATE: &str = "MASTODON_STATE";
+// MiAuthの認可の途中で、連携先のインスタンスとセッションIDを覚えておくクッキー
+static MISSKEY_INSTANCE: &str = "MISSKEY_INSTANCE";
+static MISSKEY_SESSION: &str = "MISSKEY_SESSION";
 
 pub fn create_route() -> Router<crate::state::AppState> {
     Router::new()
         .route("/auth/get_jwt", get(get_jwt))
         .route("/auth/twitter", get(twitter_auth))
+        .route("/auth/twitter/link", get(twitter_link))
         .route("/auth/authorized", get(login_authorized))
+        .route("/auth/mastodon", get(mastodon_auth))
+        .route("/auth/mastodon/authorized", get(mastodon_authorized))
+        .route("/auth/bluesky", post(bluesky_link))
+        .route("/auth/misskey", get(misskey_auth))
+        .route("/auth/misskey/authorized", get(misskey_authorized))
         .route("/auth/logout", get(logout))
 }
 
@@ -68,6 +84,7 @@ async fn twitter_auth(
         .add_scope(Scope::new("users.read".to_string()))
         .add_scope(Scope::new("tweet.read".to_string()))
         .add_scope(Scope::new("tweet.write".to_string()))
+        .add_scope(Scope::new("media.write".to_string()))
         .add_scope(Scope::new("offline.access".to_string()))
         .set_pkce_challenge(pkce_code_challenge)
         .url();
@@ -78,9 +95,30 @@ async fn twitter_auth(
         .http_only(true)
         .finish();
 
+    // 途中でやめた連携のクッキーが残っていても、通常のログインとして扱う
+    let jar = jar.remove(Cookie::build(LINK_ACCOUNT, "").path("/").finish());
+
     Ok((jar.add(cookie), Redirect::to(auth_url.as_ref())))
 }
 
+// ログイン中のユーザーに別のTwitterアカウントを連携する
+// 認可の流れはログインと同じで、戻ってきたときにクッキーを見て連携として扱う
+async fn twitter_link(
+    user: TwitterUser,
+    state: State<BasicClient>,
+    jar: SignedCookieJar,
+) -> Result<(SignedCookieJar, Redirect), StatusCode> {
+    let (jar, redirect) = twitter_auth(state, jar).await?;
+
+    let cookie = Cookie::build(LINK_ACCOUNT, user.id)
+        .path("/")
+        .secure(true)
+        .http_only(true)
+        .finish();
+
+    Ok((jar.add(cookie), redirect))
+}
+
 #[derive(Debug, Deserialize)]
 #[allow(dead_code)]
 struct AuthRequest {
@@ -134,6 +172,31 @@ async fn login_authorized(
                 expires_in: token_response.expires_in(),
             };
 
+            // 連携の途中であれば、ログイン中のユーザーはそのままでトークンだけを保存する
+            if let Some(link_cookie) = jar.get(LINK_ACCOUNT) {
+                let user_id = link_cookie.value().to_owned();
+                let jar = jar.remove(link_cookie);
+
+                let account = Account {
+                    provider: Provider::Twitter,
+                    instance: None,
+                    token,
+                };
+                link_account(
+                    user_id,
+                    account,
+                    twitter_user.name.clone(),
+                    twitter_user.username.clone(),
+                )
+                .await?;
+
+                return Ok((
+                    jar,
+                    HeaderMap::new(),
+                    Redirect::to(&CONFIG.backend_endpoint),
+                ));
+            }
+
             match crate::gq::store_session::store_session(token).await {
                 Ok(_) => {
                     tracing::debug!("twitter token stored to hasura successfuly")
@@ -245,6 +308,360 @@ async fn login_authorized(
     // }
 }
 
+async fn link_account(
+    user_id: String,
+    account: Account,
+    name: String,
+    user_name: String,
+) -> Result<(), StatusCode> {
+    let account_id = account.token.id.clone();
+
+    // ログインに使ったアカウントを選んだ場合は、ログイン時と同じようにトークンを更新する
+    let result = if account_id == user_id {
+        crate::gq::store_session::store_session(account.token)
+            .await
+            .map(|token| token.id)
+    } else {
+        crate::gq::link_account::exec(user_id.clone(), account, name, user_name).await
+    };
+
+    match result {
+        Ok(account_id) => {
+            tracing::info!("account {} linked to {}", account_id, user_id);
+            Ok(())
+        }
+        Err(HasuraError::AccountLinkedToAnotherUser) => {
+            tracing::warn!("account {} is already linked to another user", account_id);
+            Err(StatusCode::CONFLICT)
+        }
+        Err(err) => {
+            tracing::error!("failed to link account to hasura");
+            tracing::error!("{:?}", err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+fn mastodon_redirect_uri() -> String {
+    format!("{}/auth/mastodon/authorized", CONFIG.backend_endpoint)
+}
+
+// インスタンスごとに一度だけアプリを登録し、以降は保存したものを使う
+async fn mastodon_app(instance: &str) -> Result<mastodon::App, StatusCode> {
+    let saved = crate::gq::get_mastodon_app::exec(instance.to_string())
+        .await
+        .map_err(|err| {
+            tracing::error!("failed to load mastodon app: {:?}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    if let Some(app) = saved {
+        return Ok(app);
+    }
+
+    let app = mastodon::register_app(instance, &mastodon_redirect_uri())
+        .await
+        .map_err(|err| {
+            tracing::warn!("failed to register app on {}: {}", instance, err);
+            StatusCode::BAD_GATEWAY
+        })?;
+
+    let inserted = crate::gq::insert_mastodon_app::exec(instance.to_string(), &app)
+        .await
+        .map_err(|err| {
+            tracing::error!("failed to store mastodon app: {:?}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    if inserted {
+        Ok(app)
+    } else {
+        crate::gq::get_mastodon_app::exec(instance.to_string())
+            .await
+            .ok()
+            .flatten()
+            .ok_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct MastodonAuthRequest {
+    instance: String,
+}
+
+// ログイン中のユーザーにMastodonのアカウントを連携する
+async fn mastodon_auth(
+    user: TwitterUser,
+    Query(query): Query<MastodonAuthRequest>,
+    jar: SignedCookieJar,
+) -> Result<(SignedCookieJar, Redirect), StatusCode> {
+    let instance =
+        crate::publisher::normalize_instance(&query.instance).ok_or(StatusCode::BAD_REQUEST)?;
+
+    let app = mastodon_app(&instance).await?;
+    let state = CsrfToken::new_random().secret().to_owned();
+    let auth_url = mastodon::authorize_url(&instance, &app, &mastodon_redirect_uri(), &state);
+
+    let instance_cookie = Cookie::build(MASTODON_INSTANCE, instance)
+        .path("/")
+        .secure(true)
+        .http_only(true)
+        .finish();
+    let state_cookie = Cookie::build(MASTODON_STATE, state)
+        .path("/")
+        .secure(true)
+        .http_only(true)
+        .finish();
+    let link_cookie = Cookie::build(LINK_ACCOUNT, user.id)
+        .path("/")
+        .secure(true)
+        .http_only(true)
+        .finish();
+
+    Ok((
+        jar.add(instance_cookie).add(state_cookie).add(link_cookie),
+        Redirect::to(&auth_url),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct MastodonAuthorizedRequest {
+    code: String,
+    state: String,
+}
+
+async fn mastodon_authorized(
+    Query(query): Query<MastodonAuthorizedRequest>,
+    jar: SignedCookieJar,
+) -> Result<(SignedCookieJar, Redirect), StatusCode> {
+    let (instance_cookie, state_cookie, link_cookie) = match (
+        jar.get(MASTODON_INSTANCE),
+        jar.get(MASTODON_STATE),
+        jar.get(LINK_ACCOUNT),
+    ) {
+        (Some(instance_cookie), Some(state_cookie), Some(link_cookie)) => {
+            (instance_cookie, state_cookie, link_cookie)
+        }
+        _ => {
+            tracing::error!("Cookie not found");
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+    };
+
+    // 別のブラウザで始めた認可のコードは受け付けない
+    if state_cookie.value() != query.state {
+        tracing::error!("Mastodon OAuth state mismatch");
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let instance = instance_cookie.value().to_owned();
+    let user_id = link_cookie.value().to_owned();
+    let jar = jar
+        .remove(instance_cookie)
+        .remove(state_cookie)
+        .remove(link_cookie);
+
+    let app = mastodon_app(&instance).await?;
+
+    let access_token =
+        mastodon::exchange_code(&instance, &app, &query.code, &mastodon_redirect_uri())
+            .await
+            .map_err(|err| {
+                tracing::error!("{}", err);
+                StatusCode::UNAUTHORIZED
+            })?;
+
+    let mastodon_user = mastodon::verify_credentials(&instance, &access_token)
+        .await
+        .map_err(|err| {
+            tracing::error!("{}", err);
+            StatusCode::UNAUTHORIZED
+        })?;
+
+    let token = Token {
+        id: crate::publisher::account_id(Provider::Mastodon, &instance, &mastodon_user.id),
+        access_token,
+        refresh_token: None,
+        issued_at: time::OffsetDateTime::now_utc(),
+        expires_in: None,
+    };
+    let account = Account {
+        provider: Provider::Mastodon,
+        instance: Some(instance),
+        token,
+    };
+
+    link_account(
+        user_id,
+        account,
+        mastodon_user.display_name,
+        mastodon_user.username,
+    )
+    .await?;
+
+    Ok((jar, Redirect::to(&CONFIG.backend_endpoint)))
+}
+
+#[derive(Debug, Deserialize)]
+struct BlueskyLinkRequest {
+    // ハンドルかメールアドレス
+    identifier: String,
+    app_password: String,
+    // 省略した場合はbsky.social
+    service: Option<String>,
+}
+
+// ログイン中のユーザーにBlueskyのアカウントを連携する
+// BlueskyにはOAuthがないので、アプリパスワードでセッションを作り、パスワード自体は保存しない
+async fn bluesky_link(
+    user: TwitterUser,
+    Json(request): Json<BlueskyLinkRequest>,
+) -> Result<StatusCode, StatusCode> {
+    let service = match request.service.as_deref() {
+        Some(service) => {
+            crate::publisher::normalize_instance(service).ok_or(StatusCode::BAD_REQUEST)?
+        }
+        None => bluesky::DEFAULT_SERVICE.to_string(),
+    };
+
+    let session = bluesky::create_session(&service, &request.identifier, &request.app_password)
+        .await
+        .map_err(|err| {
+            tracing::warn!("failed to create bluesky session: {}", err);
+            match err {
+                bluesky::Error::BlueskyApi { .. } => StatusCode::UNAUTHORIZED,
+                _ => StatusCode::BAD_GATEWAY,
+            }
+        })?;
+
+    let account_id = crate::publisher::account_id(Provider::Bluesky, &service, &session.did);
+    let handle = session.handle.clone();
+    let account = Account {
+        provider: Provider::Bluesky,
+        instance: Some(service),
+        token: session.into_token(account_id),
+    };
+
+    link_account(user.id, account, handle.clone(), handle).await?;
+
+    Ok(StatusCode::CREATED)
+}
+
+fn misskey_callback() -> String {
+    format!("{}/auth/misskey/authorized", CONFIG.backend_endpoint)
+}
+
+#[derive(Debug, Deserialize)]
+struct MisskeyAuthRequest {
+    instance: String,
+}
+
+// ログイン中のユーザーにMisskeyのアカウントを連携する
+// MiAuthではアプリの登録が要らないので、連携のたびにセッションIDを作るだけでよい
+async fn misskey_auth(
+    user: TwitterUser,
+    Query(query): Query<MisskeyAuthRequest>,
+    jar: SignedCookieJar,
+) -> Result<(SignedCookieJar, Redirect), StatusCode> {
+    let instance =
+        crate::publisher::normalize_instance(&query.instance).ok_or(StatusCode::BAD_REQUEST)?;
+
+    let session = uuid::Uuid::new_v4().to_string();
+    let auth_url = misskey::authorize_url(&instance, &session, &misskey_callback());
+
+    let instance_cookie = Cookie::build(MISSKEY_INSTANCE, instance)
+        .path("/")
+        .secure(true)
+        .http_only(true)
+        .finish();
+    let session_cookie = Cookie::build(MISSKEY_SESSION, session)
+        .path("/")
+        .secure(true)
+        .http_only(true)
+        .finish();
+    let link_cookie = Cookie::build(LINK_ACCOUNT, user.id)
+        .path("/")
+        .secure(true)
+        .http_only(true)
+        .finish();
+
+    Ok((
+        jar.add(instance_cookie)
+            .add(session_cookie)
+            .add(link_cookie),
+        Redirect::to(&auth_url),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct MisskeyAuthorizedRequest {
+    session: String,
+}
+
+async fn misskey_authorized(
+    Query(query): Query<MisskeyAuthorizedRequest>,
+    jar: SignedCookieJar,
+) -> Result<(SignedCookieJar, Redirect), StatusCode> {
+    let (instance_cookie, session_cookie, link_cookie) = match (
+        jar.get(MISSKEY_INSTANCE),
+        jar.get(MISSKEY_SESSION),
+        jar.get(LINK_ACCOUNT),
+    ) {
+        (Some(instance_cookie), Some(session_cookie), Some(link_cookie)) => {
+            (instance_cookie, session_cookie, link_cookie)
+        }
+        _ => {
+            tracing::error!("Cookie not found");
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+    };
+
+    // 別のブラウザで始めた連携のセッションIDは受け付けない
+    if session_cookie.value() != query.session {
+        tracing::error!("MiAuth session mismatch");
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let instance = instance_cookie.value().to_owned();
+    let user_id = link_cookie.value().to_owned();
+    let jar = jar
+        .remove(instance_cookie)
+        .remove(session_cookie)
+        .remove(link_cookie);
+
+    let (access_token, misskey_user) = misskey::check_session(&instance, &query.session)
+        .await
+        .map_err(|err| {
+            tracing::error!("{}", err);
+            match err {
+                misskey::Error::MisskeyNetwork { .. } => StatusCode::BAD_GATEWAY,
+                _ => StatusCode::UNAUTHORIZED,
+            }
+        })?;
+
+    let token = Token {
+        id: crate::publisher::account_id(Provider::Misskey, &instance, &misskey_user.id),
+        access_token,
+        refresh_token: None,
+        issued_at: time::OffsetDateTime::now_utc(),
+        expires_in: None,
+    };
+    let account = Account {
+        provider: Provider::Misskey,
+        instance: Some(instance),
+        token,
+    };
+
+    // 表示名を設定していないユーザーはnameがnullになる
+    let name = misskey_user
+        .name
+        .unwrap_or_else(|| misskey_user.username.clone());
+
+    link_account(user_id, account, name, misskey_user.username).await?;
+
+    Ok((jar, Redirect::to(&CONFIG.backend_endpoint)))
+}
+
 async fn logout(
     State(store): State<PostgresSessionStore>,
     TypedHeader(cookies): TypedHeader<headers::Cookie>,
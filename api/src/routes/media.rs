@@ -0,0 +1,25 @@
+use crate::twitter::TwitterUser;
+use axum::{extract::Path, response::Redirect, routing::get, Router};
+use http::StatusCode;
+
+pub fn create_route() -> Router<crate::state::AppState> {
+    Router::new().route("/thumbnails/:media_id/:size", get(thumbnail))
+}
+
+// mediaテーブルに保存したパスから、ログイン中のユーザーのサムネイルへ期限付きのURLでリダイレクトする
+async fn thumbnail(
+    user: TwitterUser,
+    Path((media_id, size)): Path<(uuid::Uuid, u32)>,
+) -> Result<Redirect, StatusCode> {
+    if !crate::thumbnail::is_valid_size(size) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    crate::thumbnail::presign(&user.id, media_id, size)
+        .await
+        .map(|url| Redirect::temporary(&url))
+        .map_err(|err| {
+            tracing::error!("Failed to presign the thumbnail {}: {:?}", media_id, err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
@@ -1,3 +1,5 @@
 pub mod api;
 pub mod auth;
 pub mod html;
+pub mod media;
+pub mod storage;
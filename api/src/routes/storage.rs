@@ -0,0 +1,87 @@
+use crate::config::{MediaStoreKind, CONFIG};
+use crate::storage::{LocalStore, MediaStore};
+use axum::{
+    body::Bytes,
+    extract::{DefaultBodyLimit, Path, Query},
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use http::{header, HeaderMap, StatusCode};
+use serde::Deserialize;
+
+pub fn create_route() -> Router<crate::state::AppState> {
+    Router::new()
+        .route("/storage/*path", get(get_object).put(put_object))
+        .layer(DefaultBodyLimit::max(crate::media::MAX_VIDEO_BYTES))
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenQuery {
+    token: String,
+}
+
+// ローカルのストレージを使うときだけ、発行したURLでオブジェクトを読み書きできるようにする
+fn authorize(
+    path: &str,
+    method: &str,
+    content_type: Option<&str>,
+    token: &str,
+) -> Result<(LocalStore, String, String), StatusCode> {
+    if CONFIG.media_store != MediaStoreKind::Local {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let store = LocalStore::from_config();
+    let path = path.trim_start_matches('/');
+    match store.verify(path, method, content_type, token) {
+        Ok(true) => {}
+        Ok(false) => return Err(StatusCode::FORBIDDEN),
+        Err(err) => {
+            tracing::debug!("Invalid storage token for {}: {:?}", path, err);
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
+    let (user_id, key) = path.split_once('/').ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok((store, user_id.to_string(), key.to_string()))
+}
+
+async fn get_object(
+    Path(path): Path<String>,
+    Query(query): Query<TokenQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let (store, user_id, key) = authorize(&path, "GET", None, &query.token)?;
+
+    let bytes = store.get(&user_id, &key).await.map_err(|err| {
+        tracing::debug!("Failed to read {}: {:?}", path, err);
+        StatusCode::NOT_FOUND
+    })?;
+    let content_type = crate::media::content_type(&bytes).unwrap_or("application/octet-stream");
+
+    Ok(([(header::CONTENT_TYPE, content_type)], bytes))
+}
+
+async fn put_object(
+    Path(path): Path<String>,
+    Query(query): Query<TokenQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<StatusCode, StatusCode> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("application/octet-stream");
+    let (store, user_id, key) = authorize(&path, "PUT", Some(content_type), &query.token)?;
+
+    store
+        .put(&user_id, &key, &body, content_type)
+        .await
+        .map_err(|err| {
+            tracing::error!("Failed to write {}: {:?}", path, err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(StatusCode::OK)
+}
@@ -0,0 +1,238 @@
+// RSSタスクの記事フィルタ
+//
+// 除外条件（exclude）のいずれかに一致した記事は投稿しない。
+// 含める条件（include）が一つ以上ある場合は、そのいずれかに一致した記事のみを投稿する。
+// キーワードは大文字・小文字を区別せずに部分一致で判定するが、カテゴリーは完全一致で判定する。
+use regex::{Regex, RegexBuilder};
+use snafu::prelude::*;
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum Error {
+    #[snafu(display("不明なフィルタの種類です: {action}"))]
+    UnknownAction { action: String },
+
+    #[snafu(display("不明なフィルタの対象です: {field}"))]
+    UnknownField { field: String },
+
+    #[snafu(display("条件が空です。キーワードか正規表現を入力してください"))]
+    EmptyPattern,
+
+    #[snafu(display("正規表現 `{pattern}` が不正です"))]
+    InvalidRegex {
+        pattern: String,
+        source: regex::Error,
+    },
+}
+
+// Hasuraのtask_rss_filterテーブルの1行
+#[derive(Debug, Clone, derive_new::new)]
+pub struct FilterRule {
+    pub action: String,
+    pub field: String,
+    pub pattern: String,
+    pub regex: bool,
+}
+
+impl FilterRule {
+    // 保存する前に、実行時と同じ方法でコンパイルできるか確認する
+    pub fn validate(&self) -> Result<(), Error> {
+        Filter::compile(self).map(|_| ())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Include,
+    Exclude,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Any,
+    Title,
+    Description,
+    Categories,
+}
+
+#[derive(Debug, Clone)]
+enum Pattern {
+    Keyword(String),
+    Regex(Regex),
+}
+
+#[derive(Debug, Clone)]
+struct Filter {
+    action: Action,
+    field: Field,
+    pattern: Pattern,
+    source: String,
+}
+
+// フィルタの判定対象となる記事の内容
+#[derive(Debug, Clone, Copy)]
+pub struct Target<'a> {
+    pub title: &'a str,
+    pub description: &'a str,
+    pub categories: &'a [String],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Accepted,
+    Rejected { reason: String },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Filters {
+    filters: Vec<Filter>,
+}
+
+impl Filters {
+    pub fn compile(rules: &[FilterRule]) -> Result<Self, Error> {
+        let filters = rules
+            .iter()
+            .map(Filter::compile)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { filters })
+    }
+
+    pub fn evaluate(&self, target: &Target) -> Verdict {
+        if let Some(filter) = self
+            .filters
+            .iter()
+            .filter(|filter| filter.action == Action::Exclude)
+            .find(|filter| filter.matches(target))
+        {
+            return Verdict::Rejected {
+                reason: format!("除外条件「{}」に一致しました", filter.source),
+            };
+        }
+
+        let mut includes = self
+            .filters
+            .iter()
+            .filter(|filter| filter.action == Action::Include)
+            .peekable();
+
+        if includes.peek().is_none() || includes.any(|filter| filter.matches(target)) {
+            Verdict::Accepted
+        } else {
+            Verdict::Rejected {
+                reason: String::from("含める条件のいずれにも一致しませんでした"),
+            }
+        }
+    }
+}
+
+impl Filter {
+    fn compile(rule: &FilterRule) -> Result<Self, Error> {
+        let action = match rule.action.as_str() {
+            "include" => Action::Include,
+            "exclude" => Action::Exclude,
+            action => {
+                return Err(Error::UnknownAction {
+                    action: action.to_string(),
+                })
+            }
+        };
+
+        let field = match rule.field.as_str() {
+            "any" => Field::Any,
+            "title" => Field::Title,
+            "description" => Field::Description,
+            "categories" => Field::Categories,
+            field => {
+                return Err(Error::UnknownField {
+                    field: field.to_string(),
+                })
+            }
+        };
+
+        // 空のキーワードはすべての記事に一致してしまう
+        ensure!(!rule.pattern.trim().is_empty(), EmptyPatternSnafu);
+
+        let pattern = if rule.regex {
+            let regex = RegexBuilder::new(&rule.pattern)
+                .case_insensitive(true)
+                .build()
+                .context(InvalidRegexSnafu {
+                    pattern: rule.pattern.clone(),
+                })?;
+            Pattern::Regex(regex)
+        } else {
+            Pattern::Keyword(rule.pattern.trim().to_lowercase())
+        };
+
+        Ok(Self {
+            action,
+            field,
+            pattern,
+            source: rule.pattern.clone(),
+        })
+    }
+
+    fn matches(&self, target: &Target) -> bool {
+        let text = |text: &str| match &self.pattern {
+            Pattern::Keyword(keyword) => text.to_lowercase().contains(keyword.as_str()),
+            Pattern::Regex(regex) => regex.is_match(text),
+        };
+
+        let category = |category: &String| match &self.pattern {
+            Pattern::Keyword(keyword) => category.trim().to_lowercase() == *keyword,
+            Pattern::Regex(regex) => regex.is_match(category),
+        };
+
+        match self.field {
+            Field::Title => text(target.title),
+            Field::Description => text(target.description),
+            Field::Categories => target.categories.iter().any(category),
+            Field::Any => {
+                text(target.title)
+                    || text(target.description)
+                    || target.categories.iter().any(category)
+            }
+        }
+    }
+}
+
+#[test]
+fn test_evaluate_filters() {
+    let categories = vec!["質屋が解説".to_string(), "宝石".to_string()];
+    let target = Target {
+        title: "ROLEXの買取相場",
+        description: "腕時計の査定について",
+        categories: &categories,
+    };
+
+    let rule = |action: &str, field: &str, pattern: &str, regex: bool| {
+        FilterRule::new(action.into(), field.into(), pattern.into(), regex)
+    };
+
+    let filters = Filters::compile(&[rule("include", "categories", "質屋が解説", false)]).unwrap();
+    assert_eq!(filters.evaluate(&target), Verdict::Accepted);
+
+    let filters = Filters::compile(&[rule("include", "categories", "質屋", false)]).unwrap();
+    assert!(matches!(
+        filters.evaluate(&target),
+        Verdict::Rejected { .. }
+    ));
+
+    let filters = Filters::compile(&[
+        rule("include", "any", "rolex", false),
+        rule("exclude", "description", "査定|修理", true),
+    ])
+    .unwrap();
+    assert_eq!(
+        filters.evaluate(&target),
+        Verdict::Rejected {
+            reason: "除外条件「査定|修理」に一致しました".to_string()
+        }
+    );
+
+    assert!(Filters::compile(&[rule("include", "title", "(", true)]).is_err());
+    assert!(rule("exclude", "any", "  ", false).validate().is_err());
+    assert!(rule("exclude", "any", "", true).validate().is_err());
+    assert!(rule("exclude", "title", "PR", false).validate().is_ok());
+}
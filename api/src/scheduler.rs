@@ -1,5 +1,6 @@
 // use crate::error::Error;
-use crate::model::{EnabledTask, RssTask};
+use crate::config::CONFIG;
+use crate::model::{EnabledTask, Role, RssTask};
 use clokwerk::AsyncScheduler;
 use snafu::prelude::*;
 use std::collections::HashMap;
@@ -30,7 +31,7 @@ async fn start_task(
 ) -> (String, Option<tokio::task::JoinHandle<AsyncScheduler>>) {
     (
         user.user_id.clone(),
-        start_tasks(&user.tasks, &user.rss_tasks).await,
+        start_tasks(&user.role, &user.tasks, &user.rss_tasks).await,
     )
 }
 
@@ -47,10 +48,11 @@ pub async fn start_task_for_user(
     // ))?;
     let user = users.first().ok_or(Error::FailedToStartTasks)?;
 
-    Ok(start_tasks(&user.tasks, &user.rss_tasks).await)
+    Ok(start_tasks(&user.role, &user.tasks, &user.rss_tasks).await)
 }
 
 async fn start_tasks(
+    role: &Role,
     tasks: &Vec<EnabledTask>,
     rss_tasks: &Vec<RssTask>,
 ) -> Option<tokio::task::JoinHandle<AsyncScheduler>> {
@@ -65,10 +67,12 @@ async fn start_tasks(
     }
 
     if rss_tasks.len() > 0 {
+        let limits = CONFIG.plan_limits(role);
+
         rss_tasks
             .clone()
             .into_iter()
-            .for_each(|task| RssTask::to_schedule2(task, &mut scheduler));
+            .for_each(|task| RssTask::to_schedule2(task, &limits, &mut scheduler));
     }
 
     let handle = tokio::spawn(async move {
@@ -80,3 +84,40 @@ async fn start_tasks(
 
     Some(handle)
 }
+
+// 参照されないメディアのオブジェクトを定期的に削除する
+pub fn start_media_gc() -> tokio::task::JoinHandle<()> {
+    let mut scheduler = AsyncScheduler::new();
+    scheduler
+        .every(clokwerk::Interval::Hours(CONFIG.media_gc_interval_hours))
+        .run(run_media_gc);
+
+    tokio::spawn(async move {
+        loop {
+            scheduler.run_pending().await;
+            tokio::time::sleep(Duration::from_millis(1000)).await;
+        }
+    })
+}
+
+async fn run_media_gc() {
+    let dry_run = CONFIG.media_gc_dry_run;
+    let grace = time::Duration::hours(CONFIG.media_gc_grace_hours);
+
+    match crate::media_gc::run(dry_run, grace).await {
+        Ok(reports) => {
+            for report in reports.iter().filter(|report| {
+                !report.plan.orphaned.is_empty() || !report.plan.missing.is_empty()
+            }) {
+                tracing::info!(
+                    "Media GC{} {}: {} orphaned objects, {} missing objects",
+                    if dry_run { " (dry run)" } else { "" },
+                    report.user_id,
+                    report.plan.orphaned.len(),
+                    report.plan.missing.len()
+                );
+            }
+        }
+        Err(err) => tracing::error!("Media GC failed: {:?}", err),
+    }
+}
@@ -0,0 +1,235 @@
+// 定時ツイートで投稿するメッセージの選び方
+//
+// weighted: 優先度（priority）に比例した確率で選ぶ。優先度が0以下のメッセージは選ばない
+// uniform:  すべてのメッセージから同じ確率で選ぶ
+// newest:   作成日時が最も新しいメッセージを選ぶ
+// oldest:   作成日時が最も古いメッセージを選ぶ
+// rotation: 今のサイクルでまだ投稿していないメッセージのうち、最後に投稿したのが最も前のものを選ぶ
+//           reshuffleが有効な場合は、まだ投稿していないメッセージから無作為に選ぶ
+//           すべて投稿し終えたら、呼び出し側で投稿済みの印を消して次のサイクルを始める
+use rand::Rng;
+use strum_macros::{Display, EnumString};
+use time::OffsetDateTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum Strategy {
+    Weighted,
+    Uniform,
+    Newest,
+    Oldest,
+    Rotation { reshuffle: bool },
+}
+
+// 選択の対象になるメッセージ
+pub trait Candidate {
+    fn priority(&self) -> i32;
+    fn created_at(&self) -> OffsetDateTime;
+    // 今のサイクルで投稿済みかどうか
+    fn tweeted(&self) -> bool;
+    fn last_tweeted_at(&self) -> Option<OffsetDateTime>;
+}
+
+impl Strategy {
+    // strategyが未設定のタスクは、従来のrandomフラグに合わせて選ぶ
+    pub fn from_task(strategy: Option<&str>, random: bool, reshuffle: bool) -> Self {
+        match strategy.and_then(|strategy| strategy.parse().ok()) {
+            Some(Strategy::Rotation { .. }) => Strategy::Rotation { reshuffle },
+            Some(strategy) => strategy,
+            None if random => Strategy::Uniform,
+            None => Strategy::Newest,
+        }
+    }
+
+    pub fn select<'a, T, R>(&self, candidates: &'a [T], rng: &mut R) -> Option<&'a T>
+    where
+        T: Candidate,
+        R: Rng + ?Sized,
+    {
+        if candidates.is_empty() {
+            return None;
+        }
+
+        match self {
+            Strategy::Weighted => select_weighted(candidates, rng),
+            Strategy::Uniform => candidates.get(rng.gen_range(0..candidates.len())),
+            Strategy::Newest => candidates.iter().max_by_key(|c| c.created_at()),
+            Strategy::Oldest => candidates.iter().min_by_key(|c| c.created_at()),
+            Strategy::Rotation { reshuffle } => select_rotation(candidates, *reshuffle, rng),
+        }
+    }
+
+    // ローテーションで、すべてのメッセージを投稿し終えたかどうか
+    pub fn cycle_completed<T: Candidate>(&self, candidates: &[T]) -> bool {
+        matches!(self, Strategy::Rotation { .. })
+            && candidates.iter().all(|candidate| candidate.tweeted())
+    }
+}
+
+// すべての優先度が0以下の場合は、どれも同じ確率で選ぶ
+fn select_weighted<'a, T, R>(candidates: &'a [T], rng: &mut R) -> Option<&'a T>
+where
+    T: Candidate,
+    R: Rng + ?Sized,
+{
+    let weight = |candidate: &T| candidate.priority().max(0) as u64;
+    let total: u64 = candidates.iter().map(weight).sum();
+
+    if total == 0 {
+        return candidates.get(rng.gen_range(0..candidates.len()));
+    }
+
+    let mut point = rng.gen_range(0..total);
+    candidates.iter().find(|candidate| {
+        let weight = weight(candidate);
+        if point < weight {
+            true
+        } else {
+            point -= weight;
+            false
+        }
+    })
+}
+
+// 投稿済みの印が消されていない場合も、すべてのメッセージを候補にする
+fn select_rotation<'a, T, R>(candidates: &'a [T], reshuffle: bool, rng: &mut R) -> Option<&'a T>
+where
+    T: Candidate,
+    R: Rng + ?Sized,
+{
+    let remaining: Vec<&T> = candidates.iter().filter(|c| !c.tweeted()).collect();
+    let remaining = if remaining.is_empty() {
+        candidates.iter().collect()
+    } else {
+        remaining
+    };
+
+    if reshuffle {
+        remaining.get(rng.gen_range(0..remaining.len())).copied()
+    } else {
+        remaining
+            .into_iter()
+            .min_by_key(|c| (c.last_tweeted_at(), c.created_at()))
+    }
+}
+
+#[test]
+fn test_select_weighted() {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    let messages = vec![message(1, 0), message(3, 1), message(0, 2)];
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut counts = [0; 3];
+
+    for _ in 0..10000 {
+        let selected = Strategy::Weighted.select(&messages, &mut rng).unwrap();
+        let index = messages
+            .iter()
+            .position(|message| std::ptr::eq(message, selected))
+            .unwrap();
+        counts[index] += 1;
+    }
+
+    // 優先度1:3なので、おおよそ2500回と7500回になる
+    assert!((2300..2700).contains(&counts[0]), "{:?}", counts);
+    assert!((7300..7700).contains(&counts[1]), "{:?}", counts);
+    assert_eq!(counts[2], 0);
+}
+
+#[test]
+fn test_select_strategies() {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    let messages = vec![message(0, 20), message(0, 10), message(0, 30)];
+    let mut rng = StdRng::seed_from_u64(7);
+
+    let created_at = |message: Option<&crate::model::Message>| {
+        message.map(|message| message.created_at().unix_timestamp())
+    };
+
+    assert_eq!(
+        created_at(Strategy::Newest.select(&messages, &mut rng)),
+        Some(30)
+    );
+    assert_eq!(
+        created_at(Strategy::Oldest.select(&messages, &mut rng)),
+        Some(10)
+    );
+    assert!(Strategy::Weighted.select(&messages, &mut rng).is_some());
+    assert!(Strategy::Uniform.select(&messages[..0], &mut rng).is_none());
+
+    assert_eq!(
+        Strategy::from_task(Some("oldest"), true, false),
+        Strategy::Oldest
+    );
+    assert_eq!(
+        Strategy::from_task(Some("rotation"), false, true),
+        Strategy::Rotation { reshuffle: true }
+    );
+    assert_eq!(Strategy::from_task(None, true, false), Strategy::Uniform);
+    assert_eq!(Strategy::from_task(None, false, false), Strategy::Newest);
+}
+
+#[test]
+fn test_select_rotation() {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    let mut messages = vec![message(1, 10), message(1, 20), message(1, 30)];
+    let mut rng = StdRng::seed_from_u64(1);
+    let strategy = Strategy::Rotation { reshuffle: false };
+
+    // 1サイクルで古い順にすべてのメッセージを1回ずつ投稿する
+    for (tweeted_at, expected) in [(100, 10), (200, 20), (300, 30)] {
+        let index = messages
+            .iter()
+            .position(|m| std::ptr::eq(m, strategy.select(&messages, &mut rng).unwrap()))
+            .unwrap();
+        assert_eq!(messages[index].created_at().unix_timestamp(), expected);
+
+        messages[index] = messages[index]
+            .clone()
+            .tweeted_at(OffsetDateTime::from_unix_timestamp(tweeted_at).unwrap());
+    }
+    assert!(strategy.cycle_completed(&messages));
+
+    // 次のサイクルでは最後に投稿したのが最も前のメッセージから始める
+    let messages: Vec<_> = messages.into_iter().map(|m| m.reset_rotation()).collect();
+    let selected = strategy.select(&messages, &mut rng).unwrap();
+    assert_eq!(selected.created_at().unix_timestamp(), 10);
+    assert!(!strategy.cycle_completed(&messages));
+
+    // reshuffleの場合も、同じサイクルで同じメッセージは選ばない
+    let strategy = Strategy::Rotation { reshuffle: true };
+    let mut messages = messages;
+    for _ in 0..3 {
+        let index = messages
+            .iter()
+            .position(|m| std::ptr::eq(m, strategy.select(&messages, &mut rng).unwrap()))
+            .unwrap();
+        assert!(!messages[index].tweeted());
+        messages[index] = messages[index]
+            .clone()
+            .tweeted_at(OffsetDateTime::now_utc());
+    }
+    assert!(strategy.cycle_completed(&messages));
+}
+
+#[cfg(test)]
+fn message(priority: i32, created_at: i64) -> crate::model::Message {
+    let created_at = OffsetDateTime::from_unix_timestamp(created_at).unwrap();
+
+    crate::model::Message::new(
+        uuid::Uuid::new_v4(),
+        String::from("user"),
+        String::from("text"),
+        vec![],
+        priority,
+        created_at,
+        created_at,
+        false,
+        None,
+        crate::model::ThreadRetry::default(),
+        None,
+        vec![],
+    )
+}
@@ -0,0 +1,270 @@
+use super::{Error, InvalidKeySnafu, IoSnafu, MediaStore, StoredObject, TokenSnafu};
+use crate::config::CONFIG;
+use async_session::async_trait;
+use serde::{Deserialize, Serialize};
+use snafu::prelude::*;
+use std::path::{Component, Path, PathBuf};
+
+// MinIOを用意しなくても開発やテストができるように、ローカルのファイルシステムに保存する
+// presigned URLの代わりに、`/storage/{user_id}/{key}`へのURLに期限付きのトークンを付けて返す
+pub struct LocalStore {
+    root: PathBuf,
+    base_url: String,
+    secret: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    // `{user_id}/{key}`
+    sub: String,
+    method: String,
+    content_type: Option<String>,
+    exp: usize,
+}
+
+impl LocalStore {
+    pub fn new(root: impl Into<PathBuf>, base_url: String, secret: String) -> Self {
+        LocalStore {
+            root: root.into(),
+            base_url,
+            secret,
+        }
+    }
+
+    pub fn from_config() -> Self {
+        LocalStore::new(
+            &CONFIG.media_store_path,
+            CONFIG.backend_endpoint.clone(),
+            CONFIG.jwt_secret(),
+        )
+    }
+
+    // `..`などでユーザーのディレクトリの外を指すキーは受け付けない
+    fn path(&self, user_id: &str, key: &str) -> Result<PathBuf, Error> {
+        let relative = Path::new(user_id).join(key);
+        ensure!(
+            !user_id.is_empty()
+                && !user_id.contains('/')
+                && relative
+                    .components()
+                    .all(|component| matches!(component, Component::Normal(_))),
+            InvalidKeySnafu {
+                key: format!("{}/{}", user_id, key)
+            }
+        );
+
+        Ok(self.root.join(relative))
+    }
+
+    fn sign(
+        &self,
+        user_id: &str,
+        key: &str,
+        method: &str,
+        expiry_secs: u32,
+        content_type: Option<&str>,
+    ) -> Result<String, Error> {
+        self.path(user_id, key)?;
+
+        let claims = Claims {
+            sub: format!("{}/{}", user_id, key),
+            method: method.to_string(),
+            content_type: content_type.map(str::to_string),
+            exp: (time::OffsetDateTime::now_utc().unix_timestamp() + expiry_secs as i64) as usize,
+        };
+        let token = jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            &claims,
+            &jsonwebtoken::EncodingKey::from_secret(self.secret.as_bytes()),
+        )
+        .context(TokenSnafu)?;
+
+        Ok(format!(
+            "{}/storage/{}/{}?token={}",
+            self.base_url, user_id, key, token
+        ))
+    }
+
+    // `/storage/{user_id}/{key}`へのリクエストが、発行したURLどおりか確認する
+    pub fn verify(
+        &self,
+        path: &str,
+        method: &str,
+        content_type: Option<&str>,
+        token: &str,
+    ) -> Result<bool, Error> {
+        let claims = jsonwebtoken::decode::<Claims>(
+            token,
+            &jsonwebtoken::DecodingKey::from_secret(self.secret.as_bytes()),
+            &jsonwebtoken::Validation::default(),
+        )
+        .context(TokenSnafu)?
+        .claims;
+
+        Ok(claims.sub == path
+            && claims.method == method
+            && claims
+                .content_type
+                .as_deref()
+                .is_none_or(|expected| Some(expected) == content_type))
+    }
+}
+
+#[async_trait]
+impl MediaStore for LocalStore {
+    async fn get(&self, user_id: &str, key: &str) -> Result<Vec<u8>, Error> {
+        tokio::fs::read(self.path(user_id, key)?)
+            .await
+            .context(IoSnafu)
+    }
+
+    // Content-Typeは保存せず、配信するときにファイルの中身から判定する
+    async fn put(
+        &self,
+        user_id: &str,
+        key: &str,
+        bytes: &[u8],
+        _content_type: &str,
+    ) -> Result<(), Error> {
+        let path = self.path(user_id, key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await.context(IoSnafu)?;
+        }
+
+        tokio::fs::write(path, bytes).await.context(IoSnafu)
+    }
+
+    async fn delete(&self, user_id: &str, key: &str) -> Result<(), Error> {
+        tokio::fs::remove_file(self.path(user_id, key)?)
+            .await
+            .context(IoSnafu)
+    }
+
+    async fn list(&self, user_id: &str) -> Result<Vec<StoredObject>, Error> {
+        let root = self.path(user_id, "")?;
+        let mut objects = Vec::new();
+        let mut dirs = vec![root.clone()];
+
+        while let Some(dir) = dirs.pop() {
+            let mut entries = match tokio::fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(Error::Io { source: err }),
+            };
+
+            while let Some(entry) = entries.next_entry().await.context(IoSnafu)? {
+                let metadata = entry.metadata().await.context(IoSnafu)?;
+                if metadata.is_dir() {
+                    dirs.push(entry.path());
+                    continue;
+                }
+
+                let key = entry
+                    .path()
+                    .strip_prefix(&root)
+                    .ok()
+                    .and_then(|key| key.to_str())
+                    .map(|key| key.replace(std::path::MAIN_SEPARATOR, "/"));
+
+                if let Some(key) = key {
+                    objects.push(StoredObject {
+                        key,
+                        size: metadata.len(),
+                        last_modified: metadata.modified().ok().map(time::OffsetDateTime::from),
+                    });
+                }
+            }
+        }
+
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(objects)
+    }
+
+    async fn presign_get(
+        &self,
+        user_id: &str,
+        key: &str,
+        expiry_secs: u32,
+    ) -> Result<String, Error> {
+        self.sign(user_id, key, "GET", expiry_secs, None)
+    }
+
+    async fn presign_put(
+        &self,
+        user_id: &str,
+        key: &str,
+        expiry_secs: u32,
+        content_type: Option<&str>,
+    ) -> Result<String, Error> {
+        self.sign(user_id, key, "PUT", expiry_secs, content_type)
+    }
+}
+
+#[tokio::test]
+async fn test_local_store() {
+    let root = std::env::temp_dir().join(format!("still-bott-{}", uuid::Uuid::new_v4()));
+    let store = LocalStore::new(
+        &root,
+        "http://localhost:8080".to_string(),
+        "secret".to_string(),
+    );
+    let user_id = "751625934894084097";
+
+    assert!(store.list(user_id).await.unwrap().is_empty());
+
+    store
+        .put(user_id, "original", b"original", "image/jpeg")
+        .await
+        .unwrap();
+    store
+        .put(
+            user_id,
+            "thumbnails/original/240.jpg",
+            b"thumb",
+            "image/jpeg",
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(store.get(user_id, "original").await.unwrap(), b"original");
+    let keys: Vec<String> = store
+        .list(user_id)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|object| object.key)
+        .collect();
+    assert_eq!(keys, vec!["original", "thumbnails/original/240.jpg"]);
+    assert_eq!(store.usage(user_id).await.unwrap(), 13);
+
+    assert!(store.get(user_id, "../other/original").await.is_err());
+    assert!(store.get("../other", "original").await.is_err());
+
+    let url = store
+        .presign_put(user_id, "upload", 60, Some("image/png"))
+        .await
+        .unwrap();
+    let token = url.split("token=").nth(1).unwrap();
+    let path = format!("{}/upload", user_id);
+    assert!(url.starts_with("http://localhost:8080/storage/751625934894084097/upload?"));
+    assert!(store
+        .verify(&path, "PUT", Some("image/png"), token)
+        .unwrap());
+    assert!(!store
+        .verify(&path, "PUT", Some("image/gif"), token)
+        .unwrap());
+    assert!(!store.verify(&path, "GET", None, token).unwrap());
+    assert!(!store
+        .verify(
+            &format!("{}/other", user_id),
+            "PUT",
+            Some("image/png"),
+            token
+        )
+        .unwrap());
+
+    store.delete(user_id, "original").await.unwrap();
+    assert_eq!(store.list(user_id).await.unwrap().len(), 1);
+
+    std::fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,76 @@
+use super::{Error, Layout, MediaStore, MigrationSnafu, MinioStore};
+use crate::config::{MediaStoreKind, MediaStoreLayout, CONFIG};
+use snafu::prelude::*;
+
+#[derive(Debug, Default)]
+pub struct MigrationReport {
+    pub copied: usize,
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+// 以前のユーザーごとのバケットから、設定したストレージにオブジェクトをコピーする
+// 移行先に同じキーがあるオブジェクトはコピーしない。元のバケットは確認のために残す
+pub async fn migrate_buckets(dry_run: bool) -> Result<MigrationReport, Error> {
+    ensure!(
+        !(CONFIG.media_store == MediaStoreKind::S3
+            && CONFIG.media_store_layout == MediaStoreLayout::BucketPerUser),
+        MigrationSnafu {
+            message: "set MEDIA_STORE_LAYOUT=single_bucket or MEDIA_STORE=local",
+        }
+    );
+
+    let source = MinioStore::from_config(Layout::BucketPerUser)?;
+    let target = super::store();
+
+    let user_ids = crate::gq::list_user_ids::exec()
+        .await
+        .map_err(|err| Error::Migration {
+            message: format!("failed to list users: {:?}", err),
+        })?;
+
+    let mut report = MigrationReport::default();
+    for user_id in user_ids {
+        let existing: std::collections::HashSet<String> = target
+            .list(&user_id)
+            .await?
+            .into_iter()
+            .map(|object| object.key)
+            .collect();
+
+        for object in source.list(&user_id).await? {
+            if existing.contains(&object.key) {
+                report.skipped += 1;
+                continue;
+            }
+
+            if dry_run {
+                tracing::info!("[dry run] Would copy {}/{}", user_id, object.key);
+                report.copied += 1;
+                continue;
+            }
+
+            match copy(&source, target, &user_id, &object.key).await {
+                Ok(()) => report.copied += 1,
+                Err(err) => {
+                    tracing::error!("Failed to copy {}/{}: {:?}", user_id, object.key, err);
+                    report.failed += 1;
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+async fn copy(
+    source: &MinioStore,
+    target: &dyn MediaStore,
+    user_id: &str,
+    key: &str,
+) -> Result<(), Error> {
+    let bytes = source.get(user_id, key).await?;
+    let content_type = crate::media::content_type(&bytes).unwrap_or("application/octet-stream");
+
+    target.put(user_id, key, &bytes, content_type).await
+}
@@ -0,0 +1,211 @@
+use super::{
+    BucketSnafu, CreateBucketSnafu, CredentialsSnafu, Error, MediaStore, RequestSnafu, StoredObject,
+};
+use crate::config::{MediaStoreLayout, CONFIG};
+use async_session::async_trait;
+use snafu::prelude::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Layout {
+    // Twitterのユーザーごとにバケットを作る（以前からの配置）
+    BucketPerUser,
+    // 1つのバケットに`{user_id}/`のプレフィックスを付けて保存する
+    SingleBucket { bucket: String },
+}
+
+impl Layout {
+    pub fn from_config() -> Self {
+        match CONFIG.media_store_layout {
+            MediaStoreLayout::BucketPerUser => Layout::BucketPerUser,
+            MediaStoreLayout::SingleBucket => Layout::SingleBucket {
+                bucket: CONFIG.media_bucket.clone(),
+            },
+        }
+    }
+}
+
+pub struct MinioStore {
+    region: s3::region::Region,
+    credentials: s3::creds::Credentials,
+    layout: Layout,
+}
+
+impl MinioStore {
+    pub fn from_config(layout: Layout) -> Result<Self, Error> {
+        let region = s3::region::Region::Custom {
+            region: CONFIG.s3_region.clone(),
+            endpoint: CONFIG.minio_endpoint.clone(),
+        };
+        let credentials = s3::creds::Credentials::default().context(CredentialsSnafu)?;
+
+        Ok(MinioStore {
+            region,
+            credentials,
+            layout,
+        })
+    }
+
+    fn bucket_name<'a>(&'a self, user_id: &'a str) -> &'a str {
+        match &self.layout {
+            Layout::BucketPerUser => user_id,
+            Layout::SingleBucket { bucket } => bucket,
+        }
+    }
+
+    fn prefix(&self, user_id: &str) -> String {
+        match &self.layout {
+            Layout::BucketPerUser => String::new(),
+            Layout::SingleBucket { .. } => format!("{}/", user_id),
+        }
+    }
+
+    fn path(&self, user_id: &str, key: &str) -> String {
+        format!("{}{}", self.prefix(user_id), key)
+    }
+
+    fn bucket(&self, user_id: &str) -> Result<s3::Bucket, Error> {
+        Ok(s3::Bucket::new(
+            self.bucket_name(user_id),
+            self.region.clone(),
+            self.credentials.clone(),
+        )
+        .context(BucketSnafu)?
+        .with_path_style())
+    }
+
+    async fn exists(bucket: &s3::Bucket) -> bool {
+        bucket.head_object("/").await.is_ok()
+    }
+
+    // もし既存のバケットが存在しない場合には新たに作成する
+    async fn get_or_create_bucket(&self, user_id: &str) -> Result<s3::Bucket, Error> {
+        let bucket = self.bucket(user_id)?;
+
+        if !Self::exists(&bucket).await {
+            let bucket_name = self.bucket_name(user_id);
+            s3::Bucket::create_with_path_style(
+                bucket_name,
+                self.region.clone(),
+                self.credentials.clone(),
+                s3::BucketConfiguration::default(),
+            )
+            .await
+            .context(CreateBucketSnafu {
+                bucket: bucket_name,
+            })?;
+            tracing::info!("Bucket {} created", bucket_name);
+        }
+
+        Ok(bucket)
+    }
+}
+
+#[async_trait]
+impl MediaStore for MinioStore {
+    async fn get(&self, user_id: &str, key: &str) -> Result<Vec<u8>, Error> {
+        let object = self
+            .bucket(user_id)?
+            .get_object(self.path(user_id, key))
+            .await
+            .context(RequestSnafu)?;
+
+        Ok(object.bytes().to_vec())
+    }
+
+    async fn put(
+        &self,
+        user_id: &str,
+        key: &str,
+        bytes: &[u8],
+        content_type: &str,
+    ) -> Result<(), Error> {
+        self.get_or_create_bucket(user_id)
+            .await?
+            .put_object_with_content_type(self.path(user_id, key), bytes, content_type)
+            .await
+            .context(RequestSnafu)?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, user_id: &str, key: &str) -> Result<(), Error> {
+        self.bucket(user_id)?
+            .delete_object(self.path(user_id, key))
+            .await
+            .context(RequestSnafu)?;
+
+        Ok(())
+    }
+
+    // 一覧するだけでバケットを作らないように、存在しなければ空を返す
+    async fn list(&self, user_id: &str) -> Result<Vec<StoredObject>, Error> {
+        let bucket = self.bucket(user_id)?;
+        if !Self::exists(&bucket).await {
+            return Ok(Vec::new());
+        }
+
+        let prefix = self.prefix(user_id);
+        let results = bucket
+            .list(prefix.clone(), None)
+            .await
+            .context(RequestSnafu)?;
+
+        Ok(results
+            .into_iter()
+            .flat_map(|result| result.contents)
+            .filter_map(|object| {
+                Some(StoredObject {
+                    key: object.key.strip_prefix(&prefix)?.to_string(),
+                    size: object.size,
+                    last_modified: time::OffsetDateTime::parse(
+                        &object.last_modified,
+                        &time::format_description::well_known::Rfc3339,
+                    )
+                    .ok(),
+                })
+            })
+            .collect())
+    }
+
+    async fn presign_get(
+        &self,
+        user_id: &str,
+        key: &str,
+        expiry_secs: u32,
+    ) -> Result<String, Error> {
+        self.bucket(user_id)?
+            .presign_get(self.path(user_id, key), expiry_secs, None)
+            .context(RequestSnafu)
+    }
+
+    async fn presign_put(
+        &self,
+        user_id: &str,
+        key: &str,
+        expiry_secs: u32,
+        content_type: Option<&str>,
+    ) -> Result<String, Error> {
+        // 申告と違う形式のファイルはMinIOが受け付けないように、Content-Typeも署名に含める
+        let headers = match content_type {
+            Some(content_type) => {
+                let mut headers = http::HeaderMap::new();
+                headers.insert(
+                    http::header::CONTENT_TYPE,
+                    http::HeaderValue::from_str(content_type).map_err(|_| {
+                        Error::InvalidContentType {
+                            content_type: content_type.to_string(),
+                        }
+                    })?,
+                );
+                Some(headers)
+            }
+            None => None,
+        };
+
+        // アップロード先のバケットは、URLを発行する前に作成しておく
+        self.get_or_create_bucket(user_id)
+            .await?
+            .presign_put(self.path(user_id, key), expiry_secs, headers)
+            .context(RequestSnafu)
+    }
+}
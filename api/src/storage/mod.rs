@@ -0,0 +1,118 @@
+// メディアのオリジナルとサムネイルを保存するストレージ
+// 設定によって、S3（MinIO）かローカルのファイルシステムを使う
+mod local;
+mod migrate;
+mod minio;
+
+pub use self::local::LocalStore;
+pub use self::migrate::migrate_buckets;
+pub use self::minio::{Layout, MinioStore};
+
+use crate::config::{MediaStoreKind, CONFIG};
+use async_session::async_trait;
+use once_cell::sync::Lazy;
+use snafu::prelude::*;
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum Error {
+    Credentials {
+        source: s3::creds::error::CredentialsError,
+    },
+    Bucket {
+        source: s3::error::S3Error,
+    },
+    #[snafu(display("Failed to create bucket {bucket}"))]
+    CreateBucket {
+        bucket: String,
+        source: s3::error::S3Error,
+    },
+    Request {
+        source: s3::error::S3Error,
+    },
+    Io {
+        source: std::io::Error,
+    },
+    Token {
+        source: jsonwebtoken::errors::Error,
+    },
+    #[snafu(display("Invalid object key {key}"))]
+    InvalidKey {
+        key: String,
+    },
+    #[snafu(display("Invalid content type {content_type}"))]
+    InvalidContentType {
+        content_type: String,
+    },
+    #[snafu(display("Media store is not configured for migration: {message}"))]
+    Migration {
+        message: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct StoredObject {
+    // ユーザーごとのプレフィックスを含まないキー
+    pub key: String,
+    pub size: u64,
+    pub last_modified: Option<time::OffsetDateTime>,
+}
+
+// オブジェクトはユーザーごとに分けて保存し、キーはユーザーの中で一意にする
+#[async_trait]
+pub trait MediaStore: Send + Sync {
+    async fn get(&self, user_id: &str, key: &str) -> Result<Vec<u8>, Error>;
+
+    async fn put(
+        &self,
+        user_id: &str,
+        key: &str,
+        bytes: &[u8],
+        content_type: &str,
+    ) -> Result<(), Error>;
+
+    async fn delete(&self, user_id: &str, key: &str) -> Result<(), Error>;
+
+    // まだ何も保存していないユーザーは空の一覧を返す
+    async fn list(&self, user_id: &str) -> Result<Vec<StoredObject>, Error>;
+
+    async fn presign_get(
+        &self,
+        user_id: &str,
+        key: &str,
+        expiry_secs: u32,
+    ) -> Result<String, Error>;
+
+    // content_typeを指定すると、異なるContent-Typeのアップロードは拒否される
+    async fn presign_put(
+        &self,
+        user_id: &str,
+        key: &str,
+        expiry_secs: u32,
+        content_type: Option<&str>,
+    ) -> Result<String, Error>;
+
+    // オリジナルとサムネイルの合計サイズ（バイト）
+    async fn usage(&self, user_id: &str) -> Result<u64, Error> {
+        Ok(self
+            .list(user_id)
+            .await?
+            .iter()
+            .map(|object| object.size)
+            .sum())
+    }
+}
+
+static STORE: Lazy<Box<dyn MediaStore>> =
+    Lazy::new(|| from_config().expect("Failed to setup media store"));
+
+pub fn store() -> &'static dyn MediaStore {
+    STORE.as_ref()
+}
+
+fn from_config() -> Result<Box<dyn MediaStore>, Error> {
+    Ok(match CONFIG.media_store {
+        MediaStoreKind::S3 => Box::new(MinioStore::from_config(Layout::from_config())?),
+        MediaStoreKind::Local => Box::new(LocalStore::from_config()),
+    })
+}
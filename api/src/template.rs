@@ -0,0 +1,849 @@
+// RSSの記事をツイート本文に変換するテンプレートエンジン
+//
+// 書式
+//   {title}                                  変数の埋め込み
+//   {pub_date|%Y年%m月%d日}                   日付の書式指定
+//   {description|strip_html|truncate:100}    フィルタ（左から順に適用）
+//   {#if author}by {author}{#else}...{/if}   変数が空でない場合のみ出力
+//   {{ }}                                    波括弧そのもの
+//   \n                                       改行（以前のテンプレートとの互換のため）
+//
+// 変数
+//   title, url, description, summary, author, categories, feed_title, pub_date
+//
+// フィルタ
+//   strip_html      HTMLタグを除去し、実体参照を展開する
+//   truncate:N      重み付きの文字数でN文字に切り詰める
+//   fit             ツイート全体が280文字に収まるように切り詰める
+//   hashtags        カテゴリーをハッシュタグに変換する
+//   join:SEP        リストをSEPで連結する
+//   default:TEXT    値が空の場合にTEXTを出力する
+//   date:FORMAT     日付の書式指定（`%`で始まる場合は`date:`を省略できる）
+use crate::twitter_text::{char_weight, weighted_length, MAX_WEIGHTED_LENGTH};
+use snafu::prelude::*;
+use std::collections::HashMap;
+use time::OffsetDateTime;
+
+pub const DEFAULT_TEMPLATE: &str = "{title}\n\n{url}";
+
+const VARIABLES: [&str; 8] = [
+    "title",
+    "url",
+    "description",
+    "summary",
+    "author",
+    "categories",
+    "feed_title",
+    "pub_date",
+];
+
+const ELLIPSIS: char = '…';
+
+#[derive(Debug, Snafu, Clone, PartialEq, Eq)]
+#[snafu(visibility(pub(crate)))]
+pub enum Error {
+    #[snafu(display("{position}文字目: '{{' が閉じられていません"))]
+    UnclosedBrace { position: usize },
+
+    #[snafu(display("{position}文字目: 対応する '{{' のない '}}' があります"))]
+    UnmatchedBrace { position: usize },
+
+    #[snafu(display("{position}文字目: 変数名が指定されていません"))]
+    EmptyExpression { position: usize },
+
+    #[snafu(display("{position}文字目: `{name}` という変数はありません"))]
+    UnknownVariable { position: usize, name: String },
+
+    #[snafu(display("{position}文字目: `{name}` というフィルタはありません"))]
+    UnknownFilter { position: usize, name: String },
+
+    #[snafu(display("{position}文字目: フィルタ `{name}` の引数 `{argument}` が不正です"))]
+    InvalidArgument {
+        position: usize,
+        name: String,
+        argument: String,
+    },
+
+    #[snafu(display("{position}文字目: 日付の書式 `{format}` が不正です"))]
+    InvalidDateFormat { position: usize, format: String },
+
+    #[snafu(display("{position}文字目: {{#if}} に対応する {{/if}} がありません"))]
+    UnclosedIf { position: usize },
+
+    #[snafu(display("{position}文字目: {{#if}} のない `{tag}` があります"))]
+    UnexpectedTag { position: usize, tag: String },
+}
+
+impl Error {
+    pub fn position(&self) -> usize {
+        match self {
+            Error::UnclosedBrace { position }
+            | Error::UnmatchedBrace { position }
+            | Error::EmptyExpression { position }
+            | Error::UnknownVariable { position, .. }
+            | Error::UnknownFilter { position, .. }
+            | Error::InvalidArgument { position, .. }
+            | Error::InvalidDateFormat { position, .. }
+            | Error::UnclosedIf { position }
+            | Error::UnexpectedTag { position, .. } => *position,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Var {
+        name: String,
+        filters: Vec<Filter>,
+    },
+    If {
+        name: String,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Filter {
+    StripHtml,
+    Truncate(usize),
+    Fit,
+    Hashtags,
+    Join(String),
+    Default(String),
+    Date(Vec<DateItem>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum DateItem {
+    Literal(String),
+    Spec { spec: char, pad: bool },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self, Error> {
+        Self::parse_all(source).map_err(|errors| {
+            errors
+                .into_iter()
+                .next()
+                .expect("parse_all never returns an empty error list")
+        })
+    }
+
+    // 保存前の検証用に、見つかったエラーをすべて返す
+    pub fn validate(source: &str) -> Vec<Error> {
+        match Self::parse_all(source) {
+            Ok(_) => vec![],
+            Err(errors) => errors,
+        }
+    }
+
+    fn parse_all(source: &str) -> Result<Self, Vec<Error>> {
+        let tokens = tokenize(source).map_err(|err| vec![err])?;
+
+        let mut errors = Vec::new();
+        let nodes = build_tree(tokens, &mut errors);
+
+        if errors.is_empty() {
+            Ok(Self { nodes })
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn render(&self, context: &Context) -> String {
+        let mut pieces = Vec::new();
+        render_nodes(&self.nodes, context, &mut pieces);
+
+        fit_pieces(pieces)
+    }
+}
+
+//
+
+#[derive(Debug, Clone)]
+enum Value {
+    Text(String),
+    List(Vec<String>),
+    Date(OffsetDateTime),
+}
+
+impl Value {
+    fn is_empty(&self) -> bool {
+        match self {
+            Value::Text(text) => text.trim().is_empty(),
+            Value::List(list) => list.iter().all(|item| item.trim().is_empty()),
+            Value::Date(_) => false,
+        }
+    }
+
+    fn into_string(self) -> String {
+        match self {
+            Value::Text(text) => text,
+            Value::List(list) => list.join(", "),
+            Value::Date(date) => format_date(
+                date,
+                &parse_date_format("%Y-%m-%d %H:%M", 0).expect("default date format is valid"),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Context {
+    values: HashMap<&'static str, Value>,
+}
+
+impl Context {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.values.insert(name, Value::Text(value.into()));
+        self
+    }
+
+    pub fn list(mut self, name: &'static str, value: Vec<String>) -> Self {
+        self.values.insert(name, Value::List(value));
+        self
+    }
+
+    pub fn date(mut self, name: &'static str, value: OffsetDateTime) -> Self {
+        self.values.insert(name, Value::Date(value));
+        self
+    }
+
+    // テンプレートのプレビューに使う記事
+    pub fn sample() -> Self {
+        Self::new()
+            .text("title", "質屋が解説する金の見分け方")
+            .text("url", "https://www.shichimaru.com/blog/1")
+            .text(
+                "description",
+                "<p>金の&amp;純度は<b>刻印</b>で確認できます</p>",
+            )
+            .text("summary", "金の&純度は 刻印 で確認できます")
+            .text("author", "")
+            .list(
+                "categories",
+                vec!["質屋が解説".to_string(), "金 買取".to_string()],
+            )
+            .text("feed_title", "七つ屋ブログ")
+            .date(
+                "pub_date",
+                time::macros::datetime!(2023-08-01 09:05:00 +09:00),
+            )
+    }
+
+    fn get(&self, name: &str) -> Value {
+        self.values
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| Value::Text(String::new()))
+    }
+}
+
+//
+
+enum Token {
+    Text(String),
+    Tag { position: usize, body: String },
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, Error> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut text = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '{' if chars.get(i + 1) == Some(&'{') => {
+                text.push('{');
+                i += 2;
+            }
+            '}' if chars.get(i + 1) == Some(&'}') => {
+                text.push('}');
+                i += 2;
+            }
+            '\\' if chars.get(i + 1) == Some(&'n') => {
+                text.push('\n');
+                i += 2;
+            }
+            '{' => {
+                let position = i + 1;
+                let mut body = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        Some('}') => break,
+                        Some('{') | None => return Err(Error::UnclosedBrace { position }),
+                        Some(c) => body.push(*c),
+                    }
+                    i += 1;
+                }
+                i += 1;
+
+                if !text.is_empty() {
+                    tokens.push(Token::Text(std::mem::take(&mut text)));
+                }
+                tokens.push(Token::Tag { position, body });
+            }
+            '}' => return Err(Error::UnmatchedBrace { position: i + 1 }),
+            c => {
+                text.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    if !text.is_empty() {
+        tokens.push(Token::Text(text));
+    }
+
+    Ok(tokens)
+}
+
+struct Frame {
+    position: usize,
+    name: String,
+    then: Vec<Node>,
+    otherwise: Option<Vec<Node>>,
+}
+
+impl Frame {
+    fn target(&mut self) -> &mut Vec<Node> {
+        match self.otherwise {
+            Some(ref mut otherwise) => otherwise,
+            None => &mut self.then,
+        }
+    }
+}
+
+fn build_tree(tokens: Vec<Token>, errors: &mut Vec<Error>) -> Vec<Node> {
+    let mut root = Vec::new();
+    let mut stack: Vec<Frame> = Vec::new();
+
+    for token in tokens {
+        let node = match token {
+            Token::Text(text) => Node::Text(text),
+            Token::Tag { position, body } => {
+                let body = body.trim();
+
+                if let Some(name) = body.strip_prefix("#if") {
+                    let name = name.trim().to_string();
+                    check_variable(&name, position, errors);
+                    stack.push(Frame {
+                        position,
+                        name,
+                        then: vec![],
+                        otherwise: None,
+                    });
+                    continue;
+                } else if body == "#else" {
+                    match stack.last_mut() {
+                        Some(frame) if frame.otherwise.is_none() => {
+                            frame.otherwise = Some(vec![]);
+                        }
+                        _ => errors.push(Error::UnexpectedTag {
+                            position,
+                            tag: body.to_string(),
+                        }),
+                    }
+                    continue;
+                } else if body == "/if" {
+                    match stack.pop() {
+                        Some(frame) => Node::If {
+                            name: frame.name,
+                            then: frame.then,
+                            otherwise: frame.otherwise.unwrap_or_default(),
+                        },
+                        None => {
+                            errors.push(Error::UnexpectedTag {
+                                position,
+                                tag: body.to_string(),
+                            });
+                            continue;
+                        }
+                    }
+                } else {
+                    parse_expression(body, position, errors)
+                }
+            }
+        };
+
+        match stack.last_mut() {
+            Some(frame) => frame.target().push(node),
+            None => root.push(node),
+        }
+    }
+
+    if let Some(frame) = stack.first() {
+        errors.push(Error::UnclosedIf {
+            position: frame.position,
+        });
+    }
+
+    root
+}
+
+fn check_variable(name: &str, position: usize, errors: &mut Vec<Error>) {
+    if name.is_empty() {
+        errors.push(Error::EmptyExpression { position });
+    } else if !VARIABLES.contains(&name) {
+        errors.push(Error::UnknownVariable {
+            position,
+            name: name.to_string(),
+        });
+    }
+}
+
+fn parse_expression(body: &str, position: usize, errors: &mut Vec<Error>) -> Node {
+    let mut parts = body.split('|');
+    let name = parts.next().unwrap_or_default().trim().to_string();
+    check_variable(&name, position, errors);
+
+    let filters = parts
+        .filter_map(|filter| match parse_filter(filter.trim(), position) {
+            Ok(filter) => Some(filter),
+            Err(err) => {
+                errors.push(err);
+                None
+            }
+        })
+        .collect();
+
+    Node::Var { name, filters }
+}
+
+fn parse_filter(filter: &str, position: usize) -> Result<Filter, Error> {
+    if filter.starts_with('%') {
+        return parse_date_format(filter, position).map(Filter::Date);
+    }
+
+    let (name, argument) = match filter.split_once(':') {
+        Some((name, argument)) => (name.trim(), Some(argument)),
+        None => (filter, None),
+    };
+
+    let invalid_argument = || Error::InvalidArgument {
+        position,
+        name: name.to_string(),
+        argument: argument.unwrap_or_default().to_string(),
+    };
+
+    match (name, argument) {
+        ("strip_html", None) => Ok(Filter::StripHtml),
+        ("fit", None) => Ok(Filter::Fit),
+        ("hashtags", None) => Ok(Filter::Hashtags),
+        ("truncate", Some(argument)) => match argument.trim().parse::<usize>() {
+            Ok(limit) if limit > 0 => Ok(Filter::Truncate(limit)),
+            _ => Err(invalid_argument()),
+        },
+        ("join", argument) => Ok(Filter::Join(argument.unwrap_or(", ").to_string())),
+        ("default", Some(argument)) => Ok(Filter::Default(argument.to_string())),
+        ("date", Some(argument)) => parse_date_format(argument, position).map(Filter::Date),
+        ("strip_html" | "fit" | "hashtags" | "truncate" | "default" | "date", _) => {
+            Err(invalid_argument())
+        }
+        (name, _) => Err(Error::UnknownFilter {
+            position,
+            name: name.to_string(),
+        }),
+    }
+}
+
+fn parse_date_format(format: &str, position: usize) -> Result<Vec<DateItem>, Error> {
+    let invalid = || Error::InvalidDateFormat {
+        position,
+        format: format.to_string(),
+    };
+
+    let mut items = Vec::new();
+    let mut literal = String::new();
+    let mut chars = format.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            literal.push(c);
+            continue;
+        }
+
+        let pad = if chars.peek() == Some(&'-') {
+            chars.next();
+            false
+        } else {
+            true
+        };
+
+        match chars.next() {
+            Some('%') if pad => literal.push('%'),
+            Some(spec @ ('m' | 'd' | 'H' | 'M' | 'S')) => {
+                if !literal.is_empty() {
+                    items.push(DateItem::Literal(std::mem::take(&mut literal)));
+                }
+                items.push(DateItem::Spec { spec, pad });
+            }
+            Some(spec @ ('Y' | 'y' | 'a' | 'A' | 'b' | 'B')) if pad => {
+                if !literal.is_empty() {
+                    items.push(DateItem::Literal(std::mem::take(&mut literal)));
+                }
+                items.push(DateItem::Spec { spec, pad });
+            }
+            _ => return Err(invalid()),
+        }
+    }
+
+    if !literal.is_empty() {
+        items.push(DateItem::Literal(literal));
+    }
+
+    Ok(items)
+}
+
+fn format_date(date: OffsetDateTime, items: &[DateItem]) -> String {
+    const WEEKDAYS: [&str; 7] = [
+        "Sunday",
+        "Monday",
+        "Tuesday",
+        "Wednesday",
+        "Thursday",
+        "Friday",
+        "Saturday",
+    ];
+    const MONTHS: [&str; 12] = [
+        "January",
+        "February",
+        "March",
+        "April",
+        "May",
+        "June",
+        "July",
+        "August",
+        "September",
+        "October",
+        "November",
+        "December",
+    ];
+
+    let number = |value: u8, pad: bool| {
+        if pad {
+            format!("{:02}", value)
+        } else {
+            value.to_string()
+        }
+    };
+
+    let weekday = WEEKDAYS[date.weekday().number_days_from_sunday() as usize];
+    let month = MONTHS[u8::from(date.month()) as usize - 1];
+
+    items
+        .iter()
+        .map(|item| match item {
+            DateItem::Literal(literal) => literal.clone(),
+            DateItem::Spec { spec, pad } => match spec {
+                'Y' => date.year().to_string(),
+                'y' => format!("{:02}", date.year().rem_euclid(100)),
+                'm' => number(u8::from(date.month()), *pad),
+                'd' => number(date.day(), *pad),
+                'H' => number(date.hour(), *pad),
+                'M' => number(date.minute(), *pad),
+                'S' => number(date.second(), *pad),
+                'a' => weekday[..3].to_string(),
+                'A' => weekday.to_string(),
+                'b' => month[..3].to_string(),
+                'B' => month.to_string(),
+                _ => unreachable!("unsupported specs are rejected by parse_date_format"),
+            },
+        })
+        .collect()
+}
+
+//
+
+enum Piece {
+    Fixed(String),
+    Fit(String),
+}
+
+fn render_nodes(nodes: &[Node], context: &Context, pieces: &mut Vec<Piece>) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => pieces.push(Piece::Fixed(text.clone())),
+            Node::Var { name, filters } => {
+                let (value, fit) = apply_filters(context.get(name), filters);
+                if fit {
+                    pieces.push(Piece::Fit(value.into_string()))
+                } else {
+                    pieces.push(Piece::Fixed(value.into_string()))
+                }
+            }
+            Node::If {
+                name,
+                then,
+                otherwise,
+            } => {
+                if context.get(name).is_empty() {
+                    render_nodes(otherwise, context, pieces)
+                } else {
+                    render_nodes(then, context, pieces)
+                }
+            }
+        }
+    }
+}
+
+fn apply_filters(value: Value, filters: &[Filter]) -> (Value, bool) {
+    filters
+        .iter()
+        .fold((value, false), |(value, fit), filter| match filter {
+            Filter::StripHtml => (
+                match value {
+                    Value::Text(text) => Value::Text(strip_html(&text)),
+                    Value::List(list) => Value::List(list.iter().map(|s| strip_html(s)).collect()),
+                    date => date,
+                },
+                fit,
+            ),
+            Filter::Truncate(limit) => (Value::Text(truncate(&value.into_string(), *limit)), fit),
+            Filter::Fit => (value, true),
+            Filter::Hashtags => (
+                match value {
+                    Value::List(list) => Value::Text(hashtags(&list)),
+                    Value::Text(text) => Value::Text(hashtags(&[text])),
+                    date => date,
+                },
+                fit,
+            ),
+            Filter::Join(separator) => (
+                match value {
+                    Value::List(list) => Value::Text(list.join(separator)),
+                    other => other,
+                },
+                fit,
+            ),
+            Filter::Default(default) => {
+                if value.is_empty() {
+                    (Value::Text(default.clone()), fit)
+                } else {
+                    (value, fit)
+                }
+            }
+            Filter::Date(items) => (
+                match value {
+                    Value::Date(date) => Value::Text(format_date(date, items)),
+                    other => other,
+                },
+                fit,
+            ),
+        })
+}
+
+// `fit`が指定された部分に、固定部分を除いた残りの文字数を均等に割り当てる
+fn fit_pieces(pieces: Vec<Piece>) -> String {
+    let fixed: String = pieces
+        .iter()
+        .filter_map(|piece| match piece {
+            Piece::Fixed(text) => Some(text.as_str()),
+            Piece::Fit(_) => None,
+        })
+        .collect();
+
+    let fit_count = pieces
+        .iter()
+        .filter(|piece| matches!(piece, Piece::Fit(_)))
+        .count();
+
+    if fit_count == 0 {
+        return fixed;
+    }
+
+    let budget = MAX_WEIGHTED_LENGTH.saturating_sub(weighted_length(&fixed)) / fit_count;
+
+    pieces
+        .into_iter()
+        .map(|piece| match piece {
+            Piece::Fixed(text) => text,
+            Piece::Fit(text) => truncate(&text, budget),
+        })
+        .collect()
+}
+
+//
+
+fn truncate(text: &str, limit: usize) -> String {
+    if weighted_length(text) <= limit {
+        return text.to_string();
+    }
+
+    let ellipsis = char_weight(ELLIPSIS);
+    if limit < ellipsis {
+        return String::new();
+    }
+
+    let mut result = String::new();
+    let mut weight = 0;
+    for c in text.chars() {
+        if weight + char_weight(c) > limit - ellipsis {
+            break;
+        }
+        weight += char_weight(c);
+        result.push(c);
+    }
+
+    let mut result = result.trim_end().to_string();
+    result.push(ELLIPSIS);
+    result
+}
+
+pub fn strip_html(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    decode_entities(&text)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let decoded = rest.find(';').and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" | "#39" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end))
+        });
+
+        match decoded {
+            Some((c, end)) => {
+                result.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+// ハッシュタグに使えない記号や空白は取り除く
+fn hashtags(categories: &[String]) -> String {
+    let mut tags: Vec<String> = Vec::new();
+
+    for category in categories {
+        let tag: String = category
+            .chars()
+            .filter(|c| c.is_alphanumeric() || *c == '_')
+            .collect();
+
+        if !tag.is_empty() && !tag.chars().all(|c| c.is_ascii_digit()) {
+            let tag = format!("#{}", tag);
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+    }
+
+    tags.join(" ")
+}
+
+#[test]
+fn test_render_variables_and_filters() {
+    let template = Template::parse(
+        "{title} {pub_date|%Y年%-m月%-d日}\\n{description|strip_html}\n{categories|hashtags}",
+    )
+    .unwrap();
+
+    assert_eq!(
+        template.render(&Context::sample()),
+        "質屋が解説する金の見分け方 2023年8月1日\n金の&純度は 刻印 で確認できます\n#質屋が解説 #金買取"
+    );
+}
+
+#[test]
+fn test_render_conditionals() {
+    let template = Template::parse("{#if author}by {author}{#else}{feed_title}{/if}").unwrap();
+
+    assert_eq!(template.render(&Context::sample()), "七つ屋ブログ");
+}
+
+#[test]
+fn test_fit_to_max_length() {
+    let context = Context::new().text("title", "あ".repeat(200));
+    let template = Template::parse("{title|fit} {{x}}").unwrap();
+    let message = template.render(&context);
+
+    assert_eq!(weighted_length(&message), MAX_WEIGHTED_LENGTH);
+    assert!(message.ends_with("… {x}"));
+}
+
+#[test]
+fn test_validate_reports_errors() {
+    let errors = Template::validate("{titl} {pub_date|%Q} {title|upper} {#if url}");
+
+    assert_eq!(
+        errors,
+        vec![
+            Error::UnknownVariable {
+                position: 1,
+                name: "titl".to_string()
+            },
+            Error::InvalidDateFormat {
+                position: 8,
+                format: "%Q".to_string()
+            },
+            Error::UnknownFilter {
+                position: 22,
+                name: "upper".to_string()
+            },
+            Error::UnclosedIf { position: 36 },
+        ]
+    );
+
+    assert_eq!(
+        Template::validate("{title"),
+        vec![Error::UnclosedBrace { position: 1 }]
+    );
+}
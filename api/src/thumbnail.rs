@@ -0,0 +1,95 @@
+// メディアのサムネイルをオリジナルと同じストレージに保存し、期限付きのURLで配信する
+use crate::media::THUMBNAIL_SIZES;
+use crate::storage::store;
+use image::DynamicImage;
+use snafu::prelude::*;
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum Error {
+    Storage {
+        source: crate::storage::Error,
+    },
+    Media {
+        source: crate::media::Error,
+    },
+    Hasura {
+        source: crate::gq::error::HasuraError,
+    },
+}
+
+// presigned URLの有効期限（秒）
+pub const URL_EXPIRY_SECS: u32 = 10 * 60;
+
+pub fn object_key(media_id: uuid::Uuid, size: u32) -> String {
+    format!("thumbnails/{}/{}.jpg", media_id, size)
+}
+
+// mediaテーブルのthumbnailには、ログイン中のユーザーをpresigned URLへリダイレクトするパスを保存する
+pub fn path(media_id: uuid::Uuid) -> String {
+    format!("/thumbnails/{}/{}", media_id, crate::media::THUMBNAIL_SIZE)
+}
+
+pub fn is_valid_size(size: u32) -> bool {
+    THUMBNAIL_SIZES.contains(&size)
+}
+
+// すべての大きさのサムネイルを保存し、mediaテーブルに保存するパスを返す
+pub async fn save(
+    user_id: &str,
+    media_id: uuid::Uuid,
+    image: &DynamicImage,
+) -> Result<String, Error> {
+    for size in THUMBNAIL_SIZES {
+        let bytes = crate::media::thumbnail_jpeg(image, size).context(MediaSnafu)?;
+        store()
+            .put(user_id, &object_key(media_id, size), &bytes, "image/jpeg")
+            .await
+            .context(StorageSnafu)?;
+    }
+
+    Ok(path(media_id))
+}
+
+pub async fn presign(user_id: &str, media_id: uuid::Uuid, size: u32) -> Result<String, Error> {
+    store()
+        .presign_get(user_id, &object_key(media_id, size), URL_EXPIRY_SECS)
+        .await
+        .context(StorageSnafu)
+}
+
+// 動画などサムネイルが無いメディアもあるので、削除できなくてもエラーにしない
+pub async fn delete(user_id: &str, media_id: uuid::Uuid) {
+    for size in THUMBNAIL_SIZES {
+        if let Err(err) = store().delete(user_id, &object_key(media_id, size)).await {
+            tracing::warn!("Ignoring error on deleting thumbnail {}: {}", media_id, err);
+        }
+    }
+}
+
+// 以前はdata URLとしてPostgresに保存していたサムネイルを、オリジナルから作り直してストレージに移す
+// `backfill-thumbnails`コマンドで実行する。移せなかったメディアはdata URLのまま残る
+pub async fn backfill() -> Result<crate::backfill::Report, Error> {
+    crate::backfill::run(
+        crate::gq::list_legacy_thumbnails::exec,
+        |media_id, user_id: String| async move { backfill_one(media_id, &user_id).await },
+    )
+    .await
+    .context(HasuraSnafu)
+}
+
+async fn backfill_one(media_id: uuid::Uuid, user_id: &str) -> Result<(), Error> {
+    let bytes = store()
+        .get(user_id, &media_id.to_string())
+        .await
+        .context(StorageSnafu)?;
+
+    let image = crate::media::decode_oriented(&bytes).context(MediaSnafu)?;
+    let path = save(user_id, media_id, &image).await?;
+
+    crate::gq::update_media_thumbnail::exec(media_id, path)
+        .await
+        .context(HasuraSnafu)?;
+
+    Ok(())
+}
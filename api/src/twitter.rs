@@ -20,6 +20,15 @@ pub enum Error {
     },
     UserNotFound,
     UploadMedia,
+    // media.writeを追加する前にログインしたユーザーのトークンには、アップロードの権限がない
+    #[snafu(display(
+        "メディアをアップロードする権限がありません。もう一度ログインして、権限を許可し直してください"
+    ))]
+    MediaWriteNotGranted,
+    MediaProcessing {
+        message: String,
+    },
+    MediaProcessingTimeout,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -31,6 +40,15 @@ pub struct TwitterError {
     status: usize,
 }
 
+impl TwitterError {
+    // トークンのスコープが足りないか、ユーザーの認可を使わない認証でアップロードしようとした場合
+    // 凍結やポリシー違反などの403は、ログインし直しても解決しないので含めない
+    fn lacks_media_write(&self) -> bool {
+        self.type_.ends_with("/unsupported-authentication")
+            || self.detail.to_ascii_lowercase().contains("scope")
+    }
+}
+
 pub async fn refresh_token(
     oauth_client: BasicClient,
     id: String,
@@ -42,6 +60,13 @@ pub async fn refresh_token(
         .await
         .context(FailedRefreshTokenSnafu)?;
 
+    // 更新しても許可された権限は増えないので、再ログインが必要なことを記録しておく
+    if let Some(scopes) = resp.scopes() {
+        if !scopes.iter().any(|scope| scope.as_str() == "media.write") {
+            tracing::warn!("{} has not granted media.write; re-login is required", id);
+        }
+    }
+
     let new_token = crate::model::Token {
         id,
         access_token: resp.access_token().secret().to_string(),
@@ -92,77 +117,262 @@ pub async fn send_tweet_impl(token: Token, json: serde_json::Value) -> Result<St
     }
 }
 
+// スレッドをやり直す前に、投稿済みのツイートを削除する
+pub async fn delete_tweet_impl(token: Token, tweet_id: String) -> Result<(), Error> {
+    let client = reqwest::Client::new();
+
+    let resp = client
+        .delete(format!("https://api.twitter.com/2/tweets/{}", tweet_id))
+        .bearer_auth(token.access_token)
+        .send()
+        .await
+        .context(TwitterNetworkSnafu)?;
+
+    if resp.status().is_success() {
+        Ok(())
+    } else {
+        let error = resp
+            .json::<TwitterError>()
+            .await
+            .context(DecodeTwitterResponseSnafu)?;
+
+        Err(Error::TwitterError { error })
+    }
+}
+
 // Media Upload
+// ユーザー自身のOAuth2のトークン（media.writeスコープ）で、v2のエンドポイントにアップロードする
 #[derive(Serialize, Deserialize, Debug)]
 pub struct UploadMediaResponse {
-    media_id: u64,
-    media_id_string: String,
-    size: u64,
-    expires_after_secs: u64,
+    id: String,
+    expires_after_secs: Option<u64>,
+    processing_info: Option<ProcessingInfo>,
+}
+
+// アップロードしたメディアのIDと、再利用できる残り秒数
+#[derive(Debug, Clone)]
+pub struct UploadedMedia {
+    pub id: String,
+    pub expires_after_secs: Option<u64>,
+}
+
+impl From<UploadMediaResponse> for UploadedMedia {
+    fn from(resp: UploadMediaResponse) -> Self {
+        UploadedMedia {
+            id: resp.id,
+            expires_after_secs: resp.expires_after_secs,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-pub struct UploadMediaErrors {
-    errors: Vec<UploadMediaError>,
+pub struct ProcessingInfo {
+    state: String,
+    check_after_secs: Option<u64>,
+    progress_percent: Option<u64>,
+    error: Option<ProcessingError>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-pub struct UploadMediaError {
-    message: String,
-    code: usize,
+pub struct ProcessingError {
+    message: Option<String>,
 }
 
-#[derive(oauth::Request)]
-struct UploadMedia {}
+const MEDIA_UPLOAD_URI: &str = "https://api.twitter.com/2/media/upload";
+
+const MEDIA_METADATA_URI: &str = "https://api.twitter.com/2/media/metadata";
 
-pub async fn twitter_v1_media_upload(
-    user_id: String,
-    media_id: uuid::Uuid,
-    bucket: s3::Bucket,
-) -> Result<String, Error> {
-    let object = bucket.get_object(media_id.to_string()).await.unwrap();
+// APPENDで1回に送るバイト数（上限は5MB）
+const CHUNK_SIZE: usize = 4 * 1024 * 1024;
 
-    let bytes: Vec<u8> = object.bytes().to_vec();
+// 動画の変換を待つ回数の上限
+const MAX_STATUS_CHECKS: usize = 60;
 
+// ストレージを経由しない画像（RSSの記事の画像など）をアップロードする
+// GIFアニメや動画は分割アップロードで送る
+pub async fn twitter_v2_media_upload_bytes(
+    token: &Token,
+    bytes: Vec<u8>,
+    file_name: String,
+) -> Result<UploadedMedia, Error> {
+    let content_type = crate::media::content_type(&bytes).unwrap_or("image/jpeg");
+    let kind = crate::media::MediaKind::from_content_type(Some(content_type));
+
+    match kind {
+        crate::media::MediaKind::Image => {
+            simple_upload(token, bytes, file_name, content_type, kind.media_category()).await
+        }
+        _ => chunked_upload(token, bytes, content_type, kind.media_category()).await,
+    }
+}
+
+async fn simple_upload(
+    token: &Token,
+    bytes: Vec<u8>,
+    file_name: String,
+    content_type: &str,
+    media_category: &str,
+) -> Result<UploadedMedia, Error> {
     let file = multipart::Part::bytes(bytes)
-        .file_name(media_id.to_string())
-        .mime_str("image/jpeg")
+        .file_name(file_name)
+        .mime_str(content_type)
         .unwrap();
 
     let form = reqwest::multipart::Form::new()
-        .part("media", file)
-        .part("additional_owners", multipart::Part::text(user_id.clone()));
+        .text("media_category", media_category.to_string())
+        .part("media", file);
+
+    let json = post_media_upload::<Data<UploadMediaResponse>>(token, form).await?;
+
+    Ok(json.data.into())
+}
+
+async fn chunked_upload(
+    token: &Token,
+    bytes: Vec<u8>,
+    content_type: &str,
+    media_category: &str,
+) -> Result<UploadedMedia, Error> {
+    let form = reqwest::multipart::Form::new()
+        .text("command", "INIT")
+        .text("total_bytes", bytes.len().to_string())
+        .text("media_type", content_type.to_string())
+        .text("media_category", media_category.to_string());
+
+    let media_id = post_media_upload::<Data<UploadMediaResponse>>(token, form)
+        .await?
+        .data
+        .id;
+
+    for (segment_index, chunk) in bytes.chunks(CHUNK_SIZE).enumerate() {
+        let form = reqwest::multipart::Form::new()
+            .text("command", "APPEND")
+            .text("media_id", media_id.clone())
+            .text("segment_index", segment_index.to_string())
+            .part("media", multipart::Part::bytes(chunk.to_vec()));
+
+        post_media_upload::<serde_json::Value>(token, form).await?;
+    }
+
+    let form = reqwest::multipart::Form::new()
+        .text("command", "FINALIZE")
+        .text("media_id", media_id.clone());
+
+    let mut resp = post_media_upload::<Data<UploadMediaResponse>>(token, form)
+        .await?
+        .data;
+
+    // 動画やGIFアニメは、Twitter側の変換が終わるまで待つ
+    for _ in 0..MAX_STATUS_CHECKS {
+        let info = match resp.processing_info.take() {
+            Some(info) => info,
+            None => return Ok(resp.into()),
+        };
+
+        match info.state.as_str() {
+            "succeeded" => return Ok(resp.into()),
+            "failed" => {
+                return Err(Error::MediaProcessing {
+                    message: info
+                        .error
+                        .and_then(|error| error.message)
+                        .unwrap_or(info.state),
+                })
+            }
+            _ => {
+                tracing::debug!(
+                    "Waiting for Twitter media processing {}: {:?}%",
+                    media_id,
+                    info.progress_percent
+                );
+                let secs = info.check_after_secs.unwrap_or(1);
+                tokio::time::sleep(std::time::Duration::from_secs(secs)).await;
+            }
+        }
+
+        resp = media_status(token, &media_id).await?.data;
+    }
+
+    Err(Error::MediaProcessingTimeout)
+}
+
+// アップロードしたメディアに代替テキストを設定する
+pub async fn twitter_v2_media_metadata(
+    token: &Token,
+    media_id: &str,
+    alt_text: &str,
+) -> Result<(), Error> {
+    let client = reqwest::Client::new();
+    let resp = client
+        .post(MEDIA_METADATA_URI)
+        .bearer_auth(&token.access_token)
+        .json(&serde_json::json!({
+            "id": media_id,
+            "metadata": { "alt_text": { "text": alt_text } },
+        }))
+        .send()
+        .await
+        .context(TwitterNetworkSnafu)?;
 
-    let uri = "https://upload.twitter.com/1.1/media/upload.json";
-    let params = UploadMedia {};
+    decode_media_upload::<serde_json::Value>(resp).await?;
 
-    let token = crate::config::CONFIG.oauth_1_token();
-    let auth_header = oauth::post(uri, &params, &token, oauth::HMAC_SHA1);
+    Ok(())
+}
 
+async fn media_status(token: &Token, media_id: &str) -> Result<Data<UploadMediaResponse>, Error> {
     let client = reqwest::Client::new();
     let resp = client
-        .post(uri)
-        .header("Authorization", auth_header)
+        .get(MEDIA_UPLOAD_URI)
+        .query(&[("command", "STATUS"), ("media_id", media_id)])
+        .bearer_auth(&token.access_token)
+        .send()
+        .await
+        .context(TwitterNetworkSnafu)?;
+
+    decode_media_upload(resp).await
+}
+
+async fn post_media_upload<T>(token: &Token, form: reqwest::multipart::Form) -> Result<T, Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let client = reqwest::Client::new();
+    let resp = client
+        .post(MEDIA_UPLOAD_URI)
+        .bearer_auth(&token.access_token)
         .multipart(form)
         .send()
         .await
         .context(TwitterNetworkSnafu)?;
 
-    if resp.status().is_success() {
-        let json = resp
-            .json::<UploadMediaResponse>()
-            .await
-            .context(DecodeTwitterResponseSnafu)?;
+    decode_media_upload(resp).await
+}
 
-        Ok(json.media_id_string)
+async fn decode_media_upload<T>(resp: reqwest::Response) -> Result<T, Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    if resp.status().is_success() {
+        // APPENDは本文のないレスポンスを返す
+        let bytes = resp.bytes().await.context(DecodeTwitterResponseSnafu)?;
+        let body: &[u8] = if bytes.is_empty() { b"null" } else { &bytes };
+
+        serde_json::from_slice(body).map_err(|err| {
+            tracing::error!("Failed to decode Twitter media response {}", err);
+            Error::UploadMedia
+        })
     } else {
-        let errors = resp
-            .json::<UploadMediaErrors>()
-            .await
-            .context(DecodeTwitterResponseSnafu)?;
-
-        tracing::error!("Failed to upload Twitter medias {:?}", errors);
-        Err(Error::UploadMedia)
+        let status = resp.status();
+        let body = resp.text().await.context(DecodeTwitterResponseSnafu)?;
+        tracing::error!("Failed to upload Twitter medias {}: {}", status, body);
+
+        match serde_json::from_str::<TwitterError>(&body) {
+            Ok(error) if status == reqwest::StatusCode::FORBIDDEN && error.lacks_media_write() => {
+                Err(Error::MediaWriteNotGranted)
+            }
+            Ok(error) => Err(Error::TwitterError { error }),
+            Err(_) => Err(Error::UploadMedia),
+        }
     }
 }
 
@@ -195,16 +405,44 @@ pub async fn get_self(access_token: &String) -> Result<TwitterUser, Error> {
     }
 }
 #[tokio::test]
-async fn test_twitter_v1_media_upload() {
+async fn test_twitter_v2_media_upload() {
     use std::str::FromStr;
 
     let id = uuid::Uuid::from_str("a3893652-d741-463d-a4be-b9dafb5a0d96").unwrap();
     let user_id = String::from("751625934894084097");
 
-    let bucket = crate::minio::get_or_create_bucket(&user_id)
+    let account =
+        crate::gq::load_session::load_session(user_id.clone(), crate::state::oauth_client())
+            .await
+            .unwrap();
+    let bytes = crate::storage::store()
+        .get(&user_id, &id.to_string())
         .await
-        .context(crate::error::MinioSnafu)
         .unwrap();
 
-    twitter_v1_media_upload(user_id, id, bucket).await.unwrap();
+    twitter_v2_media_upload_bytes(&account.token, bytes, id.to_string())
+        .await
+        .unwrap();
+}
+
+#[test]
+fn test_lacks_media_write() {
+    let error = |type_: &str, detail: &str| TwitterError {
+        title: String::from("Forbidden"),
+        detail: detail.to_string(),
+        type_: type_.to_string(),
+        status: 403,
+    };
+
+    assert!(error(
+        "https://api.twitter.com/2/problems/unsupported-authentication",
+        "Authenticating with OAuth 2.0 Application-Only is forbidden for this endpoint."
+    )
+    .lacks_media_write());
+    assert!(error("about:blank", "Missing required OAuth2 scope: media.write").lacks_media_write());
+    assert!(!error(
+        "https://api.twitter.com/2/problems/client-forbidden",
+        "Your account is suspended and is not permitted to access this feature"
+    )
+    .lacks_media_write());
 }
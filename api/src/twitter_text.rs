@@ -0,0 +1,443 @@
+// ツイートの文字数（weighted length）の計算
+//
+// twitter-text（v3の設定）と同じ方法で数える。
+//   - 本文をNFCで正規化してから、コードポイント単位で数える
+//   - U+0000〜U+10FFなどの範囲は1、それ以外（日本語など）は2
+//   - URLは長さに関係なく23
+//   - 絵文字は、ZWJ・肌の色・国旗などで結合された並びを含めて2
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::ops::Range;
+use unicode_normalization::UnicodeNormalization;
+
+pub const MAX_WEIGHTED_LENGTH: usize = 280;
+
+pub const TRANSFORMED_URL_LENGTH: usize = 23;
+
+const SCALE: usize = 100;
+
+const DEFAULT_WEIGHT: usize = 200;
+
+// 重みが1になる範囲（コードポイント）
+const RANGES: [(u32, u32); 4] = [
+    (0x0000, 0x10FF),
+    (0x2000, 0x200D),
+    (0x2010, 0x201F),
+    (0x2032, 0x2037),
+];
+
+const INVALID_CHARACTERS: [char; 3] = ['\u{FFFE}', '\u{FEFF}', '\u{FFFF}'];
+
+// プロトコルなしでもURLとして扱うトップレベルドメイン
+// 国別のドメイン（.jpなど）はパスが続く場合のみURLとして扱う
+const GENERIC_TLDS: [&str; 16] = [
+    "com", "net", "org", "info", "biz", "edu", "gov", "mil", "int", "app", "dev", "blog", "shop",
+    "site", "online", "xyz",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseResult {
+    pub weighted_length: usize,
+    // 最大文字数に対する割合（1000分率）
+    pub permillage: usize,
+    pub valid: bool,
+}
+
+pub fn parse_tweet(text: &str) -> ParseResult {
+    parse(text, true)
+}
+
+// emoji_parsingがfalseのときは、v2の設定と同じく絵文字もコードポイントごとに数える
+fn parse(text: &str, emoji_parsing: bool) -> ParseResult {
+    let text: String = text.nfc().collect();
+
+    let mut weight = 0;
+    let mut position = 0;
+    for (range, range_weight) in special_ranges(&text, emoji_parsing) {
+        weight += text[position..range.start]
+            .chars()
+            .map(scaled_weight)
+            .sum::<usize>();
+        weight += range_weight;
+        position = range.end;
+    }
+    weight += text[position..].chars().map(scaled_weight).sum::<usize>();
+
+    let weighted_length = weight / SCALE;
+    let valid = !text.is_empty()
+        && weighted_length <= MAX_WEIGHTED_LENGTH
+        && !text.contains(&INVALID_CHARACTERS[..]);
+
+    ParseResult {
+        weighted_length,
+        permillage: weighted_length * 1000 / MAX_WEIGHTED_LENGTH,
+        valid,
+    }
+}
+
+pub fn weighted_length(text: &str) -> usize {
+    parse_tweet(text).weighted_length
+}
+
+// URLや絵文字を考慮しない、1文字だけの重み
+pub fn char_weight(c: char) -> usize {
+    scaled_weight(c) / SCALE
+}
+
+fn scaled_weight(c: char) -> usize {
+    let code = c as u32;
+
+    if RANGES
+        .iter()
+        .any(|(start, end)| (*start..=*end).contains(&code))
+    {
+        SCALE
+    } else {
+        DEFAULT_WEIGHT
+    }
+}
+
+// URLと絵文字の位置（バイト単位）と、その部分の重み
+fn special_ranges(text: &str, emoji_parsing: bool) -> Vec<(Range<usize>, usize)> {
+    let urls = extract_urls(text);
+
+    let emojis = EMOJI
+        .find_iter(text)
+        .filter(|_| emoji_parsing)
+        .map(|m| m.range())
+        .filter(|emoji| {
+            !urls
+                .iter()
+                .any(|url| emoji.start < url.end && url.start < emoji.end)
+        });
+
+    let mut ranges: Vec<(Range<usize>, usize)> = urls
+        .iter()
+        .cloned()
+        .map(|url| (url, TRANSFORMED_URL_LENGTH * SCALE))
+        .chain(emojis.map(|emoji| (emoji, DEFAULT_WEIGHT)))
+        .collect();
+    ranges.sort_by_key(|(range, _)| range.start);
+    ranges
+}
+
+static EMOJI: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(concat!(
+        r"\p{Regional_Indicator}{2}",
+        r"|[0-9#*]\x{FE0F}?\x{20E3}",
+        r"|(?:\p{Emoji_Presentation}\x{FE0F}?|\p{Extended_Pictographic}\x{FE0F})",
+        r"\p{Emoji_Modifier}?[\x{E0020}-\x{E007F}]*",
+        r"(?:\x{200D}\p{Extended_Pictographic}\x{FE0F}?\p{Emoji_Modifier}?)*",
+    ))
+    .unwrap()
+});
+
+static URL: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(concat!(
+        r"(?i)(https?://)?",
+        r"(?:[a-z0-9](?:[a-z0-9-]*[a-z0-9])?\.)+([a-z]{2,})",
+        r"(?::[0-9]+)?",
+        r"(/[!#-;=?-~]*)?",
+    ))
+    .unwrap()
+});
+
+// URLの直前に来てはいけない文字（メールアドレスやハッシュタグの一部など）
+fn is_invalid_preceding(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '@' | '＠' | '$' | '#' | '＃' | '.' | '-')
+}
+
+// 末尾の句読点や閉じ括弧はURLに含めない
+fn trim_trailing(url: &str) -> &str {
+    url.trim_end_matches(|c: char| ".,:;!?'\")]".contains(c))
+}
+
+fn extract_urls(text: &str) -> Vec<Range<usize>> {
+    URL.captures_iter(text)
+        .filter_map(|captures| {
+            let url = captures.get(0)?;
+
+            if text[..url.start()]
+                .chars()
+                .next_back()
+                .is_some_and(is_invalid_preceding)
+            {
+                return None;
+            }
+
+            let has_protocol = captures.get(1).is_some();
+            let has_path = captures.get(3).is_some_and(|path| path.as_str().len() > 1);
+            let tld = captures.get(2)?.as_str().to_ascii_lowercase();
+
+            let is_url = has_protocol
+                || GENERIC_TLDS.contains(&tld.as_str())
+                || (tld.len() == 2 && has_path);
+            if !is_url {
+                return None;
+            }
+
+            let end = url.start() + trim_trailing(url.as_str()).len();
+            Some(url.start()..end)
+        })
+        .collect()
+}
+
+// twitter-textのconformance/validate.ymlのケース
+// description・text・weightedLength・valid・permillageをそのまま写している
+//   - WeightedTweetsCounterTest（v2の設定。絵文字もコードポイントごとに数える）
+//   - WeightedTweetsWithDiscountedEmojiCounterTest（v3の設定）
+#[test]
+fn test_weighted_length_conformance() {
+    let v2_cases = [
+        (
+            "Regular Tweet with less than 280 characters",
+            "This is a test.",
+            15,
+            true,
+            53,
+        ),
+        (
+            "Count a mix of single byte single word, and double word unicode characters",
+            "H🐱☺👨\u{200D}👩\u{200D}👧\u{200D}👦",
+            16,
+            true,
+            57,
+        ),
+        (
+            "Count unicode emoji chars inside the basic multilingual plane",
+            "😷👾😡🔥💩",
+            10,
+            true,
+            35,
+        ),
+        (
+            "Count unicode emoji chars outside the basic multilingual plane with skin tone modifiers",
+            "🙋🏽👨\u{200D}🎤",
+            9,
+            true,
+            32,
+        ),
+        (
+            "Handle a 64 character domain without protocol",
+            "randomurlrandomurlrandomurlrandomurlrandomurlrandomurlrandomurls.com",
+            23,
+            true,
+            82,
+        ),
+    ];
+
+    for (description, text, weighted_length, valid, permillage) in v2_cases {
+        let result = parse(text, false);
+        assert_eq!(result.weighted_length, weighted_length, "{}", description);
+        assert_eq!(result.valid, valid, "{}", description);
+        assert_eq!(result.permillage, permillage, "{}", description);
+    }
+
+    let v3_cases = [
+        (
+            "Regular Tweet with less than 280 characters",
+            "This is a test.",
+            15,
+            true,
+            53,
+        ),
+        (
+            "Count a mix of single byte single word, and double word unicode characters",
+            "H🐱☺👨\u{200D}👩\u{200D}👧\u{200D}👦",
+            7,
+            true,
+            25,
+        ),
+        (
+            "Count unicode emoji chars inside the basic multilingual plane",
+            "😷👾😡🔥💩",
+            10,
+            true,
+            35,
+        ),
+        (
+            "Count unicode emoji chars outside the basic multilingual plane with skin tone modifiers",
+            "🙋🏽👨\u{200D}🎤",
+            4,
+            true,
+            14,
+        ),
+        (
+            "Handle a 64 character domain without protocol",
+            "randomurlrandomurlrandomurlrandomurlrandomurlrandomurlrandomurls.com",
+            23,
+            true,
+            82,
+        ),
+    ];
+
+    for (description, text, weighted_length, valid, permillage) in v3_cases {
+        let result = parse_tweet(text);
+        assert_eq!(result.weighted_length, weighted_length, "{}", description);
+        assert_eq!(result.valid, valid, "{}", description);
+        assert_eq!(result.permillage, permillage, "{}", description);
+    }
+}
+
+// URLの数え方（transformedURLLength = 23）のケース
+// 期待値はv3の設定から計算したもので、validate.ymlからの写しではない
+#[test]
+fn test_weighted_length_urls() {
+    let cases: Vec<(&str, String, usize, bool, usize)> = vec![
+        (
+            "URL with protocol",
+            "https://twitter.com".into(),
+            23,
+            true,
+            82,
+        ),
+        (
+            "URL with path, query and fragment",
+            "Check out https://example.com/path?q=1#top".into(),
+            33,
+            true,
+            117,
+        ),
+        (
+            "Uppercase protocol and domain",
+            "HTTPS://EXAMPLE.COM".into(),
+            23,
+            true,
+            82,
+        ),
+        (
+            "Two URLs",
+            "https://example.com http://example.org".into(),
+            47,
+            true,
+            167,
+        ),
+        (
+            "URL with port",
+            "example.com:8080/path".into(),
+            23,
+            true,
+            82,
+        ),
+        (
+            "Trailing period is not part of the URL",
+            "Visit example.com.".into(),
+            30,
+            true,
+            107,
+        ),
+        (
+            "URL in parentheses",
+            "(https://example.com)".into(),
+            25,
+            true,
+            89,
+        ),
+        (
+            "Host without a TLD is not a URL",
+            "http://localhost:8080".into(),
+            21,
+            true,
+            75,
+        ),
+        (
+            "Long URL is counted as 23",
+            format!("https://example.com/{}", "a".repeat(300)),
+            23,
+            true,
+            82,
+        ),
+        (
+            "280 characters including a URL",
+            format!("{} https://example.com/", "a".repeat(256)),
+            280,
+            true,
+            1000,
+        ),
+        (
+            "281 characters including a URL",
+            format!("{} https://example.com/", "a".repeat(257)),
+            281,
+            false,
+            1003,
+        ),
+        (
+            "URL without protocol after Japanese text",
+            "日本語example.com".into(),
+            29,
+            true,
+            103,
+        ),
+    ];
+
+    for (description, text, weighted_length, valid, permillage) in cases {
+        let result = parse_tweet(&text);
+        assert_eq!(result.weighted_length, weighted_length, "{}", description);
+        assert_eq!(result.valid, valid, "{}", description);
+        assert_eq!(result.permillage, permillage, "{}", description);
+    }
+}
+
+// 日本語のテキストやURLの判定など、このアプリで特に確認しておきたいケース
+#[test]
+fn test_weighted_length() {
+    let cases: Vec<(&str, String, usize, bool)> = vec![
+        ("Regular Tweet", "This is a test.".into(), 15, true),
+        ("Empty Tweet", "".into(), 0, false),
+        ("280 latin characters", "a".repeat(280), 280, true),
+        ("281 latin characters", "a".repeat(281), 281, false),
+        ("140 CJK characters", "あ".repeat(140), 280, true),
+        ("141 CJK characters", "あ".repeat(141), 282, false),
+        (
+            "Tweet with URL",
+            "Tweet with URL https://www.twitter.com/hello/world?q=1".into(),
+            38,
+            true,
+        ),
+        ("URL without protocol", "twitter.com".into(), 23, true),
+        (
+            "ccTLD without path is not a URL",
+            "example.jp".into(),
+            10,
+            true,
+        ),
+        ("ccTLD with path", "example.jp/path".into(), 23, true),
+        ("Email is not a URL", "user@example.com".into(), 16, true),
+        (
+            "URL in Japanese text",
+            "記事https://example.com/a。".into(),
+            29,
+            true,
+        ),
+        ("Single emoji", "😷".into(), 2, true),
+        ("Emoji with skin tone", "👍🏽".into(), 2, true),
+        (
+            "ZWJ family emoji",
+            "👨\u{200D}👩\u{200D}👧\u{200D}👦".into(),
+            2,
+            true,
+        ),
+        ("Flag emoji", "🇯🇵".into(), 2, true),
+        ("Keycap emoji", "1\u{FE0F}\u{20E3}".into(), 2, true),
+        (
+            "Text presentation with VS16",
+            "\u{2764}\u{FE0F}".into(),
+            2,
+            true,
+        ),
+        ("NFC normalization", "cafe\u{301}".into(), 4, true),
+        ("Invalid character", "abc\u{FFFE}".into(), 5, false),
+        (
+            "Tweet with 140 emojis",
+            "👨\u{200D}👩\u{200D}👧\u{200D}👦".repeat(140),
+            280,
+            true,
+        ),
+    ];
+
+    for (description, text, weighted_length, valid) in cases {
+        let result = parse_tweet(&text);
+        assert_eq!(result.weighted_length, weighted_length, "{}", description);
+        assert_eq!(result.valid, valid, "{}", description);
+    }
+}
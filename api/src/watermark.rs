@@ -0,0 +1,181 @@
+// 投稿する画像に、ユーザーが設定したロゴを透かしとして重ねる
+// 保存しているオリジナルには重ねず投稿のたびに重ねるので、設定を変えれば次の投稿から反映される
+use crate::storage::store;
+use image::{imageops, imageops::FilterType, DynamicImage};
+use snafu::prelude::*;
+use strum_macros::{Display, EnumString};
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum Error {
+    Storage {
+        source: crate::storage::Error,
+    },
+    Media {
+        source: crate::media::Error,
+    },
+    Hasura {
+        source: crate::gq::error::HasuraError,
+    },
+}
+
+// ロゴと画像の端との間隔（画像の短辺に対する割合）
+const MARGIN_RATIO: f32 = 0.03;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum Position {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+    Center,
+}
+
+impl Position {
+    // ロゴを置く左上の座標
+    fn offset(
+        &self,
+        (width, height): (u32, u32),
+        (logo_width, logo_height): (u32, u32),
+    ) -> (i64, i64) {
+        let margin = (width.min(height) as f32 * MARGIN_RATIO) as i64;
+        let right = width as i64 - logo_width as i64 - margin;
+        let bottom = height as i64 - logo_height as i64 - margin;
+
+        match self {
+            Position::TopLeft => (margin, margin),
+            Position::TopRight => (right, margin),
+            Position::BottomLeft => (margin, bottom),
+            Position::BottomRight => (right, bottom),
+            Position::Center => (
+                (width as i64 - logo_width as i64) / 2,
+                (height as i64 - logo_height as i64) / 2,
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    // ロゴとして重ねるメディア
+    pub media_id: uuid::Uuid,
+    pub position: Position,
+    // 0.0（透明）〜1.0（不透明）
+    pub opacity: f32,
+    // 画像の幅に対するロゴの幅の割合
+    pub scale: f32,
+}
+
+pub struct Watermark {
+    settings: Settings,
+    logo: DynamicImage,
+}
+
+// 透かしを有効にしていなければNoneを返す
+pub async fn load(user_id: &str) -> Result<Option<Watermark>, Error> {
+    let settings = match crate::gq::get_watermark::exec(user_id.to_string())
+        .await
+        .context(HasuraSnafu)?
+    {
+        Some(settings) => settings,
+        None => return Ok(None),
+    };
+
+    let bytes = store()
+        .get(user_id, &settings.media_id.to_string())
+        .await
+        .context(StorageSnafu)?;
+    let logo = crate::media::decode_oriented(&bytes).context(MediaSnafu)?;
+
+    Ok(Some(Watermark { settings, logo }))
+}
+
+impl Watermark {
+    // 静止画にだけ重ね、GIFアニメや動画はそのまま返す
+    pub fn stamp(&self, bytes: Vec<u8>) -> Result<Vec<u8>, Error> {
+        match crate::media::content_type(&bytes) {
+            Ok("image/jpeg" | "image/png" | "image/webp") => {}
+            _ => return Ok(bytes),
+        }
+
+        let image = crate::media::decode_oriented(&bytes).context(MediaSnafu)?;
+        let stamped = apply(&image, &self.logo, &self.settings);
+
+        crate::media::encode_normalized(stamped)
+            .map(|normalized| normalized.bytes)
+            .context(MediaSnafu)
+    }
+}
+
+pub fn apply(image: &DynamicImage, logo: &DynamicImage, settings: &Settings) -> DynamicImage {
+    let width = ((image.width() as f32 * settings.scale.clamp(0.0, 1.0)).round() as u32).max(1);
+    let mut logo = logo
+        .resize(width, image.height(), FilterType::Lanczos3)
+        .to_rgba8();
+
+    let opacity = settings.opacity.clamp(0.0, 1.0);
+    for pixel in logo.pixels_mut() {
+        pixel[3] = (pixel[3] as f32 * opacity).round() as u8;
+    }
+
+    let (x, y) = settings
+        .position
+        .offset((image.width(), image.height()), logo.dimensions());
+
+    let mut stamped = image.to_rgba8();
+    imageops::overlay(&mut stamped, &logo, x, y);
+
+    if image.color().has_alpha() {
+        DynamicImage::ImageRgba8(stamped)
+    } else {
+        DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(stamped).to_rgb8())
+    }
+}
+
+#[test]
+fn test_apply() {
+    use image::GenericImageView;
+
+    let image =
+        DynamicImage::ImageRgb8(image::RgbImage::from_pixel(400, 200, image::Rgb([0, 0, 0])));
+    let logo = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+        100,
+        50,
+        image::Rgba([255, 255, 255, 255]),
+    ));
+    let settings = Settings {
+        media_id: uuid::Uuid::new_v4(),
+        position: Position::BottomRight,
+        opacity: 0.5,
+        scale: 0.25,
+    };
+
+    // ロゴは幅100px（400pxの25%）になり、右下から短辺の3%（6px）離して置かれる
+    let stamped = apply(&image, &logo, &settings);
+    assert_eq!(stamped.dimensions(), (400, 200));
+    assert!(!stamped.color().has_alpha());
+    assert_eq!(stamped.get_pixel(0, 0).0, [0, 0, 0, 255]);
+    assert_eq!(stamped.get_pixel(399, 199).0, [0, 0, 0, 255]);
+    let [r, g, b, _] = stamped.get_pixel(350, 170).0;
+    assert!((120..=135).contains(&r) && r == g && g == b);
+
+    let top_left = apply(
+        &image,
+        &logo,
+        &Settings {
+            position: Position::TopLeft,
+            opacity: 1.0,
+            ..settings.clone()
+        },
+    );
+    assert_eq!(top_left.get_pixel(10, 10).0, [255, 255, 255, 255]);
+    assert_eq!(top_left.get_pixel(350, 170).0, [0, 0, 0, 255]);
+
+    assert_eq!(
+        "top_right".parse::<Position>().ok(),
+        Some(Position::TopRight)
+    );
+    assert!("middle".parse::<Position>().is_err());
+}
@@ -2001,17 +2001,23 @@ buildMinioGetUploadUrlInput :
     MinioGetUploadUrlInputRequiredFields
     -> MinioGetUploadUrlInput
 buildMinioGetUploadUrlInput required____ =
-    { dummy = required____.dummy }
+    { dummy = required____.dummy, content_type = required____.content_type, size = required____.size }
 
 
 type alias MinioGetUploadUrlInputRequiredFields =
-    { dummy : Bool }
+    { dummy : Bool
+    , content_type : String
+    , size : Int
+    }
 
 
 {-| Type for the MinioGetUploadUrlInput input object.
 -}
 type alias MinioGetUploadUrlInput =
-    { dummy : Bool }
+    { dummy : Bool
+    , content_type : String
+    , size : Int
+    }
 
 
 {-| Encode a MinioGetUploadUrlInput into a value that can be used as an argument.
@@ -2019,7 +2025,7 @@ type alias MinioGetUploadUrlInput =
 encodeMinioGetUploadUrlInput : MinioGetUploadUrlInput -> Value
 encodeMinioGetUploadUrlInput input____ =
     Encode.maybeObject
-        [ ( "dummy", Encode.bool input____.dummy |> Just ) ]
+        [ ( "dummy", Encode.bool input____.dummy |> Just ), ( "content_type", Encode.string input____.content_type |> Just ), ( "size", Encode.int input____.size |> Just ) ]
 
 
 buildRegisterEmailInput :
@@ -5999,3 +6005,28 @@ encodeUuid_comparison_exp : Uuid_comparison_exp -> Value
 encodeUuid_comparison_exp input____ =
     Encode.maybeObject
         [ ( "_eq", (ScalarCodecs.codecs |> Hasura.Scalar.unwrapEncoder .codecUuid) |> Encode.optional input____.eq_ ), ( "_gt", (ScalarCodecs.codecs |> Hasura.Scalar.unwrapEncoder .codecUuid) |> Encode.optional input____.gt_ ), ( "_gte", (ScalarCodecs.codecs |> Hasura.Scalar.unwrapEncoder .codecUuid) |> Encode.optional input____.gte_ ), ( "_in", ((ScalarCodecs.codecs |> Hasura.Scalar.unwrapEncoder .codecUuid) |> Encode.list) |> Encode.optional input____.in_ ), ( "_is_null", Encode.bool |> Encode.optional input____.is_null_ ), ( "_lt", (ScalarCodecs.codecs |> Hasura.Scalar.unwrapEncoder .codecUuid) |> Encode.optional input____.lt_ ), ( "_lte", (ScalarCodecs.codecs |> Hasura.Scalar.unwrapEncoder .codecUuid) |> Encode.optional input____.lte_ ), ( "_neq", (ScalarCodecs.codecs |> Hasura.Scalar.unwrapEncoder .codecUuid) |> Encode.optional input____.neq_ ), ( "_nin", ((ScalarCodecs.codecs |> Hasura.Scalar.unwrapEncoder .codecUuid) |> Encode.list) |> Encode.optional input____.nin_ ) ]
+
+
+buildValidateTweetInput :
+    ValidateTweetInputRequiredFields
+    -> ValidateTweetInput
+buildValidateTweetInput required____ =
+    { text = required____.text }
+
+
+type alias ValidateTweetInputRequiredFields =
+    { text : String }
+
+
+{-| Type for the ValidateTweetInput input object.
+-}
+type alias ValidateTweetInput =
+    { text : String }
+
+
+{-| Encode a ValidateTweetInput into a value that can be used as an argument.
+-}
+encodeValidateTweetInput : ValidateTweetInput -> Value
+encodeValidateTweetInput input____ =
+    Encode.maybeObject
+        [ ( "text", Encode.string input____.text |> Just ) ]
@@ -291,3 +291,7 @@ type User_min_fields
 
 type User_mutation_response
     = User_mutation_response
+
+
+type ValidateTweetOutput
+    = ValidateTweetOutput
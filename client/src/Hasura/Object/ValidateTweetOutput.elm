@@ -0,0 +1,39 @@
+-- Do not manually edit this file, it was auto-generated by dillonkearns/elm-graphql
+-- https://github.com/dillonkearns/elm-graphql
+
+
+module Hasura.Object.ValidateTweetOutput exposing (..)
+
+import Graphql.Internal.Builder.Argument as Argument exposing (Argument)
+import Graphql.Internal.Builder.Object as Object
+import Graphql.Internal.Encode as Encode exposing (Value)
+import Graphql.Operation exposing (RootMutation, RootQuery, RootSubscription)
+import Graphql.OptionalArgument exposing (OptionalArgument(..))
+import Graphql.SelectionSet exposing (SelectionSet)
+import Hasura.InputObject
+import Hasura.Interface
+import Hasura.Object
+import Hasura.Scalar
+import Hasura.Union
+import Json.Decode as Decode
+import ScalarCodecs
+
+
+max_weighted_length : SelectionSet Int Hasura.Object.ValidateTweetOutput
+max_weighted_length =
+    Object.selectionForField "Int" "max_weighted_length" [] Decode.int
+
+
+permillage : SelectionSet Int Hasura.Object.ValidateTweetOutput
+permillage =
+    Object.selectionForField "Int" "permillage" [] Decode.int
+
+
+valid : SelectionSet Bool Hasura.Object.ValidateTweetOutput
+valid =
+    Object.selectionForField "Bool" "valid" [] Decode.bool
+
+
+weighted_length : SelectionSet Int Hasura.Object.ValidateTweetOutput
+weighted_length =
+    Object.selectionForField "Int" "weighted_length" [] Decode.int
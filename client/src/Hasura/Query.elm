@@ -705,3 +705,15 @@ user_by_pk :
     -> SelectionSet (Maybe decodesTo) RootQuery
 user_by_pk requiredArgs____ object____ =
     Object.selectionForCompositeField "user_by_pk" [ Argument.required "id" requiredArgs____.id Encode.string ] object____ (Basics.identity >> Decode.nullable)
+
+
+type alias ValidateTweetRequiredArguments =
+    { args : Hasura.InputObject.ValidateTweetInput }
+
+
+validateTweet :
+    ValidateTweetRequiredArguments
+    -> SelectionSet decodesTo Hasura.Object.ValidateTweetOutput
+    -> SelectionSet decodesTo RootQuery
+validateTweet requiredArgs____ object____ =
+    Object.selectionForCompositeField "validateTweet" [ Argument.required "args" requiredArgs____.args Hasura.InputObject.encodeValidateTweetInput ] object____ Basics.identity
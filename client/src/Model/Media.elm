@@ -1,5 +1,6 @@
 module Model.Media exposing (Media, MediaId, UploadUrl, asArg, decoder, deleteMedia, getPresignPostUrl, saveMedia, selection, unwrapId)
 
+import File exposing (File)
 import Graphql.Operation exposing (RootMutation)
 import Graphql.OptionalArgument exposing (OptionalArgument(..))
 import Graphql.SelectionSet as SelectionSet exposing (SelectionSet)
@@ -65,9 +66,9 @@ type alias UploadUrl =
     }
 
 
-getPresignPostUrl : SelectionSet UploadUrl RootMutation
-getPresignPostUrl =
-    { dummy = True }
+getPresignPostUrl : File -> SelectionSet UploadUrl RootMutation
+getPresignPostUrl file =
+    { dummy = True, content_type = File.mime file, size = File.size file }
         |> (\args ->
                 Hasura.Mutation.minioGetUploadUrl { args = args }
                     (SelectionSet.map2 UploadUrl
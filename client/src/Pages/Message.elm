@@ -6,11 +6,13 @@ import File exposing (File)
 import File.Select
 import Form.Decoder as FD
 import Form.Decoder.Extra as FD
-import Graphql.Operation exposing (RootMutation)
+import Graphql.Operation exposing (RootMutation, RootQuery)
 import Graphql.OptionalArgument exposing (OptionalArgument(..))
-import Graphql.SelectionSet exposing (SelectionSet)
+import Graphql.SelectionSet as SelectionSet exposing (SelectionSet)
 import Hasura.InputObject
 import Hasura.Mutation
+import Hasura.Object.ValidateTweetOutput
+import Hasura.Query
 import Heroicons.Outline as Outline
 import Html exposing (Html, div, img, text)
 import Html.Attributes as A exposing (class)
@@ -88,6 +90,7 @@ type Error
     | FileTooLarge
     | InvalidFileType String
     | FailedUploadImage String
+    | TweetTooLong Int Int
 
 
 errorToString : Error -> String
@@ -105,6 +108,9 @@ errorToString v =
         FailedUploadImage code ->
             "メディアアップロードエラー(code=" ++ code ++ ")"
 
+        TweetTooLong weightedLength maxWeightedLength ->
+            "文字数が上限を超えています(" ++ String.fromInt weightedLength ++ "/" ++ String.fromInt maxWeightedLength ++ ")"
+
 
 type alias Form =
     { text : String
@@ -151,14 +157,7 @@ fdText =
     FD.lift .text FD.identity
         |> FD.andThen
             (\s ->
-                let
-                    length =
-                        MessageParser.length s
-                in
-                if length >= 280 then
-                    FD.fail (FD.TooLong length)
-
-                else if String.length (String.trim s) == 0 then
+                if String.length (String.trim s) == 0 then
                     FD.fail FD.NotEmpty
 
                 else
@@ -166,6 +165,24 @@ fdText =
             )
 
 
+type alias TweetValidation =
+    { valid : Bool
+    , weightedLength : Int
+    , maxWeightedLength : Int
+    }
+
+
+validateTweet : String -> SelectionSet TweetValidation RootQuery
+validateTweet text =
+    Hasura.Query.validateTweet
+        { args = Hasura.InputObject.buildValidateTweetInput { text = text } }
+        (SelectionSet.map3 TweetValidation
+            Hasura.Object.ValidateTweetOutput.valid
+            Hasura.Object.ValidateTweetOutput.weighted_length
+            Hasura.Object.ValidateTweetOutput.max_weighted_length
+        )
+
+
 saveMessage : MessageInput -> SelectionSet (Maybe Message) RootMutation
 saveMessage input =
     Hasura.Mutation.insert_message_one identity
@@ -220,6 +237,7 @@ type Msg
     | ClickedEditMessage Message
     | ClickedSaveMessage
     | ClickedDeleteMessage Message
+    | GotValidateTweet MessageInput (Shared.Response TweetValidation)
     | GotSaveMessageResponse (Shared.Response (Maybe Message))
     | ChangedForm Form
     | ClickedCloseDialog
@@ -347,16 +365,9 @@ update shared msg model =
                                         updated =
                                             { form | submitted = Just [] }
                                     in
-                                    case maybeMessageId of
-                                        Just messageId ->
-                                            ( Loaded { loaded | dialog = DialogLoading (DialogMessageEditor maybeMessageId updated maybeMedia maybeTag) }
-                                            , Shared.makeMutation loaded.token (updateMessage messageId input) GotSaveMessageResponse |> Effect.fromCmd
-                                            )
-
-                                        Nothing ->
-                                            ( Loaded { loaded | dialog = DialogLoading (DialogMessageEditor maybeMessageId updated maybeMedia maybeTag) }
-                                            , Shared.makeMutation loaded.token (saveMessage input) GotSaveMessageResponse |> Effect.fromCmd
-                                            )
+                                    ( Loaded { loaded | dialog = DialogLoading (DialogMessageEditor maybeMessageId updated maybeMedia maybeTag) }
+                                    , Shared.makeQuery loaded.token (validateTweet input.text) (GotValidateTweet input) |> Effect.fromCmd
+                                    )
 
                                 Err errors ->
                                     let
@@ -373,6 +384,31 @@ update shared msg model =
                 _ ->
                     none model
 
+        GotValidateTweet input resp ->
+            onLoaded model
+                (\loaded ->
+                    case loaded.dialog of
+                        DialogLoading ((DialogMessageEditor maybeMessageId _ _ _) as dialog) ->
+                            case resp of
+                                Ok validation ->
+                                    if validation.valid then
+                                        case maybeMessageId of
+                                            Just messageId ->
+                                                ( Loaded loaded, Shared.makeMutation loaded.token (updateMessage messageId input) GotSaveMessageResponse |> Effect.fromCmd )
+
+                                            Nothing ->
+                                                ( Loaded loaded, Shared.makeMutation loaded.token (saveMessage input) GotSaveMessageResponse |> Effect.fromCmd )
+
+                                    else
+                                        ( Loaded { loaded | dialog = DialogError (TweetTooLong validation.weightedLength validation.maxWeightedLength) dialog }, Effect.none )
+
+                                Err _ ->
+                                    ( Loaded { loaded | dialog = DialogError FailedSaveMessage dialog }, Effect.none )
+
+                        _ ->
+                            none model
+                )
+
         GotSaveMessageResponse resp ->
             onLoaded model
                 (\loaded ->
@@ -503,7 +539,7 @@ update shared msg model =
 
                             else
                                 ( Loaded { loaded | dialog = DialogMediaPicker dialog maybeMedia }
-                                , Shared.makeMutation loaded.token Media.getPresignPostUrl (GotPresignPostUrl file) |> Effect.fromCmd
+                                , Shared.makeMutation loaded.token (Media.getPresignPostUrl file) (GotPresignPostUrl file) |> Effect.fromCmd
                                 )
 
                         _ ->
@@ -0,0 +1 @@
+alter table "public"."task_rss" drop column "attach_image";
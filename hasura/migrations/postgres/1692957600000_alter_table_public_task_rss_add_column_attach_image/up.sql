@@ -0,0 +1,2 @@
+alter table "public"."task_rss" add column "attach_image" boolean
+ not null default 'false';
@@ -0,0 +1,2 @@
+DROP TABLE public.task_rss_history;
+DROP TABLE public.task_rss_filter;
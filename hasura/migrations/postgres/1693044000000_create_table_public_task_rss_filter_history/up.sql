@@ -0,0 +1,38 @@
+CREATE TABLE public.task_rss_filter (
+    id uuid DEFAULT gen_random_uuid() NOT NULL,
+    task_rss_id uuid NOT NULL,
+    user_id text NOT NULL,
+    action text NOT NULL,
+    field text DEFAULT 'any'::text NOT NULL,
+    pattern text NOT NULL,
+    regex boolean DEFAULT false NOT NULL,
+    created_at timestamp with time zone DEFAULT now() NOT NULL,
+    CONSTRAINT task_rss_filter_action_check CHECK (action = ANY (ARRAY['include'::text, 'exclude'::text])),
+    CONSTRAINT task_rss_filter_field_check CHECK (field = ANY (ARRAY['any'::text, 'title'::text, 'description'::text, 'categories'::text]))
+);
+CREATE TABLE public.task_rss_history (
+    id uuid DEFAULT gen_random_uuid() NOT NULL,
+    task_rss_id uuid NOT NULL,
+    user_id text NOT NULL,
+    status text NOT NULL,
+    title text,
+    link text,
+    pub_date timestamp with time zone,
+    reason text,
+    created_at timestamp with time zone DEFAULT now() NOT NULL,
+    CONSTRAINT task_rss_history_status_check CHECK (status = ANY (ARRAY['posted'::text, 'filtered'::text, 'failed'::text]))
+);
+ALTER TABLE ONLY public.task_rss_filter
+    ADD CONSTRAINT task_rss_filter_pkey PRIMARY KEY (id);
+ALTER TABLE ONLY public.task_rss_history
+    ADD CONSTRAINT task_rss_history_pkey PRIMARY KEY (id);
+ALTER TABLE ONLY public.task_rss_history
+    ADD CONSTRAINT task_rss_history_task_rss_id_link_status_key UNIQUE (task_rss_id, link, status);
+ALTER TABLE ONLY public.task_rss_filter
+    ADD CONSTRAINT task_rss_filter_task_rss_id_fkey FOREIGN KEY (task_rss_id) REFERENCES public.task_rss(id) ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE ONLY public.task_rss_filter
+    ADD CONSTRAINT task_rss_filter_user_id_fkey FOREIGN KEY (user_id) REFERENCES public."user"(id) ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE ONLY public.task_rss_history
+    ADD CONSTRAINT task_rss_history_task_rss_id_fkey FOREIGN KEY (task_rss_id) REFERENCES public.task_rss(id) ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE ONLY public.task_rss_history
+    ADD CONSTRAINT task_rss_history_user_id_fkey FOREIGN KEY (user_id) REFERENCES public."user"(id) ON UPDATE CASCADE ON DELETE CASCADE;
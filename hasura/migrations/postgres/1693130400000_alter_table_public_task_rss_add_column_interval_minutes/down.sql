@@ -0,0 +1 @@
+alter table "public"."task_rss" drop column "interval_minutes";
@@ -0,0 +1,3 @@
+alter table "public"."task_rss" add column "interval_minutes" integer
+ null check ("interval_minutes" > 0);
+comment on column "public"."task_rss"."interval_minutes" is E'RSSフィードの確認間隔（分）。NULLの場合はプランの既定値';
@@ -0,0 +1 @@
+alter table "public"."task_fixed_time" drop column "strategy";
@@ -0,0 +1,3 @@
+alter table "public"."task_fixed_time" add column "strategy" text
+ null check ("strategy" in ('weighted', 'uniform', 'newest', 'oldest'));
+comment on column "public"."task_fixed_time"."strategy" is E'メッセージの選び方。NULLの場合はrandomに従う（true: uniform、false: newest）';
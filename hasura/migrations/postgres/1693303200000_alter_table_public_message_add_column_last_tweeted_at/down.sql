@@ -0,0 +1,9 @@
+update "public"."task_fixed_time" set "strategy" = null where "strategy" = 'rotation';
+alter table "public"."task_fixed_time" drop constraint "task_fixed_time_strategy_check";
+alter table "public"."task_fixed_time" add constraint "task_fixed_time_strategy_check"
+ check ("strategy" in ('weighted', 'uniform', 'newest', 'oldest'));
+
+alter table "public"."task_fixed_time" drop column "reshuffle";
+
+comment on column "public"."message"."tweeted" is NULL;
+alter table "public"."message" drop column "last_tweeted_at";
@@ -0,0 +1,11 @@
+alter table "public"."message" add column "last_tweeted_at" timestamptz
+ null;
+comment on column "public"."message"."tweeted" is E'ローテーションの今のサイクルで投稿済みかどうか';
+
+alter table "public"."task_fixed_time" add column "reshuffle" boolean
+ not null default 'false';
+comment on column "public"."task_fixed_time"."reshuffle" is E'ローテーションのサイクルごとに順番を入れ替えるかどうか';
+
+alter table "public"."task_fixed_time" drop constraint "task_fixed_time_strategy_check";
+alter table "public"."task_fixed_time" add constraint "task_fixed_time_strategy_check"
+ check ("strategy" in ('weighted', 'uniform', 'newest', 'oldest', 'rotation'));
@@ -0,0 +1,7 @@
+alter table "public"."message" drop constraint "message_thread_retry_check";
+alter table "public"."message" drop column "thread_retry";
+
+comment on column "public"."message"."thread_tweet_id" is NULL;
+alter table "public"."message" drop column "thread_tweet_id";
+
+DROP TABLE public.message_part;
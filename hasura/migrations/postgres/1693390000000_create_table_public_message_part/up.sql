@@ -0,0 +1,34 @@
+CREATE TABLE public.message_part (
+    id uuid DEFAULT gen_random_uuid() NOT NULL,
+    message_id uuid NOT NULL,
+    user_id text NOT NULL,
+    "position" integer NOT NULL,
+    text text NOT NULL,
+    media_id uuid,
+    tweet_id text,
+    created_at timestamp with time zone DEFAULT now() NOT NULL,
+    updated_at timestamp with time zone DEFAULT now() NOT NULL,
+    CONSTRAINT message_part_position_check CHECK ("position" > 0)
+);
+COMMENT ON TABLE public.message_part IS 'スレッドとして投稿するメッセージの2件目以降の投稿';
+COMMENT ON COLUMN public.message_part.tweet_id IS '投稿途中のスレッドで、投稿済みのツイートのID';
+ALTER TABLE ONLY public.message_part
+    ADD CONSTRAINT message_part_pkey PRIMARY KEY (id);
+ALTER TABLE ONLY public.message_part
+    ADD CONSTRAINT message_part_message_id_position_key UNIQUE (message_id, "position");
+ALTER TABLE ONLY public.message_part
+    ADD CONSTRAINT message_part_message_id_fkey FOREIGN KEY (message_id) REFERENCES public.message(id) ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE ONLY public.message_part
+    ADD CONSTRAINT message_part_media_id_fkey FOREIGN KEY (media_id) REFERENCES public.media(id) ON UPDATE SET NULL ON DELETE SET NULL;
+ALTER TABLE ONLY public.message_part
+    ADD CONSTRAINT message_part_user_id_fkey FOREIGN KEY (user_id) REFERENCES public."user"(id) ON UPDATE CASCADE ON DELETE CASCADE;
+
+alter table "public"."message" add column "thread_tweet_id" text
+ null;
+comment on column "public"."message"."thread_tweet_id" is E'投稿途中のスレッドで、最初に投稿したツイートのID';
+
+alter table "public"."message" add column "thread_retry" text
+ not null default 'resume';
+alter table "public"."message" add constraint "message_thread_retry_check"
+ check ("thread_retry" in ('resume', 'cleanup'));
+comment on column "public"."message"."thread_retry" is E'スレッドの投稿が途中で失敗した場合に、次回続きから投稿する（resume）か、投稿済みのツイートを削除してやり直す（cleanup）か';
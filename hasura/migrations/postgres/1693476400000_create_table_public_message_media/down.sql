@@ -0,0 +1,8 @@
+comment on column "public"."media"."content_type" is NULL;
+alter table "public"."media" drop column "content_type";
+
+comment on column "public"."message"."media_id" is NULL;
+DROP TRIGGER sync_message_media_id ON public.message;
+DROP FUNCTION public.sync_message_media_id();
+
+DROP TABLE public.message_media;
@@ -0,0 +1,50 @@
+CREATE TABLE public.message_media (
+    id uuid DEFAULT gen_random_uuid() NOT NULL,
+    message_id uuid NOT NULL,
+    media_id uuid NOT NULL,
+    user_id text NOT NULL,
+    "position" integer NOT NULL,
+    created_at timestamp with time zone DEFAULT now() NOT NULL,
+    CONSTRAINT message_media_position_check CHECK ("position" >= 0 AND "position" < 4)
+);
+COMMENT ON TABLE public.message_media IS 'メッセージに添付するメディア（4件まで、positionの順に添付する）';
+ALTER TABLE ONLY public.message_media
+    ADD CONSTRAINT message_media_pkey PRIMARY KEY (id);
+ALTER TABLE ONLY public.message_media
+    ADD CONSTRAINT message_media_message_id_position_key UNIQUE (message_id, "position");
+ALTER TABLE ONLY public.message_media
+    ADD CONSTRAINT message_media_message_id_media_id_key UNIQUE (message_id, media_id);
+ALTER TABLE ONLY public.message_media
+    ADD CONSTRAINT message_media_message_id_fkey FOREIGN KEY (message_id) REFERENCES public.message(id) ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE ONLY public.message_media
+    ADD CONSTRAINT message_media_media_id_fkey FOREIGN KEY (media_id) REFERENCES public.media(id) ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE ONLY public.message_media
+    ADD CONSTRAINT message_media_user_id_fkey FOREIGN KEY (user_id) REFERENCES public."user"(id) ON UPDATE CASCADE ON DELETE CASCADE;
+
+INSERT INTO public.message_media (message_id, media_id, user_id, "position")
+    SELECT id, media_id, user_id, 0 FROM public.message WHERE media_id IS NOT NULL;
+
+-- message.media_idを使うクライアントのために、1枚目のメディアとして同期する
+CREATE FUNCTION public.sync_message_media_id() RETURNS trigger
+    LANGUAGE plpgsql
+    AS $$
+BEGIN
+    IF TG_OP = 'UPDATE' AND NEW.media_id IS NOT DISTINCT FROM OLD.media_id THEN
+        RETURN NEW;
+    END IF;
+    DELETE FROM public.message_media WHERE message_id = NEW.id AND "position" = 0;
+    IF NEW.media_id IS NOT NULL THEN
+        DELETE FROM public.message_media WHERE message_id = NEW.id AND media_id = NEW.media_id;
+        INSERT INTO public.message_media (message_id, media_id, user_id, "position")
+            VALUES (NEW.id, NEW.media_id, NEW.user_id, 0);
+    END IF;
+    RETURN NEW;
+END;
+$$;
+CREATE TRIGGER sync_message_media_id AFTER INSERT OR UPDATE OF media_id ON public.message
+    FOR EACH ROW EXECUTE FUNCTION public.sync_message_media_id();
+comment on column "public"."message"."media_id" is E'非推奨。message_mediaの1枚目と同期する';
+
+alter table "public"."media" add column "content_type" text
+ null;
+comment on column "public"."media"."content_type" is E'メディアのMIMEタイプ。GIFアニメや動画の添付ルールの確認に使う';
@@ -0,0 +1,6 @@
+comment on column "public"."user"."require_alt_text" is NULL;
+alter table "public"."user" drop column "require_alt_text";
+
+comment on column "public"."media"."alt_text" is NULL;
+alter table "public"."media" drop constraint "media_alt_text_check";
+alter table "public"."media" drop column "alt_text";
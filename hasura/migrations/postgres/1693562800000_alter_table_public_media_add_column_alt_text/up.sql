@@ -0,0 +1,9 @@
+alter table "public"."media" add column "alt_text" text
+ null;
+alter table "public"."media" add constraint "media_alt_text_check"
+ check (char_length("alt_text") <= 1000);
+comment on column "public"."media"."alt_text" is E'画像の代替テキスト（1000文字まで）';
+
+alter table "public"."user" add column "require_alt_text" boolean
+ not null default 'false';
+comment on column "public"."user"."require_alt_text" is E'代替テキストのない画像を添付したメッセージを投稿しないかどうか';
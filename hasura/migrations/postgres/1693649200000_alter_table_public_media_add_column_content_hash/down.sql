@@ -0,0 +1,7 @@
+drop index if exists "public"."media_user_id_content_hash_idx";
+
+comment on column "public"."media"."perceptual_hash" is NULL;
+alter table "public"."media" drop column "perceptual_hash";
+
+comment on column "public"."media"."content_hash" is NULL;
+alter table "public"."media" drop column "content_hash";
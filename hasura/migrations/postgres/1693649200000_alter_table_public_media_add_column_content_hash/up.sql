@@ -0,0 +1,9 @@
+alter table "public"."media" add column "content_hash" text
+ null;
+comment on column "public"."media"."content_hash" is E'保存したファイルのSHA-256（重複したアップロードの検出に使う）';
+
+alter table "public"."media" add column "perceptual_hash" text
+ null;
+comment on column "public"."media"."perceptual_hash" is E'画像のdHash（似ている画像の検出に使う）';
+
+create index "media_user_id_content_hash_idx" on "public"."media" ("user_id", "content_hash");
@@ -0,0 +1,20 @@
+CREATE TABLE public.watermark (
+    user_id text NOT NULL,
+    media_id uuid NOT NULL,
+    "position" text DEFAULT 'bottom_right'::text NOT NULL,
+    opacity real DEFAULT 0.5 NOT NULL,
+    scale real DEFAULT 0.2 NOT NULL,
+    enabled boolean DEFAULT true NOT NULL,
+    CONSTRAINT watermark_position_check CHECK ("position" IN ('top_left', 'top_right', 'bottom_left', 'bottom_right', 'center')),
+    CONSTRAINT watermark_opacity_check CHECK (opacity >= 0 AND opacity <= 1),
+    CONSTRAINT watermark_scale_check CHECK (scale > 0 AND scale <= 1)
+);
+COMMENT ON TABLE public.watermark IS '投稿する画像に透かしとして重ねるロゴの設定（ユーザーごとに1件）';
+COMMENT ON COLUMN public.watermark.media_id IS 'ロゴとして重ねるメディア';
+COMMENT ON COLUMN public.watermark.scale IS '画像の幅に対するロゴの幅の割合';
+ALTER TABLE ONLY public.watermark
+    ADD CONSTRAINT watermark_pkey PRIMARY KEY (user_id);
+ALTER TABLE ONLY public.watermark
+    ADD CONSTRAINT watermark_user_id_fkey FOREIGN KEY (user_id) REFERENCES public."user"(id) ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE ONLY public.watermark
+    ADD CONSTRAINT watermark_media_id_fkey FOREIGN KEY (media_id) REFERENCES public.media(id) ON UPDATE CASCADE ON DELETE CASCADE;
@@ -0,0 +1,13 @@
+alter table "public"."task_rss" drop constraint "task_rss_account_id_fkey";
+comment on column "public"."task_rss"."account_id" is NULL;
+alter table "public"."task_rss" drop column "account_id";
+
+alter table "public"."task_fixed_time" drop constraint "task_fixed_time_account_id_fkey";
+comment on column "public"."task_fixed_time"."account_id" is NULL;
+alter table "public"."task_fixed_time" drop column "account_id";
+
+drop index "public"."session_user_id_idx";
+
+alter table "public"."session" drop constraint "session_user_id_fkey";
+comment on column "public"."session"."user_id" is NULL;
+alter table "public"."session" drop column "user_id";
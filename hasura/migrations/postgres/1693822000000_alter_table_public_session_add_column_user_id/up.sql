@@ -0,0 +1,19 @@
+alter table "public"."session" add column "user_id" text
+ null;
+comment on column "public"."session"."user_id" is E'このアカウントを連携したユーザー（ログインに使ったアカウントはNULL）';
+alter table "public"."session" add constraint "session_user_id_fkey"
+ foreign key ("user_id") references "public"."user" ("id") on update cascade on delete cascade;
+
+create index "session_user_id_idx" on "public"."session" ("user_id");
+
+alter table "public"."task_fixed_time" add column "account_id" text
+ null;
+comment on column "public"."task_fixed_time"."account_id" is E'投稿に使うアカウント（NULLの場合はログインに使ったアカウント）';
+alter table "public"."task_fixed_time" add constraint "task_fixed_time_account_id_fkey"
+ foreign key ("account_id") references "public"."session" ("id") on update cascade on delete restrict;
+
+alter table "public"."task_rss" add column "account_id" text
+ null;
+comment on column "public"."task_rss"."account_id" is E'投稿に使うアカウント（NULLの場合はログインに使ったアカウント）';
+alter table "public"."task_rss" add constraint "task_rss_account_id_fkey"
+ foreign key ("account_id") references "public"."session" ("id") on update cascade on delete restrict;
@@ -0,0 +1,16 @@
+comment on column "public"."task_rss"."visibility" is NULL;
+alter table "public"."task_rss" drop constraint "task_rss_visibility_check";
+alter table "public"."task_rss" drop column "visibility";
+
+comment on column "public"."task_fixed_time"."visibility" is NULL;
+alter table "public"."task_fixed_time" drop constraint "task_fixed_time_visibility_check";
+alter table "public"."task_fixed_time" drop column "visibility";
+
+comment on column "public"."session"."instance" is NULL;
+alter table "public"."session" drop column "instance";
+
+comment on column "public"."session"."provider" is NULL;
+alter table "public"."session" drop constraint "session_provider_check";
+alter table "public"."session" drop column "provider";
+
+DROP TABLE public.mastodon_app;
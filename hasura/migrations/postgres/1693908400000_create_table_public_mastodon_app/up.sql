@@ -0,0 +1,32 @@
+CREATE TABLE public.mastodon_app (
+    instance text NOT NULL,
+    client_id text NOT NULL,
+    client_secret text NOT NULL,
+    created_at timestamp with time zone DEFAULT now() NOT NULL
+);
+COMMENT ON TABLE public.mastodon_app IS 'Mastodonのインスタンスごとに登録したOAuthアプリ';
+COMMENT ON COLUMN public.mastodon_app.instance IS 'インスタンスのURL（https://example.com の形式）';
+ALTER TABLE ONLY public.mastodon_app
+    ADD CONSTRAINT mastodon_app_pkey PRIMARY KEY (instance);
+
+alter table "public"."session" add column "provider" text
+ not null default 'twitter';
+alter table "public"."session" add constraint "session_provider_check"
+ check ("provider" IN ('twitter', 'mastodon'));
+comment on column "public"."session"."provider" is E'アカウントの投稿先のサービス';
+
+alter table "public"."session" add column "instance" text
+ null;
+comment on column "public"."session"."instance" is E'MastodonなどのインスタンスのURL（Twitterの場合はNULL）';
+
+alter table "public"."task_fixed_time" add column "visibility" text
+ null;
+alter table "public"."task_fixed_time" add constraint "task_fixed_time_visibility_check"
+ check ("visibility" IN ('public', 'unlisted', 'private', 'direct'));
+comment on column "public"."task_fixed_time"."visibility" is E'投稿の公開範囲（Twitterでは無視する。NULLの場合はpublic）';
+
+alter table "public"."task_rss" add column "visibility" text
+ null;
+alter table "public"."task_rss" add constraint "task_rss_visibility_check"
+ check ("visibility" IN ('public', 'unlisted', 'private', 'direct'));
+comment on column "public"."task_rss"."visibility" is E'投稿の公開範囲（Twitterでは無視する。NULLの場合はpublic）';
@@ -0,0 +1,4 @@
+comment on column "public"."session"."instance" is E'MastodonなどのインスタンスのURL（Twitterの場合はNULL）';
+alter table "public"."session" drop constraint "session_provider_check";
+alter table "public"."session" add constraint "session_provider_check"
+ check ("provider" IN ('twitter', 'mastodon'));
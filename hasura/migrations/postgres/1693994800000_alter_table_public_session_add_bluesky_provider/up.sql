@@ -0,0 +1,4 @@
+alter table "public"."session" drop constraint "session_provider_check";
+alter table "public"."session" add constraint "session_provider_check"
+ check ("provider" IN ('twitter', 'mastodon', 'bluesky'));
+comment on column "public"."session"."instance" is E'MastodonなどのインスタンスやBlueskyのPDSのURL（Twitterの場合はNULL）';
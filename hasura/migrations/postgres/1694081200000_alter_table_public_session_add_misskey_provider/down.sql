@@ -0,0 +1,3 @@
+alter table "public"."session" drop constraint "session_provider_check";
+alter table "public"."session" add constraint "session_provider_check"
+ check ("provider" IN ('twitter', 'mastodon', 'bluesky'));
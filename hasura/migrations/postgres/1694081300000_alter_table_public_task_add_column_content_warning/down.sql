@@ -0,0 +1,5 @@
+comment on column "public"."task_rss"."content_warning" is NULL;
+alter table "public"."task_rss" drop column "content_warning";
+
+comment on column "public"."task_fixed_time"."content_warning" is NULL;
+alter table "public"."task_fixed_time" drop column "content_warning";
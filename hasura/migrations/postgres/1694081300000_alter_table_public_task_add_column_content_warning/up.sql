@@ -0,0 +1,7 @@
+alter table "public"."task_fixed_time" add column "content_warning" text
+ null;
+comment on column "public"."task_fixed_time"."content_warning" is E'投稿に付ける注意書き（CW）。対応していないサービスでは無視する';
+
+alter table "public"."task_rss" add column "content_warning" text
+ null;
+comment on column "public"."task_rss"."content_warning" is E'投稿に付ける注意書き（CW）。対応していないサービスでは無視する';
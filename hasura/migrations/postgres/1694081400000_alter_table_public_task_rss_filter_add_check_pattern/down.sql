@@ -0,0 +1 @@
+alter table "public"."task_rss_filter" drop constraint "task_rss_filter_pattern_check";
@@ -0,0 +1 @@
+alter table "public"."task_rss_filter" add constraint "task_rss_filter_pattern_check" check (btrim(pattern) <> '') not valid;